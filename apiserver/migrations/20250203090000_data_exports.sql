@@ -0,0 +1,9 @@
+create table data_exports (
+	id bigint primary key,
+	user_id bigint references users(id) not null,
+	status varchar(20) not null default 'pending',
+	download_token varchar(255) unique,
+	file_path varchar(1024),
+	created_at bigint not null,
+	expires_at bigint
+);
@@ -0,0 +1,23 @@
+create table public_profiles (
+	user_id bigint primary key references users(id),
+	display_name varchar(100),
+	about_me varchar(1000),
+	location varchar(100),
+	pronouns varchar(50),
+	job_title varchar(100),
+	company varchar(100),
+	display_name_public boolean not null default true,
+	about_me_public boolean not null default true,
+	location_public boolean not null default true,
+	pronouns_public boolean not null default true,
+	job_title_public boolean not null default true,
+	company_public boolean not null default true,
+	links_public boolean not null default true
+);
+create table profile_links (
+	id bigint primary key,
+	user_id bigint references users(id) not null,
+	label varchar(50) not null,
+	url varchar(500) not null,
+	position integer not null
+);
@@ -0,0 +1,9 @@
+alter table users add column email_sha256 varchar(64) unique;
+update users set email_sha256 = encode(sha256(convert_to(lower(trim(email)), 'UTF8')), 'hex');
+alter table users alter column email_sha256 set not null;
+create table openid_urls (
+	id bigint primary key,
+	user_id bigint references users(id) not null,
+	url varchar(500) not null,
+	url_hash varchar(64) not null unique
+);
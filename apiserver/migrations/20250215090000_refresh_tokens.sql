@@ -0,0 +1,11 @@
+create table refresh_tokens (
+	id bigint primary key,
+	user_id bigint references users(id) not null,
+	family_id bigint not null,
+	token_hash varchar(64) not null unique,
+	created_at bigint not null,
+	expires_at bigint not null,
+	used_at bigint,
+	revoked boolean not null default false
+);
+create index refresh_tokens_family_id_idx on refresh_tokens(family_id);
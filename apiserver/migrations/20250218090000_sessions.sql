@@ -0,0 +1,15 @@
+create table sessions (
+	id bigint primary key,
+	user_id bigint references users(id) not null,
+	user_agent varchar(512),
+	ip varchar(64),
+	created_at bigint not null,
+	last_seen_at bigint not null,
+	revoked_at bigint
+);
+create index sessions_user_id_idx on sessions(user_id);
+-- refresh token families become sessions, tokens issued before this point are dropped
+delete from refresh_tokens;
+alter table refresh_tokens rename column family_id to session_id;
+alter index refresh_tokens_family_id_idx rename to refresh_tokens_session_id_idx;
+alter table refresh_tokens add constraint refresh_tokens_session_id_fkey foreign key (session_id) references sessions(id);
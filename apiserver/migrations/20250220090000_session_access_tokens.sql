@@ -0,0 +1,2 @@
+alter table sessions add column access_token_hash varchar(64);
+alter table sessions add column access_expires_at bigint;
@@ -0,0 +1,12 @@
+create table api_tokens (
+	id bigint primary key,
+	user_id bigint references users(id) not null,
+	name varchar(100) not null,
+	token_hash varchar(64) not null unique,
+	scopes text[] not null,
+	created_at bigint not null,
+	expires_at bigint,
+	last_used_at bigint,
+	revoked_at bigint
+);
+create index api_tokens_user_id_idx on api_tokens(user_id);
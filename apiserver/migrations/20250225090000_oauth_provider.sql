@@ -0,0 +1,32 @@
+create table oauth_clients (
+	id bigint primary key,
+	user_id bigint references users(id) not null,
+	name varchar(100) not null,
+	client_id varchar(64) not null unique,
+	client_secret_hash varchar(64),
+	redirect_uris text[] not null,
+	created_at bigint not null
+);
+create index oauth_clients_user_id_idx on oauth_clients(user_id);
+create table oauth_authorization_codes (
+	id bigint primary key,
+	code_hash varchar(64) not null unique,
+	client_id bigint references oauth_clients(id) on delete cascade not null,
+	user_id bigint references users(id) not null,
+	redirect_uri text not null,
+	scopes text[] not null,
+	code_challenge varchar(128) not null,
+	nonce varchar(255),
+	created_at bigint not null,
+	expires_at bigint not null,
+	used_at bigint
+);
+create table oauth_consents (
+	user_id bigint references users(id) not null,
+	client_id bigint references oauth_clients(id) on delete cascade not null,
+	scopes text[] not null,
+	created_at bigint not null,
+	primary key (user_id, client_id)
+);
+-- access tokens handed to oauth clients live next to personal access tokens
+alter table api_tokens add column oauth_client_id bigint references oauth_clients(id) on delete cascade;
@@ -0,0 +1,21 @@
+-- accounts created through single sign on have no password of their own
+alter table users alter column password drop not null;
+create table user_identities (
+	id bigint primary key,
+	user_id bigint references users(id) not null,
+	issuer varchar(255) not null,
+	subject varchar(255) not null,
+	email varchar(255) not null,
+	created_at bigint not null,
+	unique (issuer, subject)
+);
+create index user_identities_user_id_idx on user_identities(user_id);
+create table oidc_logins (
+	id bigint primary key,
+	state_hash varchar(64) not null unique,
+	provider varchar(50) not null,
+	nonce varchar(64) not null,
+	code_verifier varchar(128) not null,
+	created_at bigint not null,
+	expires_at bigint not null
+);
@@ -0,0 +1,24 @@
+create table totp_credentials (
+	user_id bigint primary key references users(id),
+	secret varchar(64) not null,
+	created_at bigint not null,
+	confirmed_at bigint,
+	last_used_step bigint
+);
+create table recovery_codes (
+	id bigint primary key,
+	user_id bigint references users(id) not null,
+	code_hash varchar(64) not null,
+	created_at bigint not null,
+	used_at bigint
+);
+create index recovery_codes_user_id_idx on recovery_codes(user_id);
+-- a password sign in waiting for its second factor
+create table login_challenges (
+	id bigint primary key,
+	token_hash varchar(64) not null unique,
+	user_id bigint references users(id) not null,
+	created_at bigint not null,
+	expires_at bigint not null,
+	attempts integer not null default 0
+);
@@ -0,0 +1,10 @@
+create table passkeys (
+	id bigint primary key,
+	user_id bigint references users(id) not null,
+	name varchar(100) not null,
+	credential_id text not null unique,
+	passkey text not null,
+	created_at bigint not null,
+	last_used_at bigint
+);
+create index passkeys_user_id_idx on passkeys(user_id);
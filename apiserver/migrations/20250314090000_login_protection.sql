@@ -0,0 +1,28 @@
+create table failed_logins (
+	id bigint primary key,
+	user_id bigint references users(id),
+	ip varchar(64) not null,
+	created_at bigint not null
+);
+create index failed_logins_user_id_idx on failed_logins(user_id, created_at);
+create index failed_logins_ip_idx on failed_logins(ip, created_at);
+
+alter table users add column locked_until bigint;
+
+create table account_unlocks (
+	id bigint primary key,
+	user_id bigint references users(id) not null,
+	token_hash varchar(64) not null unique,
+	created_at bigint not null,
+	expires_at bigint not null,
+	used_at bigint
+);
+
+create table login_sources (
+	user_id bigint references users(id) not null,
+	user_agent_hash varchar(64) not null,
+	ip varchar(64) not null,
+	first_seen_at bigint not null,
+	last_seen_at bigint not null,
+	primary key (user_id, user_agent_hash, ip)
+);
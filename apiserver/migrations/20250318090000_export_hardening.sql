@@ -0,0 +1,5 @@
+-- download links are bearer tokens, keep only their digest like every other token
+update data_exports set download_token = encode(sha256(convert_to(download_token, 'UTF8')), 'hex') where download_token is not null;
+
+-- one export at a time per user
+create unique index data_exports_one_pending on data_exports(user_id) where status = 'pending';
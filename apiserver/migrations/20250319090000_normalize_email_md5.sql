@@ -0,0 +1,2 @@
+-- md5 lookups hash the trimmed, lowercased address like the sha256 column
+update users set email_hash = md5(lower(trim(email))) where email_hash <> md5(lower(trim(email)));
@@ -0,0 +1,16 @@
+-- rfc 6749 section 4.1.3 only requires the redirect_uri at the token endpoint when the authorize request sent it
+alter table oauth_authorization_codes alter column redirect_uri drop not null;
+create table oauth_refresh_tokens (
+	id bigint primary key,
+	token_hash varchar(64) not null unique,
+	family_id bigint not null,
+	client_id bigint references oauth_clients(id) on delete cascade not null,
+	user_id bigint references users(id) not null,
+	scopes text[] not null,
+	created_at bigint not null,
+	expires_at bigint not null,
+	used_at bigint,
+	revoked boolean not null default false
+);
+create index oauth_refresh_tokens_family_id_idx on oauth_refresh_tokens(family_id);
+create index oauth_refresh_tokens_user_id_client_id_idx on oauth_refresh_tokens(user_id, client_id);
@@ -0,0 +1,13 @@
+-- an external login matching an account that has a password waits for the owner to confirm by email
+create table identity_link_requests (
+	id bigint primary key,
+	user_id bigint references users(id) not null,
+	token_hash varchar(64) not null unique,
+	issuer varchar(255) not null,
+	subject varchar(255) not null,
+	email varchar(255) not null,
+	created_at bigint not null,
+	expires_at bigint not null,
+	used_at bigint
+);
+create index oidc_logins_expires_at_idx on oidc_logins(expires_at);
@@ -0,0 +1 @@
+create index login_challenges_user_id_idx on login_challenges(user_id);
@@ -0,0 +1,4 @@
+-- when the person behind a session last proved who they are, a sign in or a re-authentication
+alter table sessions add column authenticated_at bigint;
+update sessions set authenticated_at = created_at;
+alter table sessions alter column authenticated_at set not null;
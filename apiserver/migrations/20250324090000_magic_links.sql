@@ -0,0 +1,11 @@
+-- emailed single use sign in links, only the digest of the token is kept
+create table magic_links (
+	id bigint primary key,
+	user_id bigint references users(id) not null,
+	token_hash varchar(64) not null unique,
+	created_at bigint not null,
+	expires_at bigint not null,
+	used_at bigint
+);
+create index magic_links_user_id_idx on magic_links(user_id);
+create index magic_links_expires_at_idx on magic_links(expires_at);
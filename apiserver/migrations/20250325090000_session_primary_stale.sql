@@ -0,0 +1,4 @@
+-- sessions whose redis entry could not be rewritten or deleted, kept here so a
+-- restart or another instance still knows not to trust redis for them
+alter table sessions add column primary_stale_until bigint;
+create index sessions_primary_stale_idx on sessions(primary_stale_until) where primary_stale_until is not null;
@@ -0,0 +1,9 @@
+-- failures for addresses without an account are counted under the address hash,
+-- so backoff and lockout look the same whether or not the account exists
+alter table failed_logins add column email_key varchar(64);
+create index failed_logins_email_key_idx on failed_logins(email_key, created_at) where email_key is not null;
+
+create table decoy_locks (
+	email_key varchar(64) primary key,
+	locked_until bigint not null
+);
@@ -0,0 +1,71 @@
+use std::{collections::BTreeSet, path::Path};
+
+use cloudinary::upload::{OptionalParameters, Source, UploadResult};
+
+use crate::AppState;
+
+pub struct AddedImage {
+    pub profile_id: i64,
+    pub secure_url: String,
+}
+
+pub async fn add_profile_image(
+    user_id: i64,
+    file_path: &Path,
+    app_state: &AppState,
+) -> Result<AddedImage, String> {
+    let profile_id_res = app_state.snow_flake.lock().unwrap().generate_id();
+
+    if profile_id_res.is_err() {
+        return Err("Issue generating profile id".to_string());
+    }
+    let profile_id = profile_id_res.unwrap() as i64;
+
+    let transaction_res = app_state.database_connection_pool.begin().await;
+    if transaction_res.is_err() {
+        return Err("Issue starting the transaction".to_string());
+    }
+
+    let mut transaction = transaction_res.unwrap();
+
+    let query_result = sqlx::query!(
+        "INSERT INTO profile (id, user_id) VALUES ($1, $2)",
+        profile_id,
+        user_id
+    )
+    .execute(&mut *transaction) // Dereference the transaction here
+    .await;
+
+    if query_result.is_err() {
+        return Err("Failed to execute query".to_string());
+    }
+
+    let options = BTreeSet::from([OptionalParameters::PublicId(format!(
+        "gravatar/{}/{}",
+        user_id, profile_id
+    ))]);
+
+    let cld_result = app_state
+        .cloudinary
+        .image(Source::Path(file_path.to_str().unwrap().into()), &options)
+        .await;
+    if cld_result.is_err() {
+        return Err("Issue writing to the cloud".to_string());
+    }
+    let commit_result = transaction.commit().await;
+    if commit_result.is_err() {
+        return Err("Issue writing to the cloud".to_string());
+    }
+
+    let mut secure_url = "".to_owned();
+    let cld_res = cld_result.unwrap();
+
+    if let UploadResult::Response(val) = cld_res {
+        secure_url = val.secure_url;
+    }
+
+    Ok(AddedImage {
+        profile_id,
+        secure_url,
+    })
+}
@@ -0,0 +1,35 @@
+use crate::{
+    models::public_profile_model::{ProfileLinkFromDB, PublicProfileFromDB},
+    AppState,
+};
+
+pub async fn fetch_profile_details(
+    user_id: i64,
+    app_state: &AppState,
+) -> Result<(PublicProfileFromDB, Vec<ProfileLinkFromDB>), String> {
+    let profile_res =
+        sqlx::query_as::<_, PublicProfileFromDB>("select * from public_profiles where user_id=$1")
+            .bind(user_id)
+            .fetch_optional(&app_state.database_connection_pool)
+            .await;
+
+    if profile_res.is_err() {
+        return Err("Issue talking to the database".to_string());
+    }
+
+    let links_res = sqlx::query_as::<_, ProfileLinkFromDB>(
+        "select * from profile_links where user_id=$1 order by position",
+    )
+    .bind(user_id)
+    .fetch_all(&app_state.database_connection_pool)
+    .await;
+
+    if links_res.is_err() {
+        return Err("Issue talking to the database".to_string());
+    }
+
+    let profile = profile_res
+        .unwrap()
+        .unwrap_or_else(|| PublicProfileFromDB::empty(user_id));
+    Ok((profile, links_res.unwrap()))
+}
@@ -0,0 +1,17 @@
+use crate::{models::profile_model::ProfileFromDB, AppState};
+
+pub async fn fetch_profile_images(
+    user_id: i64,
+    app_state: &AppState,
+) -> Result<Vec<ProfileFromDB>, String> {
+    let profile_pics_res =
+        sqlx::query_as::<_, ProfileFromDB>("select * from profile where user_id=$1 order by id")
+            .bind(user_id)
+            .fetch_all(&app_state.database_connection_pool)
+            .await;
+
+    match profile_pics_res {
+        Err(_) => Err("Issue talking to the database".to_string()),
+        Ok(profile_pics) => Ok(profile_pics),
+    }
+}
@@ -0,0 +1,44 @@
+use sqlx::prelude::FromRow;
+
+use crate::AppState;
+
+#[derive(FromRow)]
+pub struct ApiTokenOwner {
+    pub id: i64,
+    pub user_id: i64,
+    pub email: String,
+    pub scopes: Vec<String>,
+}
+
+// looks up a live personal access token and records that it was used
+pub async fn find_api_token(
+    token: &str,
+    app_state: &AppState,
+) -> Result<Option<ApiTokenOwner>, String> {
+    let now = crate::helpers::current_time::current_time_secs()?;
+
+    let owner_res = sqlx::query_as::<_, ApiTokenOwner>(
+        "select api_tokens.id, api_tokens.user_id, users.email, api_tokens.scopes
+        from api_tokens join users on users.id = api_tokens.user_id
+        where api_tokens.token_hash=$1 and api_tokens.revoked_at is null
+        and (api_tokens.expires_at is null or api_tokens.expires_at>$2)",
+    )
+    .bind(crate::helpers::hash_token::hash_token(token))
+    .bind(now)
+    .fetch_optional(&app_state.database_connection_pool)
+    .await;
+
+    if owner_res.is_err() {
+        return Err("Issue talking to the database".to_string());
+    }
+    let owner = owner_res.unwrap();
+
+    if let Some(owner) = &owner {
+        let _ = sqlx::query("update api_tokens set last_used_at=$1 where id=$2")
+            .bind(now)
+            .bind(owner.id)
+            .execute(&app_state.database_connection_pool)
+            .await;
+    }
+    Ok(owner)
+}
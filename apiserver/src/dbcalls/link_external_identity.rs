@@ -0,0 +1,221 @@
+use crate::{
+    helpers::{hash_token::hash_token, mailer::send_in_background, oidc_client::ExternalClaims},
+    models::user_model::UserFromDB,
+    AppState,
+};
+
+const LINK_REQUEST_LIFETIME_SECS: i64 = 3600;
+
+pub enum ExternalLogin {
+    SignedIn(UserFromDB),
+    // the matching account has a password, its owner was emailed a link to confirm
+    ConfirmationSent,
+}
+
+#[derive(sqlx::FromRow)]
+struct ExistingAccount {
+    #[sqlx(flatten)]
+    user: UserFromDB,
+    has_password: bool,
+}
+
+#[derive(sqlx::FromRow)]
+struct LinkRequestFromDB {
+    user_id: i64,
+    issuer: String,
+    subject: String,
+    email: String,
+}
+
+// someone holding the email at the provider is not necessarily the person who
+// set the password here, so the owner has to approve the link from their inbox
+async fn request_link_confirmation(
+    claims: &ExternalClaims,
+    account: &ExistingAccount,
+    email: &str,
+    provider_name: &str,
+    app_state: &AppState,
+) -> Result<(), String> {
+    let id_res = app_state.snow_flake.lock().unwrap().generate_id();
+    if id_res.is_err() {
+        return Err("Issue generating the id".to_string());
+    }
+    let now = crate::helpers::current_time::current_time_secs()?;
+    let link_token = crate::helpers::generate_random_token::generate_random_token();
+
+    let insert_res = sqlx::query(
+        "insert into identity_link_requests(id, user_id, token_hash, issuer, subject, email, created_at, expires_at)
+        values($1, $2, $3, $4, $5, $6, $7, $8)",
+    )
+    .bind(id_res.unwrap() as i64)
+    .bind(account.user.id)
+    .bind(hash_token(&link_token))
+    .bind(&claims.iss)
+    .bind(&claims.sub)
+    .bind(email)
+    .bind(now)
+    .bind(now + LINK_REQUEST_LIFETIME_SECS)
+    .execute(&app_state.database_connection_pool)
+    .await;
+    if insert_res.is_err() {
+        return Err("Issue talking to the database".to_string());
+    }
+
+    send_in_background(
+        app_state.mailer.clone(),
+        account.user.email.clone(),
+        format!("Connect {} to your Gravatar account", provider_name),
+        format!(
+            "Someone tried to sign in with {} using this email address.\n\n\
+            If that was you, connect it to your account with this link, it works for the next hour:\n\
+            {}/api/v1/user/oidc/link/{}\n\n\
+            If it was not, ignore this email and nothing will change.",
+            provider_name, app_state.public_url, link_token
+        ),
+    );
+    Ok(())
+}
+
+// opened from the link in the confirmation email
+pub async fn confirm_identity_link(link_token: &str, app_state: &AppState) -> Result<bool, String> {
+    let now = crate::helpers::current_time::current_time_secs()?;
+    let request_res = sqlx::query_as::<_, LinkRequestFromDB>(
+        "update identity_link_requests set used_at=$1
+        where token_hash=$2 and used_at is null and expires_at>$1
+        returning user_id, issuer, subject, email",
+    )
+    .bind(now)
+    .bind(hash_token(link_token))
+    .fetch_optional(&app_state.database_connection_pool)
+    .await;
+    let request = match request_res {
+        Err(_) => return Err("Issue talking to the database".to_string()),
+        Ok(None) => return Ok(false),
+        Ok(Some(request)) => request,
+    };
+
+    let identity_id_res = app_state.snow_flake.lock().unwrap().generate_id();
+    if identity_id_res.is_err() {
+        return Err("Issue generating the id".to_string());
+    }
+    // a second confirmed request for the same identity changes nothing
+    let link_res = sqlx::query(
+        "insert into user_identities(id, user_id, issuer, subject, email, created_at)
+        values($1, $2, $3, $4, $5, $6) on conflict (issuer, subject) do nothing",
+    )
+    .bind(identity_id_res.unwrap() as i64)
+    .bind(request.user_id)
+    .bind(&request.issuer)
+    .bind(&request.subject)
+    .bind(&request.email)
+    .bind(now)
+    .execute(&app_state.database_connection_pool)
+    .await;
+    if link_res.is_err() {
+        return Err("Issue talking to the database".to_string());
+    }
+    Ok(true)
+}
+
+// an external login lands on the account it was linked to before, otherwise on
+// a password-less account holding the same verified email, otherwise on a new
+// account, accounts with a password are only linked once their owner confirms
+pub async fn link_external_identity(
+    claims: &ExternalClaims,
+    provider_name: &str,
+    app_state: &AppState,
+) -> Result<ExternalLogin, String> {
+    let linked_res = sqlx::query_as::<_, UserFromDB>(
+        "select users.id, users.email, users.email_hash, users.email_sha256, users.active_photo_id
+        from user_identities join users on users.id = user_identities.user_id
+        where user_identities.issuer=$1 and user_identities.subject=$2",
+    )
+    .bind(&claims.iss)
+    .bind(&claims.sub)
+    .fetch_optional(&app_state.database_connection_pool)
+    .await;
+    if linked_res.is_err() {
+        return Err("Issue talking to the database".to_string());
+    }
+    if let Some(user) = linked_res.unwrap() {
+        return Ok(ExternalLogin::SignedIn(user));
+    }
+
+    // unverified addresses could belong to anyone, so they never link or create accounts
+    let email = match claims.verified_email() {
+        Some(email) => email.trim().to_lowercase(),
+        None => return Err("The identity provider did not verify the email address".to_string()),
+    };
+
+    let existing_res = sqlx::query_as::<_, ExistingAccount>(
+        "select id, email, email_hash, email_sha256, active_photo_id, password is not null as has_password
+        from users where lower(email)=$1",
+    )
+    .bind(&email)
+    .fetch_optional(&app_state.database_connection_pool)
+    .await;
+    if existing_res.is_err() {
+        return Err("Issue talking to the database".to_string());
+    }
+    let existing = existing_res.unwrap();
+    if let Some(account) = existing.as_ref().filter(|account| account.has_password) {
+        request_link_confirmation(claims, account, &email, provider_name, app_state).await?;
+        return Ok(ExternalLogin::ConfirmationSent);
+    }
+
+    let mut transaction = match app_state.database_connection_pool.begin().await {
+        Ok(transaction) => transaction,
+        Err(_) => return Err("Issue talking to the database".to_string()),
+    };
+
+    let user = match existing {
+        Some(account) => account.user,
+        None => {
+            let user_id_res = app_state.snow_flake.lock().unwrap().generate_id();
+            if user_id_res.is_err() {
+                return Err("Issue generating the id".to_string());
+            }
+            let created_res = sqlx::query_as::<_, UserFromDB>(
+                "insert into users(id, email, password, email_hash, email_sha256)
+                values($1, $2, null, $3, $4)
+                returning id, email, email_hash, email_sha256, active_photo_id",
+            )
+            .bind(user_id_res.unwrap() as i64)
+            .bind(&email)
+            .bind(crate::helpers::libravatar_hash::email_md5(&email))
+            .bind(crate::helpers::libravatar_hash::email_sha256(&email))
+            .fetch_one(&mut *transaction)
+            .await;
+            match created_res {
+                Ok(user) => user,
+                Err(_) => return Err("Issue creating the account".to_string()),
+            }
+        }
+    };
+
+    let identity_id_res = app_state.snow_flake.lock().unwrap().generate_id();
+    if identity_id_res.is_err() {
+        return Err("Issue generating the id".to_string());
+    }
+    let now = crate::helpers::current_time::current_time_secs()?;
+    let link_res = sqlx::query(
+        "insert into user_identities(id, user_id, issuer, subject, email, created_at)
+        values($1, $2, $3, $4, $5, $6)",
+    )
+    .bind(identity_id_res.unwrap() as i64)
+    .bind(user.id)
+    .bind(&claims.iss)
+    .bind(&claims.sub)
+    .bind(&email)
+    .bind(now)
+    .execute(&mut *transaction)
+    .await;
+    if link_res.is_err() {
+        return Err("Issue talking to the database".to_string());
+    }
+
+    if transaction.commit().await.is_err() {
+        return Err("Issue talking to the database".to_string());
+    }
+    Ok(ExternalLogin::SignedIn(user))
+}
@@ -0,0 +1,339 @@
+use log::warn;
+use sqlx::prelude::FromRow;
+
+use crate::{
+    helpers::{hash_token::hash_token, mailer::send_in_background},
+    models::user_model::UserFromDBWithPassword,
+    AppState,
+};
+
+// failures older than this are forgotten
+const FAILURE_WINDOW_SECS: i64 = 900;
+// the first few mistakes are free, then each failure doubles the wait
+const BACKOFF_AFTER_FAILURES: i64 = 3;
+const MAX_BACKOFF_SECS: i64 = 300;
+const LOCKOUT_AFTER_FAILURES: i64 = 10;
+const LOCKOUT_SECS: i64 = 3600;
+// spraying many accounts from one address
+const IP_FAILURE_LIMIT: i64 = 50;
+
+// who a sign in attempt is for, an address nobody signed up with is tracked under
+// its hash and gets the same backoff and lockout, so the answers give nothing away
+pub enum LoginSubject<'a> {
+    Account {
+        user_id: i64,
+        email: &'a str,
+        locked_until: Option<i64>,
+    },
+    Unknown(&'a str),
+}
+
+impl<'a> LoginSubject<'a> {
+    pub fn account(user: &'a UserFromDBWithPassword) -> Self {
+        LoginSubject::Account {
+            user_id: user.id,
+            email: &user.email,
+            locked_until: user.locked_until,
+        }
+    }
+}
+
+pub enum LoginGate {
+    Allowed,
+    RetryAfter(i64),
+    Locked,
+}
+
+#[derive(FromRow)]
+struct FailureStats {
+    failures: i64,
+    first_at: Option<i64>,
+    last_at: Option<i64>,
+}
+
+fn backoff_secs(failures: i64) -> i64 {
+    if failures < BACKOFF_AFTER_FAILURES {
+        return 0;
+    }
+    let exponent = (failures - BACKOFF_AFTER_FAILURES + 1).min(16) as u32;
+    2_i64.pow(exponent).min(MAX_BACKOFF_SECS)
+}
+
+// counts recent failures for an account, an unknown address or an ip
+async fn failure_stats(
+    user_id: Option<i64>,
+    email_key: Option<&str>,
+    ip: Option<&str>,
+    since: i64,
+    app_state: &AppState,
+) -> Result<FailureStats, String> {
+    let stats_res = sqlx::query_as::<_, FailureStats>(
+        "select count(*) as failures, min(created_at) as first_at, max(created_at) as last_at
+        from failed_logins where ($1::bigint is null or user_id=$1)
+        and ($2::varchar is null or email_key=$2)
+        and ($3::varchar is null or ip=$3) and created_at>$4",
+    )
+    .bind(user_id)
+    .bind(email_key)
+    .bind(ip)
+    .bind(since)
+    .fetch_one(&app_state.database_connection_pool)
+    .await;
+    match stats_res {
+        Err(_) => Err("Issue talking to the database".to_string()),
+        Ok(stats) => Ok(stats),
+    }
+}
+
+// decides whether a password may be tried at all, before it is checked
+pub async fn check_login_gate(
+    subject: &LoginSubject<'_>,
+    ip: &str,
+    app_state: &AppState,
+) -> Result<LoginGate, String> {
+    let now = crate::helpers::current_time::current_time_secs()?;
+    let since = now - FAILURE_WINDOW_SECS;
+
+    let locked = match subject {
+        LoginSubject::Account { locked_until, .. } => locked_until.is_some_and(|until| until > now),
+        LoginSubject::Unknown(email_key) => {
+            let decoy_res = sqlx::query_scalar::<_, bool>(
+                "select exists(select 1 from decoy_locks where email_key=$1 and locked_until>$2)",
+            )
+            .bind(email_key)
+            .bind(now)
+            .fetch_one(&app_state.database_connection_pool)
+            .await;
+            match decoy_res {
+                Err(_) => return Err("Issue talking to the database".to_string()),
+                Ok(locked) => locked,
+            }
+        }
+    };
+    if locked {
+        return Ok(LoginGate::Locked);
+    }
+
+    let ip_stats = failure_stats(None, None, Some(ip), since, app_state).await?;
+    if ip_stats.failures >= IP_FAILURE_LIMIT {
+        let first_at = ip_stats.first_at.unwrap_or(now);
+        return Ok(LoginGate::RetryAfter(
+            (first_at + FAILURE_WINDOW_SECS - now).max(1),
+        ));
+    }
+
+    let subject_stats = match subject {
+        LoginSubject::Account { user_id, .. } => {
+            failure_stats(Some(*user_id), None, None, since, app_state).await?
+        }
+        LoginSubject::Unknown(email_key) => {
+            failure_stats(None, Some(email_key), None, since, app_state).await?
+        }
+    };
+    let retry_at = subject_stats.last_at.unwrap_or(now) + backoff_secs(subject_stats.failures);
+    if retry_at > now {
+        return Ok(LoginGate::RetryAfter(retry_at - now));
+    }
+    Ok(LoginGate::Allowed)
+}
+
+pub async fn record_failed_login(
+    subject: &LoginSubject<'_>,
+    ip: &str,
+    app_state: &AppState,
+) -> Result<(), String> {
+    let id_res = app_state.snow_flake.lock().unwrap().generate_id();
+    if id_res.is_err() {
+        return Err("Issue generating the id".to_string());
+    }
+    let now = crate::helpers::current_time::current_time_secs()?;
+
+    let (user_id, email_key) = match subject {
+        LoginSubject::Account { user_id, .. } => (Some(*user_id), None),
+        LoginSubject::Unknown(email_key) => (None, Some(*email_key)),
+    };
+    let insert_res = sqlx::query(
+        "insert into failed_logins(id, user_id, email_key, ip, created_at) values($1, $2, $3, $4, $5)",
+    )
+    .bind(id_res.unwrap() as i64)
+    .bind(user_id)
+    .bind(email_key)
+    .bind(ip)
+    .bind(now)
+    .execute(&app_state.database_connection_pool)
+    .await;
+    if insert_res.is_err() {
+        return Err("Issue talking to the database".to_string());
+    }
+
+    let user_stats = failure_stats(
+        user_id,
+        email_key,
+        None,
+        now - FAILURE_WINDOW_SECS,
+        app_state,
+    )
+    .await?;
+    if user_stats.failures < LOCKOUT_AFTER_FAILURES {
+        return Ok(());
+    }
+
+    let (user_id, email) = match subject {
+        LoginSubject::Account { user_id, email, .. } => (*user_id, *email),
+        // locked for as long as a real account would be, with nobody to email
+        LoginSubject::Unknown(email_key) => {
+            let decoy_res = sqlx::query(
+                "insert into decoy_locks(email_key, locked_until) values($1, $2)
+                on conflict (email_key) do update set locked_until=$2 where decoy_locks.locked_until<=$3",
+            )
+            .bind(email_key)
+            .bind(now + LOCKOUT_SECS)
+            .bind(now)
+            .execute(&app_state.database_connection_pool)
+            .await;
+            return match decoy_res {
+                Err(_) => Err("Issue talking to the database".to_string()),
+                Ok(_) => Ok(()),
+            };
+        }
+    };
+
+    // only the failure that locks the account sends the email
+    let lock_res = sqlx::query(
+        "update users set locked_until=$1 where id=$2 and (locked_until is null or locked_until<=$3)",
+    )
+    .bind(now + LOCKOUT_SECS)
+    .bind(user_id)
+    .bind(now)
+    .execute(&app_state.database_connection_pool)
+    .await;
+    match lock_res {
+        Err(_) => return Err("Issue talking to the database".to_string()),
+        Ok(result) if result.rows_affected() == 0 => return Ok(()),
+        Ok(_) => {}
+    }
+
+    let unlock_id_res = app_state.snow_flake.lock().unwrap().generate_id();
+    if unlock_id_res.is_err() {
+        return Err("Issue generating the id".to_string());
+    }
+    let unlock_token = crate::helpers::generate_random_token::generate_random_token();
+    let unlock_res = sqlx::query(
+        "insert into account_unlocks(id, user_id, token_hash, created_at, expires_at)
+        values($1, $2, $3, $4, $5)",
+    )
+    .bind(unlock_id_res.unwrap() as i64)
+    .bind(user_id)
+    .bind(hash_token(&unlock_token))
+    .bind(now)
+    .bind(now + LOCKOUT_SECS)
+    .execute(&app_state.database_connection_pool)
+    .await;
+    if unlock_res.is_err() {
+        return Err("Issue talking to the database".to_string());
+    }
+
+    send_in_background(
+        app_state.mailer.clone(),
+        email.to_string(),
+        "Your Gravatar account has been locked".to_string(),
+        format!(
+            "There were {} failed sign in attempts on your account, so it is locked for the next hour.\n\n\
+            If that was you, unlock it now with this link:\n{}/api/v1/user/unlock/{}\n\n\
+            If it was not, someone may be guessing your password, consider changing it.",
+            user_stats.failures, app_state.public_url, unlock_token
+        ),
+    );
+    Ok(())
+}
+
+pub async fn clear_failed_logins(user_id: i64, app_state: &AppState) -> Result<(), String> {
+    let clear_res = sqlx::query("delete from failed_logins where user_id=$1")
+        .bind(user_id)
+        .execute(&app_state.database_connection_pool)
+        .await;
+    match clear_res {
+        Err(_) => Err("Issue talking to the database".to_string()),
+        Ok(_) => Ok(()),
+    }
+}
+
+pub async fn unlock_account(unlock_token: &str, app_state: &AppState) -> Result<bool, String> {
+    let now = crate::helpers::current_time::current_time_secs()?;
+    let unlock_res = sqlx::query_scalar::<_, i64>(
+        "update account_unlocks set used_at=$1
+        where token_hash=$2 and used_at is null and expires_at>$1 returning user_id",
+    )
+    .bind(now)
+    .bind(hash_token(unlock_token))
+    .fetch_optional(&app_state.database_connection_pool)
+    .await;
+    let user_id = match unlock_res {
+        Err(_) => return Err("Issue talking to the database".to_string()),
+        Ok(None) => return Ok(false),
+        Ok(Some(user_id)) => user_id,
+    };
+
+    let reset_res = sqlx::query("update users set locked_until=null where id=$1")
+        .bind(user_id)
+        .execute(&app_state.database_connection_pool)
+        .await;
+    if reset_res.is_err() {
+        return Err("Issue talking to the database".to_string());
+    }
+    clear_failed_logins(user_id, app_state).await?;
+    Ok(true)
+}
+
+// remembers where each account signs in from and emails the owner about
+// any browser or address it has not been seen on before
+pub async fn note_login_source(
+    user_id: i64,
+    email: &str,
+    user_agent: Option<&str>,
+    ip: Option<&str>,
+    app_state: &AppState,
+) {
+    let now = match crate::helpers::current_time::current_time_secs() {
+        Ok(now) => now,
+        Err(_) => return,
+    };
+    let ip = ip.unwrap_or("unknown");
+    let user_agent = user_agent.unwrap_or("unknown");
+
+    let known_res = sqlx::query_scalar::<_, bool>(
+        "select exists(select 1 from login_sources where user_id=$1)",
+    )
+    .bind(user_id)
+    .fetch_one(&app_state.database_connection_pool)
+    .await;
+    let upsert_res = sqlx::query_scalar::<_, bool>(
+        "insert into login_sources(user_id, user_agent_hash, ip, first_seen_at, last_seen_at)
+        values($1, $2, $3, $4, $4)
+        on conflict (user_id, user_agent_hash, ip) do update set last_seen_at=$4
+        returning (xmax = 0)",
+    )
+    .bind(user_id)
+    .bind(hash_token(user_agent))
+    .bind(ip)
+    .bind(now)
+    .fetch_one(&app_state.database_connection_pool)
+    .await;
+
+    match (known_res, upsert_res) {
+        // the very first sign in has nothing to compare against
+        (Ok(true), Ok(true)) => send_in_background(
+            app_state.mailer.clone(),
+            email.to_string(),
+            "New sign in to your Gravatar account".to_string(),
+            format!(
+                "Your account was just signed in to from a new device or location.\n\n\
+                Address: {}\nBrowser: {}\n\n\
+                If this was not you, sign out all sessions and change your password.",
+                ip, user_agent
+            ),
+        ),
+        (Ok(_), Ok(_)) => {}
+        _ => warn!("Issue recording the sign in source for user {}", user_id),
+    }
+}
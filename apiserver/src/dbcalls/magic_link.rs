@@ -0,0 +1,86 @@
+use crate::{
+    helpers::{hash_token::hash_token, mailer::send_in_background},
+    models::user_model::UserFromDB,
+    AppState,
+};
+
+const MAGIC_LINK_LIFETIME_SECS: i64 = 15 * 60;
+
+// unknown addresses get no email and no error, so the caller learns nothing
+// about which addresses have an account
+pub async fn send_magic_link(email: &str, app_state: &AppState) -> Result<(), String> {
+    let user_res = sqlx::query_as::<_, UserFromDB>("select * from users where email_sha256=$1")
+        .bind(crate::helpers::libravatar_hash::email_sha256(email))
+        .fetch_optional(&app_state.database_connection_pool)
+        .await;
+    let user = match user_res {
+        Err(_) => return Err("Issue talking to the database".to_string()),
+        Ok(None) => return Ok(()),
+        Ok(Some(user)) => user,
+    };
+
+    let id_res = app_state.snow_flake.lock().unwrap().generate_id();
+    if id_res.is_err() {
+        return Err("Issue generating the id".to_string());
+    }
+    let now = crate::helpers::current_time::current_time_secs()?;
+    let link_token = crate::helpers::generate_random_token::generate_random_token();
+
+    // only the newest link works, asking again retires the ones already sent
+    let retire_res = sqlx::query("delete from magic_links where user_id=$1 and used_at is null")
+        .bind(user.id)
+        .execute(&app_state.database_connection_pool)
+        .await;
+    if retire_res.is_err() {
+        return Err("Issue talking to the database".to_string());
+    }
+    let insert_res = sqlx::query(
+        "insert into magic_links(id, user_id, token_hash, created_at, expires_at)
+        values($1, $2, $3, $4, $5)",
+    )
+    .bind(id_res.unwrap() as i64)
+    .bind(user.id)
+    .bind(hash_token(&link_token))
+    .bind(now)
+    .bind(now + MAGIC_LINK_LIFETIME_SECS)
+    .execute(&app_state.database_connection_pool)
+    .await;
+    if insert_res.is_err() {
+        return Err("Issue talking to the database".to_string());
+    }
+
+    send_in_background(
+        app_state.mailer.clone(),
+        user.email,
+        "Your Gravatar sign in link".to_string(),
+        format!(
+            "Use this link to sign in to Gravatar, it works once within the next 15 minutes:\n\
+            {}/api/v1/user/signin/link/{}\n\n\
+            If you did not ask for it, ignore this email, nobody can sign in without the link.",
+            app_state.public_url, link_token
+        ),
+    );
+    Ok(())
+}
+
+// marks the link used in the same statement that checks it, so it can only be redeemed once
+pub async fn redeem_magic_link(
+    link_token: &str,
+    app_state: &AppState,
+) -> Result<Option<UserFromDB>, String> {
+    let now = crate::helpers::current_time::current_time_secs()?;
+    let user_res = sqlx::query_as::<_, UserFromDB>(
+        "update magic_links set used_at=$1 from users
+        where magic_links.token_hash=$2 and magic_links.used_at is null and magic_links.expires_at>$1
+        and users.id=magic_links.user_id
+        returning users.id, users.email, users.email_hash, users.email_sha256, users.active_photo_id",
+    )
+    .bind(now)
+    .bind(hash_token(link_token))
+    .fetch_optional(&app_state.database_connection_pool)
+    .await;
+    match user_res {
+        Err(_) => Err("Issue talking to the database".to_string()),
+        Ok(user) => Ok(user),
+    }
+}
@@ -0,0 +1,167 @@
+use crate::AppState;
+
+// last seen is only written once a minute to keep request overhead low
+const LAST_SEEN_RESOLUTION_SECS: i64 = 60;
+// adding credentials or setting a first password needs a sign in or re-authentication this recent
+pub const REAUTHENTICATION_WINDOW_SECS: i64 = 300;
+
+pub async fn create_session(
+    user_id: i64,
+    user_agent: Option<&str>,
+    ip: Option<&str>,
+    app_state: &AppState,
+) -> Result<i64, String> {
+    let session_id_res = app_state.snow_flake.lock().unwrap().generate_id();
+    if session_id_res.is_err() {
+        return Err("Issue generating the id".to_string());
+    }
+    let session_id = session_id_res.unwrap() as i64;
+    let now = crate::helpers::current_time::current_time_secs()?;
+
+    let insert_res = sqlx::query(
+        "insert into sessions(id, user_id, user_agent, ip, created_at, last_seen_at, authenticated_at)
+        values($1, $2, $3, $4, $5, $5, $5)",
+    )
+    .bind(session_id)
+    .bind(user_id)
+    .bind(user_agent.map(|user_agent| user_agent.chars().take(512).collect::<String>()))
+    .bind(ip)
+    .bind(now)
+    .execute(&app_state.database_connection_pool)
+    .await;
+
+    match insert_res {
+        Err(_) => Err("Issue talking to the database".to_string()),
+        Ok(_) => Ok(session_id),
+    }
+}
+
+pub async fn mark_authenticated(
+    user_id: i64,
+    session_id: i64,
+    app_state: &AppState,
+) -> Result<(), String> {
+    let now = crate::helpers::current_time::current_time_secs()?;
+    let update_res = sqlx::query(
+        "update sessions set authenticated_at=$1 where id=$2 and user_id=$3 and revoked_at is null",
+    )
+    .bind(now)
+    .bind(session_id)
+    .bind(user_id)
+    .execute(&app_state.database_connection_pool)
+    .await;
+    match update_res {
+        Err(_) => Err("Issue talking to the database".to_string()),
+        Ok(_) => Ok(()),
+    }
+}
+
+// api tokens have no session, so they never count as a recent sign in
+pub async fn recently_authenticated(
+    user_id: i64,
+    session_id: Option<i64>,
+    app_state: &AppState,
+) -> Result<bool, String> {
+    let session_id = match session_id {
+        Some(session_id) => session_id,
+        None => return Ok(false),
+    };
+    let now = crate::helpers::current_time::current_time_secs()?;
+    let recent_res = sqlx::query_scalar::<_, bool>(
+        "select exists(select 1 from sessions where id=$1 and user_id=$2
+        and revoked_at is null and authenticated_at>$3)",
+    )
+    .bind(session_id)
+    .bind(user_id)
+    .bind(now - REAUTHENTICATION_WINDOW_SECS)
+    .fetch_one(&app_state.database_connection_pool)
+    .await;
+    match recent_res {
+        Err(_) => Err("Issue talking to the database".to_string()),
+        Ok(recent) => Ok(recent),
+    }
+}
+
+pub async fn touch_session(session_id: i64, app_state: &AppState) {
+    let now = match crate::helpers::current_time::current_time_secs() {
+        Ok(now) => now,
+        Err(_) => return,
+    };
+    let _ = sqlx::query("update sessions set last_seen_at=$1 where id=$2 and last_seen_at<$3")
+        .bind(now)
+        .bind(session_id)
+        .bind(now - LAST_SEEN_RESOLUTION_SECS)
+        .execute(&app_state.database_connection_pool)
+        .await;
+}
+
+// returns false when the session does not exist or belongs to someone else
+pub async fn revoke_session(
+    user_id: i64,
+    session_id: i64,
+    app_state: &AppState,
+) -> Result<bool, String> {
+    let now = crate::helpers::current_time::current_time_secs()?;
+
+    let revoke_res = sqlx::query(
+        "update sessions set revoked_at=$1 where id=$2 and user_id=$3 and revoked_at is null",
+    )
+    .bind(now)
+    .bind(session_id)
+    .bind(user_id)
+    .execute(&app_state.database_connection_pool)
+    .await;
+
+    if revoke_res.is_err() {
+        return Err("Issue talking to the database".to_string());
+    }
+    if revoke_res.unwrap().rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    let tokens_res = sqlx::query("update refresh_tokens set revoked=true where session_id=$1")
+        .bind(session_id)
+        .execute(&app_state.database_connection_pool)
+        .await;
+    if tokens_res.is_err() {
+        return Err("Issue talking to the database".to_string());
+    }
+
+    app_state.session_store.remove(session_id).await?;
+    Ok(true)
+}
+
+#[derive(sqlx::FromRow)]
+struct RevokedSession {
+    id: i64,
+}
+
+pub async fn revoke_all_sessions(user_id: i64, app_state: &AppState) -> Result<(), String> {
+    let now = crate::helpers::current_time::current_time_secs()?;
+
+    let revoke_res = sqlx::query_as::<_, RevokedSession>(
+        "update sessions set revoked_at=$1 where user_id=$2 and revoked_at is null returning id",
+    )
+    .bind(now)
+    .bind(user_id)
+    .fetch_all(&app_state.database_connection_pool)
+    .await;
+
+    if revoke_res.is_err() {
+        return Err("Issue talking to the database".to_string());
+    }
+    let revoked = revoke_res.unwrap();
+
+    let tokens_res = sqlx::query("update refresh_tokens set revoked=true where user_id=$1")
+        .bind(user_id)
+        .execute(&app_state.database_connection_pool)
+        .await;
+    if tokens_res.is_err() {
+        return Err("Issue talking to the database".to_string());
+    }
+
+    for session in revoked {
+        app_state.session_store.remove(session.id).await?;
+    }
+    Ok(())
+}
@@ -1 +1,10 @@
-pub mod check_user_exists;
+pub mod add_profile_image;
+pub mod fetch_profile_details;
+pub mod fetch_profile_images;
+pub mod find_api_token;
+pub mod link_external_identity;
+pub mod login_protection;
+pub mod magic_link;
+pub mod manage_sessions;
+pub mod select_profile_image;
+pub mod two_factor;
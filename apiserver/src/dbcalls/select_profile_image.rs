@@ -0,0 +1,41 @@
+use crate::{
+    models::{profile_model::ProfileFromDB, user_model::UserFromDB},
+    AppState,
+};
+
+// returns false when the image does not belong to the user
+pub async fn select_profile_image(
+    user_id: i64,
+    profile_id: i64,
+    app_state: &AppState,
+) -> Result<bool, String> {
+    // check if profile exists in the database
+    let profile_exists_res =
+        sqlx::query_as::<_, ProfileFromDB>("select * from profile where user_id=$1 and id=$2")
+            .bind(user_id)
+            .bind(profile_id)
+            .fetch_optional(&app_state.database_connection_pool)
+            .await;
+
+    if profile_exists_res.is_err() {
+        return Err("Issue talking to the database".to_string());
+    }
+
+    if profile_exists_res.unwrap().is_none() {
+        return Ok(false);
+    }
+
+    let updated_user_res = sqlx::query_as::<_, UserFromDB>(
+        "update users set active_photo_id=$1 where id=$2 returning *",
+    )
+    .bind(profile_id)
+    .bind(user_id)
+    .fetch_optional(&app_state.database_connection_pool)
+    .await;
+
+    if updated_user_res.is_err() {
+        return Err("Issue updating the database".to_string());
+    }
+
+    Ok(true)
+}
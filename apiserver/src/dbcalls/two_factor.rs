@@ -0,0 +1,211 @@
+use sqlx::prelude::FromRow;
+
+use crate::{
+    helpers::{hash_token::hash_token, totp},
+    AppState,
+};
+
+// how long a password sign in may wait for its second factor
+const LOGIN_CHALLENGE_LIFETIME_SECS: i64 = 300;
+const LOGIN_CHALLENGE_MAX_ATTEMPTS: i32 = 5;
+
+#[derive(FromRow)]
+pub struct TotpCredentialFromDB {
+    pub secret: String,
+    pub confirmed_at: Option<i64>,
+    pub last_used_step: Option<i64>,
+}
+
+#[derive(FromRow)]
+pub struct LoginChallengeFromDB {
+    pub user_id: i64,
+    pub expires_at: i64,
+    pub attempts: i32,
+}
+
+pub async fn fetch_totp_credential(
+    user_id: i64,
+    app_state: &AppState,
+) -> Result<Option<TotpCredentialFromDB>, String> {
+    let credential_res = sqlx::query_as::<_, TotpCredentialFromDB>(
+        "select secret, confirmed_at, last_used_step from totp_credentials where user_id=$1",
+    )
+    .bind(user_id)
+    .fetch_optional(&app_state.database_connection_pool)
+    .await;
+
+    match credential_res {
+        Err(_) => Err("Issue talking to the database".to_string()),
+        Ok(credential) => Ok(credential),
+    }
+}
+
+pub async fn is_two_factor_enabled(user_id: i64, app_state: &AppState) -> Result<bool, String> {
+    let credential = fetch_totp_credential(user_id, app_state).await?;
+    Ok(credential.is_some_and(|credential| credential.confirmed_at.is_some()))
+}
+
+// accepts either a current authenticator code or an unused recovery code
+pub async fn verify_second_factor(
+    user_id: i64,
+    code: Option<&str>,
+    recovery_code: Option<&str>,
+    app_state: &AppState,
+) -> Result<bool, String> {
+    let now = crate::helpers::current_time::current_time_secs()?;
+
+    if let Some(code) = code {
+        let credential = match fetch_totp_credential(user_id, app_state).await? {
+            Some(credential) if credential.confirmed_at.is_some() => credential,
+            _ => return Ok(false),
+        };
+        let step = match totp::verify_code(&credential.secret, code, now, credential.last_used_step)
+        {
+            Some(step) => step,
+            None => return Ok(false),
+        };
+        // a code is good for one use, even within its thirty seconds
+        let update_res = sqlx::query(
+            "update totp_credentials set last_used_step=$1
+            where user_id=$2 and (last_used_step is null or last_used_step<$1)",
+        )
+        .bind(step)
+        .bind(user_id)
+        .execute(&app_state.database_connection_pool)
+        .await;
+        return match update_res {
+            Err(_) => Err("Issue talking to the database".to_string()),
+            Ok(result) => Ok(result.rows_affected() == 1),
+        };
+    }
+
+    if let Some(recovery_code) = recovery_code {
+        let use_res = sqlx::query(
+            "update recovery_codes set used_at=$1
+            where user_id=$2 and code_hash=$3 and used_at is null",
+        )
+        .bind(now)
+        .bind(user_id)
+        .bind(hash_token(&totp::normalize_recovery_code(recovery_code)))
+        .execute(&app_state.database_connection_pool)
+        .await;
+        return match use_res {
+            Err(_) => Err("Issue talking to the database".to_string()),
+            Ok(result) => Ok(result.rows_affected() == 1),
+        };
+    }
+
+    Ok(false)
+}
+
+// earlier recovery codes stop working once new ones are issued
+pub async fn replace_recovery_codes(
+    user_id: i64,
+    app_state: &AppState,
+) -> Result<Vec<String>, String> {
+    let now = crate::helpers::current_time::current_time_secs()?;
+    let recovery_codes = totp::generate_recovery_codes();
+
+    let mut transaction = match app_state.database_connection_pool.begin().await {
+        Ok(transaction) => transaction,
+        Err(_) => return Err("Issue talking to the database".to_string()),
+    };
+    let delete_res = sqlx::query("delete from recovery_codes where user_id=$1")
+        .bind(user_id)
+        .execute(&mut *transaction)
+        .await;
+    if delete_res.is_err() {
+        return Err("Issue talking to the database".to_string());
+    }
+
+    for recovery_code in &recovery_codes {
+        let id_res = app_state.snow_flake.lock().unwrap().generate_id();
+        if id_res.is_err() {
+            return Err("Issue generating the id".to_string());
+        }
+        let insert_res = sqlx::query(
+            "insert into recovery_codes(id, user_id, code_hash, created_at) values($1, $2, $3, $4)",
+        )
+        .bind(id_res.unwrap() as i64)
+        .bind(user_id)
+        .bind(hash_token(&totp::normalize_recovery_code(recovery_code)))
+        .bind(now)
+        .execute(&mut *transaction)
+        .await;
+        if insert_res.is_err() {
+            return Err("Issue talking to the database".to_string());
+        }
+    }
+
+    if transaction.commit().await.is_err() {
+        return Err("Issue talking to the database".to_string());
+    }
+    Ok(recovery_codes)
+}
+
+pub async fn create_login_challenge(user_id: i64, app_state: &AppState) -> Result<String, String> {
+    let id_res = app_state.snow_flake.lock().unwrap().generate_id();
+    if id_res.is_err() {
+        return Err("Issue generating the id".to_string());
+    }
+    let now = crate::helpers::current_time::current_time_secs()?;
+    let challenge_token = crate::helpers::generate_random_token::generate_random_token();
+
+    // only the newest challenge stays open, so attempts cannot be spread over many
+    let delete_res = sqlx::query("delete from login_challenges where user_id=$1")
+        .bind(user_id)
+        .execute(&app_state.database_connection_pool)
+        .await;
+    if delete_res.is_err() {
+        return Err("Issue talking to the database".to_string());
+    }
+
+    let insert_res = sqlx::query(
+        "insert into login_challenges(id, token_hash, user_id, created_at, expires_at)
+        values($1, $2, $3, $4, $5)",
+    )
+    .bind(id_res.unwrap() as i64)
+    .bind(hash_token(&challenge_token))
+    .bind(user_id)
+    .bind(now)
+    .bind(now + LOGIN_CHALLENGE_LIFETIME_SECS)
+    .execute(&app_state.database_connection_pool)
+    .await;
+
+    match insert_res {
+        Err(_) => Err("Issue talking to the database".to_string()),
+        Ok(_) => Ok(challenge_token),
+    }
+}
+
+// counts the attempt up front so guessing is capped even under concurrency
+pub async fn take_login_challenge(
+    challenge_token: &str,
+    app_state: &AppState,
+) -> Result<Option<i64>, String> {
+    let now = crate::helpers::current_time::current_time_secs()?;
+    let challenge_res = sqlx::query_as::<_, LoginChallengeFromDB>(
+        "update login_challenges set attempts=attempts+1 where token_hash=$1
+        returning user_id, expires_at, attempts",
+    )
+    .bind(hash_token(challenge_token))
+    .fetch_optional(&app_state.database_connection_pool)
+    .await;
+
+    match challenge_res {
+        Err(_) => Err("Issue talking to the database".to_string()),
+        Ok(Some(challenge))
+            if challenge.expires_at > now && challenge.attempts <= LOGIN_CHALLENGE_MAX_ATTEMPTS =>
+        {
+            Ok(Some(challenge.user_id))
+        }
+        Ok(_) => Ok(None),
+    }
+}
+
+pub async fn finish_login_challenge(challenge_token: &str, app_state: &AppState) {
+    let _ = sqlx::query("delete from login_challenges where token_hash=$1")
+        .bind(hash_token(challenge_token))
+        .execute(&app_state.database_connection_pool)
+        .await;
+}
@@ -0,0 +1,18 @@
+// personal access tokens carry this prefix so the middleware can tell them from jwts
+pub const API_TOKEN_PREFIX: &str = "gpat_";
+// access tokens handed out to oauth clients
+pub const OAUTH_TOKEN_PREFIX: &str = "goat_";
+
+pub const AVATARS_READ: &str = "avatars:read";
+pub const AVATARS_WRITE: &str = "avatars:write";
+pub const PROFILE_READ: &str = "profile:read";
+pub const PROFILE_WRITE: &str = "profile:write";
+
+pub const ALL_SCOPES: [&str; 4] = [AVATARS_READ, AVATARS_WRITE, PROFILE_READ, PROFILE_WRITE];
+
+pub const OPENID: &str = "openid";
+pub const PROFILE: &str = "profile";
+pub const EMAIL: &str = "email";
+
+// what third party apps may ask for, profile editing stays with the user
+pub const OAUTH_SCOPES: [&str; 5] = [OPENID, PROFILE, EMAIL, AVATARS_READ, AVATARS_WRITE];
@@ -0,0 +1,323 @@
+use std::{fs::File, io::Write, path::PathBuf};
+
+use actix_web::web;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use log::{error, info};
+use zip::{write::SimpleFileOptions, ZipWriter};
+
+use sha2::Sha256;
+use sqlx::{Pool, Postgres};
+
+use crate::{
+    models::{export_model::DataExportFromDB, user_model::UserFromDB},
+    AppState,
+};
+
+// download links stay valid for a day after the archive is built
+pub const EXPORT_LINK_LIFETIME_SECS: i64 = 86400;
+// each export downloads every image again, so users get one a day
+pub const EXPORT_REQUEST_COOLDOWN_SECS: i64 = 86400;
+// a build still pending after this long died with the process that ran it
+pub const EXPORT_BUILD_TIMEOUT_SECS: i64 = 3600;
+
+#[derive(serde::Serialize)]
+struct ExportedEmail {
+    email: String,
+    md5: String,
+    sha256: String,
+}
+
+#[derive(serde::Serialize)]
+struct ExportedImage {
+    id: i64,
+    url: String,
+    active: bool,
+    file: Option<String>,
+}
+
+struct DownloadedImage {
+    file_name: String,
+    bytes: Vec<u8>,
+}
+
+// derived rather than random so every status check hands out the same link, only
+// its digest is stored and a rebuilt archive with a new expiry gets a new one
+pub fn download_token(secret: &str, export_id: i64, expires_at: i64) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac accepts keys of any length");
+    mac.update(format!("export-download:{}:{}", export_id, expires_at).as_bytes());
+    URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes())
+}
+
+pub async fn build_export(app_state: web::Data<AppState>, export_id: i64, user_id: i64) {
+    let update_result = match write_export(&app_state, export_id, user_id).await {
+        Ok(file_path) => {
+            let now = match crate::helpers::current_time::current_time_secs() {
+                Ok(now) => now,
+                Err(err_string) => {
+                    error!("Export {} failed: {}", export_id, err_string);
+                    return;
+                }
+            };
+            info!("Export {} is ready", export_id);
+            // a build that outlived the timeout was already marked failed and stays that way
+            let expires_at = now + EXPORT_LINK_LIFETIME_SECS;
+            let ready_res = sqlx::query(
+                "update data_exports set status='ready', file_path=$1, expires_at=$2, download_token=$3 where id=$4 and status='pending'",
+            )
+            .bind(file_path.to_string_lossy().to_string())
+            .bind(expires_at)
+            .bind(crate::helpers::hash_token::hash_token(&download_token(
+                &app_state.access_token_secret,
+                export_id,
+                expires_at,
+            )))
+            .bind(export_id)
+            .execute(&app_state.database_connection_pool)
+            .await;
+            if ready_res
+                .as_ref()
+                .is_ok_and(|ready| ready.rows_affected() == 0)
+            {
+                let _ = tokio::fs::remove_file(&file_path).await;
+            }
+            ready_res
+        }
+        Err(err_string) => {
+            error!("Export {} failed: {}", export_id, err_string);
+            sqlx::query("update data_exports set status='failed' where id=$1")
+                .bind(export_id)
+                .execute(&app_state.database_connection_pool)
+                .await
+        }
+    };
+
+    if update_result.is_err() {
+        error!("Issue updating the status of export {}", export_id);
+    }
+}
+
+// frees the one pending export slot when the process building it went away,
+// only for one user when given
+pub async fn fail_stale_exports(
+    pool: &Pool<Postgres>,
+    user_id: Option<i64>,
+) -> Result<u64, String> {
+    let now = crate::helpers::current_time::current_time_secs()?;
+    match sqlx::query(
+        "update data_exports set status='failed' where status='pending' and created_at <= $1 and ($2::bigint is null or user_id=$2)",
+    )
+    .bind(now - EXPORT_BUILD_TIMEOUT_SECS)
+    .bind(user_id)
+    .execute(pool)
+    .await
+    {
+        Ok(failed) => Ok(failed.rows_affected()),
+        Err(_) => Err("Issue talking to the database".to_string()),
+    }
+}
+
+// archives are otherwise only removed when someone follows an expired link
+pub async fn remove_expired_exports(pool: &Pool<Postgres>) -> Result<u64, String> {
+    let now = crate::helpers::current_time::current_time_secs()?;
+    let expired_res = sqlx::query_as::<_, DataExportFromDB>(
+        "update data_exports set status='expired', download_token=null where status='ready' and expires_at <= $1 returning *",
+    )
+    .bind(now)
+    .fetch_all(pool)
+    .await;
+    if expired_res.is_err() {
+        return Err("Issue talking to the database".to_string());
+    }
+
+    let mut removed = 0;
+    for export in expired_res.unwrap() {
+        if let Some(file_path) = &export.file_path {
+            match tokio::fs::remove_file(file_path).await {
+                Ok(()) => removed += 1,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => error!("Issue removing export {}: {}", export.id, err),
+            }
+        }
+        let _ = sqlx::query("update data_exports set file_path=null where id=$1")
+            .bind(export.id)
+            .execute(pool)
+            .await;
+    }
+    Ok(removed)
+}
+
+async fn write_export(
+    app_state: &web::Data<AppState>,
+    export_id: i64,
+    user_id: i64,
+) -> Result<PathBuf, String> {
+    let user_res = sqlx::query_as::<_, UserFromDB>("select * from users where id=$1")
+        .bind(user_id)
+        .fetch_optional(&app_state.database_connection_pool)
+        .await;
+    if user_res.is_err() {
+        return Err("Issue talking to the database".to_string());
+    }
+    let user = match user_res.unwrap() {
+        Some(user) => user,
+        None => return Err("User not found".to_string()),
+    };
+
+    let images_res =
+        crate::dbcalls::fetch_profile_images::fetch_profile_images(user_id, app_state).await;
+    if let Err(err_string) = images_res {
+        return Err(err_string);
+    }
+    let images = images_res.unwrap();
+
+    let details_res =
+        crate::dbcalls::fetch_profile_details::fetch_profile_details(user_id, app_state).await;
+    if let Err(err_string) = details_res {
+        return Err(err_string);
+    }
+    let (details, links) = details_res.unwrap();
+
+    let emails = vec![ExportedEmail {
+        md5: crate::helpers::libravatar_hash::email_md5(&user.email),
+        sha256: crate::helpers::libravatar_hash::email_sha256(&user.email),
+        email: user.email.clone(),
+    }];
+
+    let mut image_metadata = Vec::new();
+    let mut downloaded_images = Vec::new();
+    for image in images.iter() {
+        let url = crate::helpers::image_url::image_url(
+            &app_state.cloudinary_cloud_name,
+            user_id,
+            image.id,
+            None,
+        );
+        let downloaded = download_image(&app_state.http_client, &url, image.id).await;
+        if let Err(err_string) = &downloaded {
+            error!("Export {}: {}", export_id, err_string);
+        }
+        let downloaded = downloaded.ok();
+        image_metadata.push(ExportedImage {
+            id: image.id,
+            url,
+            active: image.id == user.active_photo_id,
+            file: downloaded
+                .as_ref()
+                .map(|downloaded| format!("images/{}", downloaded.file_name)),
+        });
+        if let Some(downloaded) = downloaded {
+            downloaded_images.push(downloaded);
+        }
+    }
+
+    let account_json = serde_json::to_vec_pretty(&user);
+    let emails_json = serde_json::to_vec_pretty(&emails);
+    let images_json = serde_json::to_vec_pretty(&image_metadata);
+    let profile_json = serde_json::to_vec_pretty(&details.into_details(links));
+    if account_json.is_err()
+        || emails_json.is_err()
+        || images_json.is_err()
+        || profile_json.is_err()
+    {
+        return Err("Issue serializing the export".to_string());
+    }
+
+    let file_path = app_state
+        .export_dir
+        .join(format!("export-{}-{}.zip", user_id, export_id));
+    let archive_path = file_path.clone();
+    let entries = vec![
+        ("account.json".to_string(), account_json.unwrap()),
+        ("emails.json".to_string(), emails_json.unwrap()),
+        ("images.json".to_string(), images_json.unwrap()),
+        ("profile.json".to_string(), profile_json.unwrap()),
+    ]
+    .into_iter()
+    .chain(
+        downloaded_images
+            .into_iter()
+            .map(|image| (format!("images/{}", image.file_name), image.bytes)),
+    )
+    .collect::<Vec<(String, Vec<u8>)>>();
+
+    let write_res = web::block(move || write_zip(&archive_path, entries)).await;
+    match write_res {
+        Ok(Ok(())) => Ok(file_path),
+        Ok(Err(err_string)) => Err(err_string),
+        Err(_) => Err("Issue writing the archive".to_string()),
+    }
+}
+
+async fn download_image(
+    client: &reqwest::Client,
+    url: &str,
+    image_id: i64,
+) -> Result<DownloadedImage, String> {
+    let response_res = client.get(url).send().await;
+    if response_res.is_err() {
+        return Err(format!("Issue downloading image {}", image_id));
+    }
+    let response = response_res.unwrap();
+    if !response.status().is_success() {
+        return Err(format!(
+            "Issue downloading image {}: {}",
+            image_id,
+            response.status()
+        ));
+    }
+
+    let extension = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<mime::Mime>().ok())
+        .filter(|content_type| content_type.type_() == mime::IMAGE)
+        .map(|content_type| content_type.subtype().as_str().to_string())
+        .unwrap_or("bin".to_string());
+
+    let bytes_res = response.bytes().await;
+    if bytes_res.is_err() {
+        return Err(format!("Issue reading image {}", image_id));
+    }
+
+    Ok(DownloadedImage {
+        file_name: format!("{}.{}", image_id, extension),
+        bytes: bytes_res.unwrap().to_vec(),
+    })
+}
+
+fn write_zip(file_path: &PathBuf, entries: Vec<(String, Vec<u8>)>) -> Result<(), String> {
+    let file_res = File::create(file_path);
+    if file_res.is_err() {
+        return Err("Issue creating the archive file".to_string());
+    }
+
+    let mut zip = ZipWriter::new(file_res.unwrap());
+    let options = SimpleFileOptions::default();
+    for (name, bytes) in entries {
+        if zip.start_file(name, options).is_err() || zip.write_all(&bytes).is_err() {
+            return Err("Issue writing the archive".to_string());
+        }
+    }
+
+    if zip.finish().is_err() {
+        return Err("Issue writing the archive".to_string());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn download_tokens_are_stable_until_the_archive_changes() {
+        let token = download_token("secret", 42, 1_700_000_000);
+        assert_eq!(token, download_token("secret", 42, 1_700_000_000));
+        assert_ne!(token, download_token("secret", 42, 1_700_086_400));
+        assert_ne!(token, download_token("secret", 43, 1_700_000_000));
+        assert_ne!(token, download_token("other secret", 42, 1_700_000_000));
+    }
+}
@@ -0,0 +1,178 @@
+use std::net::{IpAddr, SocketAddr};
+
+use actix_web::http::header::HeaderMap;
+
+// an address or cidr block that a reverse proxy in front of the server connects from
+#[derive(Debug, Clone, PartialEq)]
+struct ProxyRange {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl ProxyRange {
+    fn parse(raw: &str) -> Result<Self, String> {
+        let (address, prefix) = match raw.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (raw, None),
+        };
+        let network: IpAddr = match address.parse() {
+            Ok(network) => network,
+            Err(_) => return Err(format!("{} is not an ip address", raw)),
+        };
+        let max_prefix = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix.map(|prefix| prefix.parse::<u8>()) {
+            None => max_prefix,
+            Some(Ok(prefix)) if prefix <= max_prefix => prefix,
+            Some(_) => return Err(format!("{} has an invalid prefix length", raw)),
+        };
+        Ok(ProxyRange { network, prefix })
+    }
+
+    fn contains(&self, ip: &IpAddr) -> bool {
+        // mapped addresses show up when a dual stack socket accepts ipv4
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(*ip),
+            IpAddr::V4(_) => *ip,
+        };
+        match (self.network, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+// forwarded headers are only believed when the connection comes from one of
+// these, anyone else could write whatever address they like into them
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies {
+    ranges: Vec<ProxyRange>,
+}
+
+impl TrustedProxies {
+    // TRUSTED_PROXIES is a comma separated list of addresses and cidr blocks
+    pub fn parse(raw: &str) -> Result<Self, String> {
+        let mut ranges = Vec::new();
+        for entry in raw
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+        {
+            ranges.push(ProxyRange::parse(entry)?);
+        }
+        Ok(TrustedProxies { ranges })
+    }
+
+    fn is_trusted(&self, ip: &IpAddr) -> bool {
+        self.ranges.iter().any(|range| range.contains(ip))
+    }
+
+    // walks x-forwarded-for from the nearest hop outwards and stops at the first
+    // address that is not one of our proxies, that is the client
+    pub fn client_ip(&self, peer_addr: Option<SocketAddr>, headers: &HeaderMap) -> String {
+        let peer_ip = match peer_addr {
+            Some(peer_addr) => peer_addr.ip(),
+            None => return "unknown".to_string(),
+        };
+        if !self.is_trusted(&peer_ip) {
+            return peer_ip.to_string();
+        }
+
+        let mut client_ip = peer_ip;
+        let hops = headers
+            .get_all("x-forwarded-for")
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .collect::<Vec<&str>>();
+        for hop in hops.into_iter().rev() {
+            // a garbled entry could be anything, so the last good hop is as far as we trust
+            let hop_ip = match hop.parse::<IpAddr>() {
+                Ok(hop_ip) => hop_ip,
+                Err(_) => break,
+            };
+            client_ip = hop_ip;
+            if !self.is_trusted(&hop_ip) {
+                break;
+            }
+        }
+        client_ip.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::header::{HeaderName, HeaderValue};
+
+    use super::*;
+
+    fn forwarded(values: &[&str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append(
+                HeaderName::from_static("x-forwarded-for"),
+                HeaderValue::from_str(value).unwrap(),
+            );
+        }
+        headers
+    }
+
+    fn peer(ip: &str) -> Option<SocketAddr> {
+        Some(SocketAddr::new(ip.parse().unwrap(), 40000))
+    }
+
+    #[test]
+    fn ignores_forwarded_headers_from_untrusted_peers() {
+        let proxies = TrustedProxies::parse("10.0.0.0/8").unwrap();
+        let headers = forwarded(&["1.2.3.4"]);
+        assert_eq!(
+            proxies.client_ip(peer("203.0.113.9"), &headers),
+            "203.0.113.9"
+        );
+        assert_eq!(
+            TrustedProxies::default().client_ip(peer("10.0.0.1"), &headers),
+            "10.0.0.1"
+        );
+        assert_eq!(proxies.client_ip(None, &headers), "unknown");
+    }
+
+    #[test]
+    fn takes_the_first_untrusted_hop_from_the_right() {
+        let proxies = TrustedProxies::parse("10.0.0.0/8, 192.168.1.1").unwrap();
+        // the client made up the first entry, our proxies appended the rest
+        let headers = forwarded(&["6.6.6.6, 198.51.100.7", "192.168.1.1"]);
+        assert_eq!(
+            proxies.client_ip(peer("10.1.2.3"), &headers),
+            "198.51.100.7"
+        );
+        assert_eq!(
+            proxies.client_ip(peer("10.1.2.3"), &forwarded(&[])),
+            "10.1.2.3"
+        );
+        assert_eq!(
+            proxies.client_ip(peer("10.1.2.3"), &forwarded(&["6.6.6.6, junk"])),
+            "10.1.2.3"
+        );
+        assert_eq!(
+            proxies.client_ip(peer("::ffff:10.1.2.3"), &forwarded(&["2001:db8::1"])),
+            "2001:db8::1"
+        );
+    }
+
+    #[test]
+    fn parses_addresses_and_cidr_blocks() {
+        assert!(TrustedProxies::parse("").unwrap().ranges.is_empty());
+        assert!(TrustedProxies::parse("10.0.0.0/33").is_err());
+        assert!(TrustedProxies::parse("proxy.local").is_err());
+        let proxies = TrustedProxies::parse("0.0.0.0/0, fd00::/8").unwrap();
+        assert!(proxies.is_trusted(&"8.8.8.8".parse().unwrap()));
+        assert!(proxies.is_trusted(&"fd12::1".parse().unwrap()));
+        assert!(!proxies.is_trusted(&"2001:db8::1".parse().unwrap()));
+    }
+}
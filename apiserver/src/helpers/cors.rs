@@ -0,0 +1,220 @@
+use actix_cors::Cors;
+use actix_web::http::{header::HeaderName, Method};
+
+// headers browser clients need to read back, mostly for backing off when rate limited
+const EXPOSED_HEADERS: [&str; 6] = [
+    "retry-after",
+    "www-authenticate",
+    "ratelimit-limit",
+    "ratelimit-remaining",
+    "ratelimit-reset",
+    "ratelimit-policy",
+];
+
+const DEFAULT_METHODS: [&str; 4] = ["GET", "POST", "PUT", "DELETE"];
+const DEFAULT_HEADERS: [&str; 3] = ["authorization", "content-type", "x-csrf-token"];
+
+// which browser origins may call the api and what they may send, the avatar
+// routes are public and do not go through this
+#[derive(Debug, Clone)]
+pub struct CorsPolicy {
+    pub allowed_origins: Vec<String>,
+    pub allowed_methods: Vec<Method>,
+    pub allowed_headers: Vec<HeaderName>,
+    pub allow_credentials: bool,
+    pub max_age_secs: usize,
+}
+
+impl CorsPolicy {
+    // empty method and header lists fall back to what the spa needs
+    pub fn parse(
+        origins: &[String],
+        methods: &[String],
+        headers: &[String],
+        allow_credentials: bool,
+        max_age_secs: usize,
+    ) -> Result<Self, String> {
+        let allowed_origins = crate::helpers::csrf::parse_origins(&origins.join(","))?;
+
+        let methods: Vec<&str> = if methods.is_empty() {
+            DEFAULT_METHODS.to_vec()
+        } else {
+            methods.iter().map(|method| method.trim()).collect()
+        };
+        let mut allowed_methods: Vec<Method> = Vec::new();
+        for method in methods {
+            match Method::from_bytes(method.to_uppercase().as_bytes()) {
+                Ok(method) if !method.as_str().is_empty() => allowed_methods.push(method),
+                _ => return Err(format!("{} is not an http method", method)),
+            }
+        }
+
+        let headers: Vec<&str> = if headers.is_empty() {
+            DEFAULT_HEADERS.to_vec()
+        } else {
+            headers.iter().map(|header| header.trim()).collect()
+        };
+        let mut allowed_headers: Vec<HeaderName> = Vec::new();
+        for header in headers {
+            match HeaderName::from_bytes(header.as_bytes()) {
+                Ok(header) => allowed_headers.push(header),
+                Err(_) => return Err(format!("{} is not a header name", header)),
+            }
+        }
+
+        Ok(CorsPolicy {
+            allowed_origins,
+            allowed_methods,
+            allowed_headers,
+            allow_credentials,
+            max_age_secs,
+        })
+    }
+
+    // a fresh middleware per worker, only listed origins get an answer
+    pub fn api(&self) -> Cors {
+        let mut cors = Cors::default()
+            .allowed_methods(self.allowed_methods.clone())
+            .allowed_headers(self.allowed_headers.clone())
+            .expose_headers(EXPOSED_HEADERS)
+            .max_age(self.max_age_secs);
+        for origin in &self.allowed_origins {
+            cors = cors.allowed_origin(origin);
+        }
+        if self.allow_credentials {
+            cors = cors.supports_credentials();
+        }
+        cors
+    }
+}
+
+// avatars and public profiles can be embedded and fetched from anywhere, without cookies
+pub fn public_cors() -> Cors {
+    Cors::default()
+        .allow_any_origin()
+        .send_wildcard()
+        .allowed_methods(vec![Method::GET, Method::HEAD])
+        .expose_headers(EXPOSED_HEADERS)
+        .max_age(86400)
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{
+        http::header,
+        test::{self, TestRequest},
+        web, App, HttpResponse,
+    };
+
+    use super::*;
+
+    fn list(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    fn policy() -> CorsPolicy {
+        CorsPolicy::parse(&list(&["https://app.example.com"]), &[], &[], true, 600).unwrap()
+    }
+
+    #[test]
+    fn parses_the_policy() {
+        let policy = policy();
+        assert_eq!(policy.allowed_origins, vec!["https://app.example.com"]);
+        assert_eq!(policy.allowed_methods.len(), 4);
+        assert!(policy
+            .allowed_headers
+            .contains(&HeaderName::from_static("x-csrf-token")));
+        assert!(policy.allow_credentials);
+
+        let custom = CorsPolicy::parse(
+            &[],
+            &list(&["get", " patch"]),
+            &list(&["content-type"]),
+            false,
+            0,
+        )
+        .unwrap();
+        assert_eq!(custom.allowed_methods, vec![Method::GET, Method::PATCH]);
+        assert!(!custom.allow_credentials);
+
+        assert!(
+            CorsPolicy::parse(&list(&["https://app.example.com/path"]), &[], &[], true, 0).is_err()
+        );
+        assert!(CorsPolicy::parse(&[], &list(&["GET", ""]), &[], true, 0).is_err());
+        assert!(CorsPolicy::parse(&[], &[], &list(&["bad header"]), true, 0).is_err());
+    }
+
+    #[actix_web::test]
+    async fn api_scopes_answer_listed_origins_with_credentials() {
+        let app = test::init_service(
+            App::new().service(
+                web::scope("/api/v1/profile")
+                    .wrap(policy().api())
+                    .route("/details", web::put().to(HttpResponse::Ok)),
+            ),
+        )
+        .await;
+
+        let preflight = TestRequest::default()
+            .method(Method::OPTIONS)
+            .uri("/api/v1/profile/details")
+            .insert_header((header::ORIGIN, "https://app.example.com"))
+            .insert_header((header::ACCESS_CONTROL_REQUEST_METHOD, "PUT"))
+            .insert_header((header::ACCESS_CONTROL_REQUEST_HEADERS, "x-csrf-token"))
+            .to_request();
+        let response = test::call_service(&app, preflight).await;
+        assert!(response.status().is_success());
+        let headers = response.headers();
+        assert_eq!(
+            headers.get(header::ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(),
+            "https://app.example.com"
+        );
+        assert_eq!(
+            headers
+                .get(header::ACCESS_CONTROL_ALLOW_CREDENTIALS)
+                .unwrap(),
+            "true"
+        );
+
+        let foreign = TestRequest::default()
+            .method(Method::OPTIONS)
+            .uri("/api/v1/profile/details")
+            .insert_header((header::ORIGIN, "https://evil.example"))
+            .insert_header((header::ACCESS_CONTROL_REQUEST_METHOD, "PUT"))
+            .to_request();
+        let response = test::try_call_service(&app, foreign).await;
+        let allowed = response.ok().and_then(|response| {
+            response
+                .headers()
+                .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+                .cloned()
+        });
+        assert!(allowed.is_none());
+    }
+
+    #[actix_web::test]
+    async fn avatars_are_open_to_any_origin() {
+        let app = test::init_service(
+            App::new().service(
+                web::resource("/avatar/{hash}")
+                    .wrap(public_cors())
+                    .route(web::get().to(HttpResponse::Ok)),
+            ),
+        )
+        .await;
+
+        let request = TestRequest::get()
+            .uri("/avatar/abc")
+            .insert_header((header::ORIGIN, "https://blog.example"))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        let headers = response.headers();
+        assert_eq!(
+            headers.get(header::ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(),
+            "*"
+        );
+        assert!(headers
+            .get(header::ACCESS_CONTROL_ALLOW_CREDENTIALS)
+            .is_none());
+    }
+}
@@ -0,0 +1,218 @@
+use actix_web::http::{header, header::HeaderMap, Method};
+
+pub const CSRF_COOKIE: &str = "csrfToken";
+pub const CSRF_HEADER: &str = "x-csrf-token";
+
+fn origin_of(url: &str) -> Option<String> {
+    let origin = reqwest::Url::parse(url.trim()).ok()?.origin();
+    if !origin.is_tuple() {
+        return None;
+    }
+    Some(origin.ascii_serialization())
+}
+
+// a comma separated list of bare origins like https://app.example.com
+pub fn parse_origins(raw: &str) -> Result<Vec<String>, String> {
+    let mut origins: Vec<String> = Vec::new();
+    for entry in raw.split(',').map(|entry| entry.trim()) {
+        if entry.is_empty() {
+            continue;
+        }
+        match origin_of(entry) {
+            Some(origin) if entry.trim_end_matches('/') == origin => {
+                if !origins.contains(&origin) {
+                    origins.push(origin);
+                }
+            }
+            _ => return Err(format!("{} is not an origin", entry)),
+        }
+    }
+    Ok(origins)
+}
+
+// browser origins allowed to make cookie authenticated writes, the public url
+// is always one of them
+#[derive(Debug, Clone, Default)]
+pub struct TrustedOrigins {
+    origins: Vec<String>,
+}
+
+impl TrustedOrigins {
+    pub fn parse(raw: &str, public_url: &str) -> Result<Self, String> {
+        let mut trusted_origins = TrustedOrigins::default();
+        if let Some(origin) = origin_of(public_url) {
+            trusted_origins.origins.push(origin);
+        }
+        trusted_origins.extend(&parse_origins(raw)?);
+        Ok(trusted_origins)
+    }
+
+    pub fn extend(&mut self, origins: &[String]) {
+        for origin in origins {
+            if !self.contains(origin) {
+                self.origins.push(origin.clone());
+            }
+        }
+    }
+
+    pub fn contains(&self, origin: &str) -> bool {
+        self.origins.iter().any(|trusted| trusted == origin)
+    }
+
+    // browsers send origin on cross site writes, the referer is the fallback for
+    // older ones and neither is required since other clients send no such header,
+    // also used by the sign in link form to refuse forged posts
+    pub fn allows(&self, headers: &HeaderMap) -> Result<(), String> {
+        let origin = match headers.get(header::ORIGIN) {
+            Some(origin) => Some(origin.to_str().unwrap_or("null").to_string()),
+            None => headers
+                .get(header::REFERER)
+                .map(|referer| origin_of(referer.to_str().unwrap_or("")).unwrap_or_default()),
+        };
+        match origin {
+            Some(origin) if !self.contains(&origin) => {
+                Err(format!("Requests from {} are not allowed", origin))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+// only writes riding on the access token cookie need a check, a bearer token
+// is never attached by the browser on its own
+pub fn check_request(
+    trusted_origins: &TrustedOrigins,
+    method: &Method,
+    headers: &HeaderMap,
+    session_cookie: Option<&str>,
+    csrf_cookie: Option<&str>,
+) -> Result<(), String> {
+    let safe_method = matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS);
+    if safe_method || headers.contains_key(header::AUTHORIZATION) || session_cookie.is_none() {
+        return Ok(());
+    }
+
+    trusted_origins.allows(headers)?;
+
+    // double submit, a page on another site can neither read the cookie nor the
+    // token handed out when signing in
+    let presented = headers
+        .get(CSRF_HEADER)
+        .and_then(|presented| presented.to_str().ok());
+    match (presented, csrf_cookie) {
+        (Some(presented), Some(expected))
+            if presented.len() == expected.len()
+                && openssl::memcmp::eq(presented.as_bytes(), expected.as_bytes()) =>
+        {
+            Ok(())
+        }
+        (None, _) => Err("Missing X-CSRF-Token header".to_string()),
+        _ => Err("Invalid csrf token, sign in again".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::header::{HeaderName, HeaderValue};
+
+    use super::*;
+
+    fn headers(pairs: &[(&str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(
+                HeaderName::from_bytes(name.as_bytes()).unwrap(),
+                HeaderValue::from_str(value).unwrap(),
+            );
+        }
+        headers
+    }
+
+    fn trusted() -> TrustedOrigins {
+        TrustedOrigins::parse(
+            "https://app.example.com, http://localhost:5173/",
+            "https://gravatar.example.com/",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn parses_origins() {
+        let trusted = trusted();
+        assert!(trusted.contains("https://gravatar.example.com"));
+        assert!(trusted.contains("https://app.example.com"));
+        assert!(trusted.contains("http://localhost:5173"));
+        assert!(!trusted.contains("http://app.example.com"));
+        assert!(TrustedOrigins::parse("https://app.example.com/settings", "").is_err());
+        assert!(TrustedOrigins::parse("app.example.com", "").is_err());
+    }
+
+    #[test]
+    fn checks_cookie_authenticated_writes() {
+        let trusted = trusted();
+        let good = headers(&[
+            ("origin", "https://app.example.com"),
+            ("x-csrf-token", "token"),
+        ]);
+        assert!(check_request(&trusted, &Method::POST, &good, Some("jwt"), Some("token")).is_ok());
+        assert!(check_request(&trusted, &Method::PUT, &good, Some("jwt"), Some("other")).is_err());
+        assert!(check_request(&trusted, &Method::DELETE, &good, Some("jwt"), None).is_err());
+
+        let missing_token = headers(&[("origin", "https://app.example.com")]);
+        assert!(check_request(
+            &trusted,
+            &Method::POST,
+            &missing_token,
+            Some("jwt"),
+            Some("token")
+        )
+        .is_err());
+
+        let foreign = headers(&[
+            ("origin", "https://evil.example"),
+            ("x-csrf-token", "token"),
+        ]);
+        assert!(check_request(
+            &trusted,
+            &Method::POST,
+            &foreign,
+            Some("jwt"),
+            Some("token")
+        )
+        .is_err());
+        let foreign_referer = headers(&[
+            ("referer", "https://evil.example/page"),
+            ("x-csrf-token", "token"),
+        ]);
+        assert!(check_request(
+            &trusted,
+            &Method::POST,
+            &foreign_referer,
+            Some("jwt"),
+            Some("token")
+        )
+        .is_err());
+        let sandboxed = headers(&[("origin", "null"), ("x-csrf-token", "token")]);
+        assert!(check_request(
+            &trusted,
+            &Method::POST,
+            &sandboxed,
+            Some("jwt"),
+            Some("token")
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn skips_reads_bearer_tokens_and_anonymous_requests() {
+        let trusted = trusted();
+        let foreign = headers(&[("origin", "https://evil.example")]);
+        assert!(check_request(&trusted, &Method::GET, &foreign, Some("jwt"), None).is_ok());
+        assert!(check_request(&trusted, &Method::POST, &foreign, None, None).is_ok());
+        let bearer = headers(&[
+            ("origin", "https://evil.example"),
+            ("authorization", "Bearer gr_pat_abc"),
+        ]);
+        assert!(check_request(&trusted, &Method::POST, &bearer, Some("jwt"), None).is_ok());
+    }
+}
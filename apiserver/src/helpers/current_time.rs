@@ -0,0 +1,8 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub fn current_time_secs() -> Result<i64, String> {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(duration) => Ok(duration.as_secs() as i64),
+        Err(_) => Err("Issue reading the system time".to_string()),
+    }
+}
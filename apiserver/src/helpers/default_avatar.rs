@@ -0,0 +1,68 @@
+use std::io::Cursor;
+
+use image::{DynamicImage, ImageFormat, Rgba, RgbaImage};
+
+fn encode_png(image: RgbaImage) -> Result<Vec<u8>, String> {
+    let mut png_bytes = Cursor::new(Vec::new());
+    if DynamicImage::ImageRgba8(image)
+        .write_to(&mut png_bytes, ImageFormat::Png)
+        .is_err()
+    {
+        return Err("Issue encoding the default avatar".to_string());
+    }
+    Ok(png_bytes.into_inner())
+}
+
+pub fn blank(size: u32) -> Result<Vec<u8>, String> {
+    encode_png(RgbaImage::from_pixel(size, size, Rgba([0, 0, 0, 0])))
+}
+
+// a grey head and shoulders outline, the "mystery person" default
+pub fn mystery_person(size: u32) -> Result<Vec<u8>, String> {
+    let background = Rgba([196, 196, 196, 255]);
+    let foreground = Rgba([240, 240, 240, 255]);
+    let scale = size as f32;
+    let image = RgbaImage::from_fn(size, size, |x, y| {
+        let (px, py) = (x as f32 / scale, y as f32 / scale);
+        let in_head = (px - 0.5).powi(2) + (py - 0.38).powi(2) <= 0.18f32.powi(2);
+        let in_shoulders =
+            py >= 0.62 && ((px - 0.5) / 0.36).powi(2) + ((py - 1.0) / 0.38).powi(2) <= 1.0;
+        if in_head || in_shoulders {
+            foreground
+        } else {
+            background
+        }
+    });
+    encode_png(image)
+}
+
+// a symmetric 5x5 pattern coloured and shaped by the hash
+pub fn identicon(hash: &str, size: u32) -> Result<Vec<u8>, String> {
+    let bytes = match hex::decode(hash) {
+        Ok(bytes) if bytes.len() >= 16 => bytes,
+        _ => md5::compute(hash.as_bytes()).0.to_vec(),
+    };
+
+    let colour = Rgba([bytes[0], bytes[1], bytes[2], 255]);
+    let background = Rgba([240, 240, 240, 255]);
+    let mut cells = [[false; 5]; 5];
+    for row in 0..5 {
+        for column in 0..3 {
+            let filled = bytes[(3 + row * 3 + column) % bytes.len()] % 2 == 0;
+            cells[row][column] = filled;
+            cells[row][4 - column] = filled;
+        }
+    }
+
+    let cell_size = (size as f32 / 5.0).max(1.0);
+    let image = RgbaImage::from_fn(size, size, |x, y| {
+        let column = ((x as f32 / cell_size) as usize).min(4);
+        let row = ((y as f32 / cell_size) as usize).min(4);
+        if cells[row][column] {
+            colour
+        } else {
+            background
+        }
+    });
+    encode_png(image)
+}
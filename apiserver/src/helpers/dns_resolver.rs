@@ -0,0 +1,83 @@
+#[cfg(test)]
+use std::collections::HashMap;
+use std::net::SocketAddr;
+
+use hickory_resolver::{
+    config::{NameServerConfigGroup, ResolverConfig, ResolverOpts},
+    TokioAsyncResolver,
+};
+
+#[derive(Debug, Clone)]
+pub struct SrvRecord {
+    pub priority: u16,
+    pub weight: u16,
+    pub port: u16,
+    pub target: String,
+}
+
+// kept behind a trait so federation lookups can be pointed at a stub
+#[async_trait::async_trait]
+pub trait SrvResolver: Send + Sync {
+    async fn lookup_srv(&self, name: &str) -> Result<Vec<SrvRecord>, String>;
+}
+
+// answers from a fixed table so tests never touch dns, point DNS_NAMESERVER at a
+// local server to run without public dns
+#[cfg(test)]
+pub struct StaticSrvResolver {
+    pub records: HashMap<String, Vec<SrvRecord>>,
+}
+
+#[cfg(test)]
+#[async_trait::async_trait]
+impl SrvResolver for StaticSrvResolver {
+    async fn lookup_srv(&self, name: &str) -> Result<Vec<SrvRecord>, String> {
+        Ok(self.records.get(name).cloned().unwrap_or_default())
+    }
+}
+
+pub struct HickorySrvResolver {
+    resolver: TokioAsyncResolver,
+}
+
+impl HickorySrvResolver {
+    // uses the system configuration unless a nameserver address is given
+    pub fn new(nameserver: Option<SocketAddr>) -> Result<Self, String> {
+        let resolver = match nameserver {
+            Some(address) => TokioAsyncResolver::tokio(
+                ResolverConfig::from_parts(
+                    None,
+                    vec![],
+                    NameServerConfigGroup::from_ips_clear(&[address.ip()], address.port(), true),
+                ),
+                ResolverOpts::default(),
+            ),
+            None => match TokioAsyncResolver::tokio_from_system_conf() {
+                Ok(resolver) => resolver,
+                Err(_) => return Err("Issue reading the system dns configuration".to_string()),
+            },
+        };
+        Ok(HickorySrvResolver { resolver })
+    }
+}
+
+#[async_trait::async_trait]
+impl SrvResolver for HickorySrvResolver {
+    async fn lookup_srv(&self, name: &str) -> Result<Vec<SrvRecord>, String> {
+        match self.resolver.srv_lookup(name).await {
+            Ok(lookup) => Ok(lookup
+                .iter()
+                .map(|record| SrvRecord {
+                    priority: record.priority(),
+                    weight: record.weight(),
+                    port: record.port(),
+                    target: record.target().to_utf8().trim_end_matches('.').to_string(),
+                })
+                .collect()),
+            Err(err) => match err.kind() {
+                hickory_resolver::error::ResolveErrorKind::NoRecordsFound { .. } => Ok(Vec::new()),
+                _ => Err("Issue looking up srv records".to_string()),
+            },
+        }
+    }
+}
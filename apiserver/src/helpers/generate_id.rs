@@ -20,4 +20,9 @@ impl Snowflake {
         self.counter = (self.counter + 1) % 8192;
         Ok(shifted_epoch | shifted_machine_id | current_counter)
     }
+
+    // seconds since the unix epoch at which the id was generated
+    pub fn timestamp_of(id: i64) -> i64 {
+        id >> 23
+    }
 }
@@ -0,0 +1,26 @@
+use std::io::Cursor;
+
+use image::{DynamicImage, ImageFormat, Luma};
+use qrcode::QrCode;
+
+pub fn generate_qr_png(data: &str, size: u32) -> Result<Vec<u8>, String> {
+    let code_res = QrCode::new(data.as_bytes());
+    if code_res.is_err() {
+        return Err("Issue generating the qr code".to_string());
+    }
+
+    let image = code_res
+        .unwrap()
+        .render::<Luma<u8>>()
+        .min_dimensions(size, size)
+        .build();
+
+    let mut png_bytes = Cursor::new(Vec::new());
+    if DynamicImage::ImageLuma8(image)
+        .write_to(&mut png_bytes, ImageFormat::Png)
+        .is_err()
+    {
+        return Err("Issue encoding the qr code".to_string());
+    }
+    Ok(png_bytes.into_inner())
+}
@@ -0,0 +1,7 @@
+use rand::RngCore;
+
+pub fn generate_random_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bs58::encode(bytes).into_string()
+}
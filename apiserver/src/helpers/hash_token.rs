@@ -0,0 +1,6 @@
+use sha2::{Digest, Sha256};
+
+// opaque tokens are only ever stored as their sha256 digest
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
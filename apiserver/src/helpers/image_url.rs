@@ -0,0 +1,16 @@
+use cloudinary::transformation::{CropMode, Image, Transformations};
+
+pub fn image_url(cloud_name: &str, user_id: i64, profile_id: i64, size: Option<u32>) -> String {
+    let mut image = Image::new(
+        cloud_name.into(),
+        format!("gravatar/{}/{}", user_id, profile_id).into(),
+    );
+    if let Some(size) = size {
+        image = image.add_transformation(Transformations::Crop(CropMode::Fill {
+            width: size,
+            height: size,
+            gravity: None,
+        }));
+    }
+    image.build().to_string()
+}
@@ -0,0 +1,187 @@
+use actix_web::{
+    cookie::{Cookie, SameSite},
+    http::header,
+    HttpRequest, HttpResponse,
+};
+
+use crate::{helpers::csrf::CSRF_COOKIE, responses::general_error::GeneralError, AppState};
+
+pub const REFRESH_TOKEN_LIFETIME_SECS: i64 = 30 * 86400;
+pub const REFRESH_TOKEN_COOKIE_PATH: &str = "/api/v1/user/signin/refresh";
+
+#[derive(serde::Serialize)]
+pub struct LoginResponse {
+    #[serde(rename = "accessToken")]
+    access_token: String,
+    #[serde(rename = "refreshToken")]
+    refresh_token: String,
+    #[serde(rename = "userId")]
+    user_id: i64,
+    // clients on another origin can not read the cookie, they send this back instead
+    #[serde(rename = "csrfToken")]
+    csrf_token: String,
+}
+
+pub struct IssuedTokens {
+    pub user_id: i64,
+    pub access_token: String,
+    pub refresh_token: String,
+}
+
+// issues an access token plus a refresh token belonging to the given session
+pub async fn issue_tokens(
+    app_state: &AppState,
+    user_id: i64,
+    email: &str,
+    session_id: i64,
+) -> Result<IssuedTokens, String> {
+    let access_token_res = crate::helpers::generate_token::generate_token(
+        email,
+        user_id,
+        session_id,
+        &app_state.signing_keys,
+        app_state.access_token_lifetime_secs,
+    );
+    if access_token_res.is_err() {
+        return Err("Issue generating the token".to_string());
+    }
+    let access_token = access_token_res.unwrap();
+
+    let refresh_token_id_res = app_state.snow_flake.lock().unwrap().generate_id();
+    if refresh_token_id_res.is_err() {
+        return Err("Issue generating the id".to_string());
+    }
+    let now = crate::helpers::current_time::current_time_secs()?;
+    let refresh_token = crate::helpers::generate_random_token::generate_random_token();
+
+    let insert_res = sqlx::query(
+        "insert into refresh_tokens(id, user_id, session_id, token_hash, created_at, expires_at)
+        values($1, $2, $3, $4, $5, $6)",
+    )
+    .bind(refresh_token_id_res.unwrap() as i64)
+    .bind(user_id)
+    .bind(session_id)
+    .bind(crate::helpers::hash_token::hash_token(&refresh_token))
+    .bind(now)
+    .bind(now + REFRESH_TOKEN_LIFETIME_SECS)
+    .execute(&app_state.database_connection_pool)
+    .await;
+
+    if insert_res.is_err() {
+        return Err("Issue talking to the database".to_string());
+    }
+
+    app_state
+        .session_store
+        .store(
+            session_id,
+            &access_token,
+            now + app_state.access_token_lifetime_secs,
+        )
+        .await?;
+
+    Ok(IssuedTokens {
+        user_id,
+        access_token,
+        refresh_token,
+    })
+}
+
+fn removal_cookie(name: &str, path: &str) -> Cookie<'static> {
+    let mut cookie = Cookie::build(name.to_string(), "")
+        .path(path.to_string())
+        .secure(true)
+        .http_only(name != CSRF_COOKIE)
+        .same_site(SameSite::None)
+        .finish();
+    cookie.make_removal();
+    cookie
+}
+
+pub fn clear_tokens_response() -> HttpResponse {
+    HttpResponse::Ok()
+        .cookie(removal_cookie("accessToken", "/"))
+        // clients signed in before the identity moved into the token still carry this one
+        .cookie(removal_cookie("userId", "/"))
+        .cookie(removal_cookie("refreshToken", REFRESH_TOKEN_COOKIE_PATH))
+        .cookie(removal_cookie(CSRF_COOKIE, "/"))
+        .json(())
+}
+
+pub fn tokens_response(tokens: IssuedTokens) -> HttpResponse {
+    let cookie1 = Cookie::build("accessToken", tokens.access_token.clone())
+        .path("/")
+        .secure(true)
+        .http_only(true)
+        .same_site(SameSite::None)
+        .finish();
+
+    let cookie2 = Cookie::build("refreshToken", tokens.refresh_token.clone())
+        .path(REFRESH_TOKEN_COOKIE_PATH)
+        .secure(true)
+        .http_only(true)
+        .same_site(SameSite::None)
+        .finish();
+
+    // readable by scripts on our own origin for the double submit check
+    let csrf_token = crate::helpers::generate_random_token::generate_random_token();
+    let cookie3 = Cookie::build(CSRF_COOKIE, csrf_token.clone())
+        .path("/")
+        .secure(true)
+        .same_site(SameSite::None)
+        .finish();
+
+    HttpResponse::Ok()
+        .cookie(cookie1)
+        .cookie(cookie2)
+        .cookie(cookie3)
+        .json(LoginResponse {
+            user_id: tokens.user_id,
+            access_token: tokens.access_token,
+            refresh_token: tokens.refresh_token,
+            csrf_token,
+        })
+}
+
+// every sign in starts a new session with its own refresh token family
+pub async fn sign_in_response(
+    req: &HttpRequest,
+    app_state: &AppState,
+    user_id: i64,
+    email: &str,
+) -> HttpResponse {
+    let ip = app_state
+        .trusted_proxies
+        .client_ip(req.peer_addr(), req.headers());
+    let user_agent = req
+        .headers()
+        .get(header::USER_AGENT)
+        .and_then(|user_agent| user_agent.to_str().ok());
+    let session_id_res = crate::dbcalls::manage_sessions::create_session(
+        user_id,
+        user_agent,
+        Some(ip.as_str()),
+        app_state,
+    )
+    .await;
+    if let Err(err_string) = session_id_res {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: err_string,
+        });
+    }
+    crate::dbcalls::login_protection::note_login_source(
+        user_id,
+        email,
+        user_agent,
+        Some(ip.as_str()),
+        app_state,
+    )
+    .await;
+
+    match issue_tokens(app_state, user_id, email, session_id_res.unwrap()).await {
+        Err(err_string) => HttpResponse::InternalServerError().json(GeneralError {
+            message: err_string,
+        }),
+        Ok(tokens) => tokens_response(tokens),
+    }
+}
@@ -0,0 +1,54 @@
+use sha2::{Digest, Sha256};
+
+// libravatar hashes the trimmed, lowercased address
+pub fn email_sha256(email: &str) -> String {
+    hex::encode(Sha256::digest(email.trim().to_lowercase().as_bytes()))
+}
+
+// gravatar clients normalize the same way before taking the md5
+pub fn email_md5(email: &str) -> String {
+    hex::encode(md5::compute(email.trim().to_lowercase().as_bytes()).0)
+}
+
+// openid urls are hashed after lowercasing only the scheme and host
+pub fn openid_url_hash(url: &str) -> Result<String, String> {
+    let parsed_res = reqwest::Url::parse(url.trim());
+    if parsed_res.is_err() {
+        return Err("Invalid openid url".to_string());
+    }
+    let parsed = parsed_res.unwrap();
+    if parsed.scheme() != "http" && parsed.scheme() != "https" {
+        return Err("Invalid openid url".to_string());
+    }
+
+    let trimmed = url.trim();
+    let scheme_end = trimmed.find("://").unwrap() + 3;
+    let host_end = trimmed[scheme_end..]
+        .find(['/', '?', '#'])
+        .map(|index| index + scheme_end)
+        .unwrap_or(trimmed.len());
+    let normalized = format!(
+        "{}{}",
+        trimmed[..host_end].to_lowercase(),
+        &trimmed[host_end..]
+    );
+
+    Ok(hex::encode(Sha256::digest(normalized.as_bytes())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashes_ignore_case_and_surrounding_space() {
+        assert_eq!(
+            email_md5(" Ada@Example.COM "),
+            "3e3417d7ef77d5932a6734b916515ed5"
+        );
+        assert_eq!(
+            email_sha256(" Ada@Example.COM "),
+            "b5fc85e55755f9e0d030a10ab4429b6b2944855f9a0d60077fe832becbc41d72"
+        );
+    }
+}
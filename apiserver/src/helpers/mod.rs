@@ -1,3 +1,30 @@
+pub mod api_scopes;
+pub mod build_export;
+pub mod client_ip;
+pub mod cors;
+pub mod csrf;
+pub mod current_time;
+pub mod default_avatar;
+pub mod dns_resolver;
 pub mod generate_id;
+pub mod generate_qr;
+pub mod generate_random_token;
 pub mod generate_token;
+pub mod hash_token;
+pub mod image_url;
+pub mod issue_tokens;
+pub mod libravatar_hash;
+pub mod mailer;
+pub mod oauth;
+pub mod oidc_client;
+pub mod password;
+pub mod password_policy;
+pub mod periodic_cleanup;
+pub mod rate_limit;
+pub mod render_profile;
+pub mod session_store;
+pub mod signing_keys;
+pub mod totp;
 pub mod validate_token;
+pub mod webauthn;
+pub mod xmlrpc;
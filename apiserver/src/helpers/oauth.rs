@@ -0,0 +1,326 @@
+use std::net::IpAddr;
+
+use actix_web::{http::header, HttpResponse};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+use super::{api_scopes::OAUTH_SCOPES, render_profile::escape_xml, signing_keys::SigningKeys};
+
+pub const AUTHORIZATION_CODE_LIFETIME_SECS: i64 = 60;
+pub const OAUTH_ACCESS_TOKEN_LIFETIME_SECS: i64 = 3600;
+pub const OAUTH_REFRESH_TOKEN_LIFETIME_SECS: i64 = 30 * 86400;
+
+// schemes that run code or read local data in the browser, never a redirect target
+const FORBIDDEN_REDIRECT_SCHEMES: [&str; 6] =
+    ["javascript", "data", "vbscript", "file", "blob", "about"];
+
+// rfc 7636 s256, the only method we accept
+pub fn pkce_challenge(code_verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()))
+}
+
+pub fn verify_pkce(code_verifier: &str, code_challenge: &str) -> bool {
+    if code_verifier.len() < 43 || code_verifier.len() > 128 {
+        return false;
+    }
+    pkce_challenge(code_verifier) == code_challenge
+}
+
+pub fn parse_scopes(scope: &str) -> Result<Vec<String>, String> {
+    let mut scopes: Vec<String> = Vec::new();
+    for requested in scope.split_whitespace() {
+        if !OAUTH_SCOPES.contains(&requested) {
+            return Err(format!("Unknown scope {}", requested));
+        }
+        if !scopes.iter().any(|scope| scope == requested) {
+            scopes.push(requested.to_string());
+        }
+    }
+    if scopes.is_empty() {
+        return Err("At least one scope is required".to_string());
+    }
+    Ok(scopes)
+}
+
+// OAUTH_REDIRECT_SCHEMES is a comma separated list of custom schemes native apps may register
+pub fn parse_redirect_schemes(raw: &str) -> Result<Vec<String>, String> {
+    let mut schemes: Vec<String> = Vec::new();
+    for scheme in raw.split(',').map(|scheme| scheme.trim().to_lowercase()) {
+        if scheme.is_empty() {
+            continue;
+        }
+        let well_formed = scheme.starts_with(|character: char| character.is_ascii_alphabetic())
+            && scheme.chars().all(|character| {
+                character.is_ascii_alphanumeric() || matches!(character, '+' | '-' | '.')
+            });
+        if !well_formed {
+            return Err(format!("{} is not a valid uri scheme", scheme));
+        }
+        if FORBIDDEN_REDIRECT_SCHEMES.contains(&scheme.as_str())
+            || scheme == "http"
+            || scheme == "https"
+        {
+            return Err(format!("{} cannot be allowed as a custom scheme", scheme));
+        }
+        schemes.push(scheme);
+    }
+    Ok(schemes)
+}
+
+// rfc 8252: https anywhere, plain http only to the loopback interface and
+// custom schemes only when the operator allowed them
+pub fn redirect_uri_allowed(redirect_uri: &str, custom_schemes: &[String]) -> bool {
+    let url = match reqwest::Url::parse(redirect_uri) {
+        Ok(url) => url,
+        Err(_) => return false,
+    };
+    match url.scheme() {
+        "https" => url.host_str().is_some(),
+        "http" => url.host_str().is_some_and(|host| {
+            host == "localhost"
+                || host
+                    .trim_start_matches('[')
+                    .trim_end_matches(']')
+                    .parse::<IpAddr>()
+                    .is_ok_and(|ip| ip.is_loopback())
+        }),
+        scheme => {
+            !FORBIDDEN_REDIRECT_SCHEMES.contains(&scheme)
+                && custom_schemes.iter().any(|allowed| allowed == scheme)
+        }
+    }
+}
+
+// sends the browser back to the client with the given query parameters
+pub fn redirect_to_client(redirect_uri: &str, params: &[(&str, &str)]) -> HttpResponse {
+    let mut location = match reqwest::Url::parse(redirect_uri) {
+        Ok(location) => location,
+        Err(_) => {
+            return HttpResponse::BadRequest()
+                .content_type("text/html; charset=utf-8")
+                .body(render_error_page("The redirect uri is not valid"))
+        }
+    };
+    location.query_pairs_mut().extend_pairs(params);
+    HttpResponse::Found()
+        .insert_header((header::LOCATION, location.to_string()))
+        .finish()
+}
+
+// rfc 6749 section 5.2 error body, token responses must never be cached
+pub fn token_error(
+    status: actix_web::http::StatusCode,
+    error: &str,
+    description: &str,
+) -> HttpResponse {
+    HttpResponse::build(status)
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .json(serde_json::json!({
+            "error": error,
+            "error_description": description,
+        }))
+}
+
+#[derive(serde::Serialize)]
+pub struct IdTokenClaims {
+    pub iss: String,
+    pub sub: String,
+    pub aud: String,
+    pub exp: i64,
+    pub iat: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+}
+
+// clients check the signature against /.well-known/jwks.json, with only the
+// shared secret configured they rely on oidc core 3.1.3.7 and the tls channel
+pub fn sign_id_token(claims: &IdTokenClaims, signing_keys: &SigningKeys) -> Result<String, String> {
+    match signing_keys.sign(claims) {
+        Ok(id_token) => Ok(id_token),
+        Err(_) => Err("Issue generating the id token".to_string()),
+    }
+}
+
+// the bare html shell of every page this server renders itself
+pub fn page(title: &str, content: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{}</title></head>\
+        <body>{}</body></html>",
+        escape_xml(title),
+        content
+    )
+}
+
+pub fn render_error_page(message: &str) -> String {
+    page(
+        "Authorization failed",
+        &format!(
+            "<h1>Authorization failed</h1><p>{}</p>",
+            escape_xml(message)
+        ),
+    )
+}
+
+fn describe_scope(scope: &str) -> &'static str {
+    match scope {
+        "openid" => "Know who you are",
+        "profile" => "Read your public profile",
+        "email" => "Read your email address",
+        "avatars:read" => "See your avatars",
+        "avatars:write" => "Upload and choose your avatars",
+        _ => "Unknown permission",
+    }
+}
+
+// the consent form posts every authorization parameter back unchanged
+pub fn render_consent_page(
+    client_name: &str,
+    scopes: &[String],
+    fields: &[(&str, &str)],
+) -> String {
+    let scope_items: String = scopes
+        .iter()
+        .map(|scope| format!("<li>{}</li>", describe_scope(scope)))
+        .collect();
+    let hidden_fields: String = fields
+        .iter()
+        .map(|(name, value)| {
+            format!(
+                "<input type=\"hidden\" name=\"{}\" value=\"{}\">",
+                escape_xml(name),
+                escape_xml(value)
+            )
+        })
+        .collect();
+    page(
+        "Authorize application",
+        &format!(
+            "<h1>{} wants to access your account</h1><p>It will be able to:</p><ul>{}</ul>\
+            <form method=\"post\" action=\"/oauth/authorize\">{}\
+            <button type=\"submit\" name=\"decision\" value=\"allow\">Allow</button>\
+            <button type=\"submit\" name=\"decision\" value=\"deny\">Deny</button></form>",
+            escape_xml(client_name),
+            scope_items,
+            hidden_fields
+        ),
+    )
+}
+
+pub struct ConsentRequest<'a> {
+    pub user_id: i64,
+    pub session_id: i64,
+    pub client_id: &'a str,
+    pub redirect_uri: Option<&'a str>,
+    pub scopes: &'a [String],
+}
+
+fn consent_mac(secret: &str, request: &ConsentRequest) -> Hmac<Sha256> {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac accepts keys of any length");
+    // a json array keeps the fields apart whatever characters they hold
+    let message = serde_json::json!([
+        "consent",
+        request.user_id,
+        request.session_id,
+        request.client_id,
+        request.redirect_uri,
+        request.scopes,
+    ]);
+    mac.update(message.to_string().as_bytes());
+    mac
+}
+
+// ties the consent form to the signed in user, the application, where the code
+// goes and what was shown, so other sites cannot post it or swap the scopes
+pub fn consent_token(secret: &str, request: &ConsentRequest) -> String {
+    URL_SAFE_NO_PAD.encode(consent_mac(secret, request).finalize().into_bytes())
+}
+
+pub fn verify_consent_token(secret: &str, request: &ConsentRequest, presented: &str) -> bool {
+    match URL_SAFE_NO_PAD.decode(presented) {
+        Ok(presented) => consent_mac(secret, request)
+            .verify_slice(&presented)
+            .is_ok(),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redirect_uris_need_https_or_loopback_http() {
+        let none: Vec<String> = Vec::new();
+        assert!(redirect_uri_allowed("https://app.example.com/cb", &none));
+        assert!(redirect_uri_allowed("http://127.0.0.1:5000/cb", &none));
+        assert!(redirect_uri_allowed("http://[::1]/cb", &none));
+        assert!(redirect_uri_allowed("http://localhost:3000/cb", &none));
+        assert!(!redirect_uri_allowed("http://app.example.com/cb", &none));
+        assert!(!redirect_uri_allowed("javascript:alert(1)", &none));
+        assert!(!redirect_uri_allowed("com.example.app:/cb", &none));
+    }
+
+    #[test]
+    fn custom_schemes_must_be_allowed_explicitly() {
+        let schemes = parse_redirect_schemes(" com.example.app , Myapp ").unwrap();
+        assert_eq!(schemes, vec!["com.example.app", "myapp"]);
+        assert!(redirect_uri_allowed("com.example.app:/cb", &schemes));
+        assert!(redirect_uri_allowed("myapp://cb", &schemes));
+        assert!(!redirect_uri_allowed("other.app:/cb", &schemes));
+        assert!(parse_redirect_schemes("javascript").is_err());
+        assert!(parse_redirect_schemes("https").is_err());
+        assert!(parse_redirect_schemes("1bad").is_err());
+        assert!(parse_redirect_schemes("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn consent_tokens_are_bound_to_the_whole_request() {
+        let scopes = vec!["openid".to_string(), "email".to_string()];
+        let request = ConsentRequest {
+            user_id: 1,
+            session_id: 2,
+            client_id: "client",
+            redirect_uri: Some("https://app.example.com/cb"),
+            scopes: &scopes,
+        };
+        let token = consent_token("secret", &request);
+        assert!(verify_consent_token("secret", &request, &token));
+        assert!(!verify_consent_token("other secret", &request, &token));
+        assert!(!verify_consent_token("secret", &request, "not base64!"));
+
+        let wider_scopes = vec!["openid".to_string(), "avatars:write".to_string()];
+        let changed = [
+            ConsentRequest {
+                user_id: 3,
+                ..request
+            },
+            ConsentRequest {
+                session_id: 4,
+                ..request
+            },
+            ConsentRequest {
+                client_id: "other",
+                ..request
+            },
+            ConsentRequest {
+                redirect_uri: Some("https://evil.example.com/cb"),
+                ..request
+            },
+            ConsentRequest {
+                redirect_uri: None,
+                ..request
+            },
+            ConsentRequest {
+                scopes: &wider_scopes,
+                ..request
+            },
+        ];
+        for changed in changed {
+            assert!(!verify_consent_token("secret", &changed, &token));
+        }
+    }
+}
@@ -0,0 +1,481 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use jsonwebtoken::{
+    decode, decode_header,
+    jwk::{Jwk, JwkSet},
+    Algorithm, DecodingKey, Validation,
+};
+
+// an external identity provider, configured through OIDC_PROVIDERS as a json array
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct OidcProvider {
+    pub name: String,
+    pub issuer: String,
+    #[serde(rename = "clientId")]
+    pub client_id: String,
+    #[serde(rename = "clientSecret")]
+    pub client_secret: Option<String>,
+    #[serde(default = "default_scopes")]
+    pub scopes: String,
+}
+
+fn default_scopes() -> String {
+    "openid email profile".to_string()
+}
+
+pub fn parse_providers(raw: &str) -> Result<Vec<OidcProvider>, String> {
+    let providers: Vec<OidcProvider> = match serde_json::from_str(raw) {
+        Ok(providers) => providers,
+        Err(err) => return Err(format!("Invalid oidc provider configuration: {}", err)),
+    };
+    for provider in &providers {
+        if provider.name.is_empty()
+            || !provider
+                .name
+                .chars()
+                .all(|character| character.is_ascii_alphanumeric() || character == '-')
+        {
+            return Err(format!(
+                "Oidc provider name {:?} should be letters, digits and dashes",
+                provider.name
+            ));
+        }
+    }
+    Ok(providers)
+}
+
+// discovery documents and key sets rarely change, an unknown kid still refetches the keys
+const PROVIDER_CACHE_TTL: Duration = Duration::from_secs(3600);
+
+struct CacheEntry<T> {
+    fetched_at: Instant,
+    value: Arc<T>,
+}
+
+#[derive(Default)]
+pub struct ProviderCache {
+    metadata: Mutex<HashMap<String, CacheEntry<ProviderMetadata>>>,
+    jwks: Mutex<HashMap<String, CacheEntry<JwkSet>>>,
+}
+
+fn cached<T>(entries: &Mutex<HashMap<String, CacheEntry<T>>>, key: &str) -> Option<Arc<T>> {
+    entries
+        .lock()
+        .unwrap()
+        .get(key)
+        .filter(|entry| entry.fetched_at.elapsed() < PROVIDER_CACHE_TTL)
+        .map(|entry| entry.value.clone())
+}
+
+fn remember<T>(entries: &Mutex<HashMap<String, CacheEntry<T>>>, key: &str, value: T) -> Arc<T> {
+    let value = Arc::new(value);
+    entries.lock().unwrap().insert(
+        key.to_string(),
+        CacheEntry {
+            fetched_at: Instant::now(),
+            value: value.clone(),
+        },
+    );
+    value
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct ProviderMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+}
+
+#[derive(serde::Deserialize)]
+struct TokenEndpointResponse {
+    id_token: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct ExternalClaims {
+    pub iss: String,
+    pub sub: String,
+    pub email: Option<String>,
+    // some providers send this as the string "true"
+    pub email_verified: Option<serde_json::Value>,
+    pub nonce: Option<String>,
+}
+
+impl ExternalClaims {
+    pub fn verified_email(&self) -> Option<&str> {
+        let verified = match &self.email_verified {
+            Some(serde_json::Value::Bool(verified)) => *verified,
+            Some(serde_json::Value::String(verified)) => verified == "true",
+            _ => false,
+        };
+        if verified {
+            self.email.as_deref()
+        } else {
+            None
+        }
+    }
+}
+
+pub async fn discover(
+    http_client: &reqwest::Client,
+    cache: &ProviderCache,
+    provider: &OidcProvider,
+) -> Result<Arc<ProviderMetadata>, String> {
+    if let Some(metadata) = cached(&cache.metadata, &provider.issuer) {
+        return Ok(metadata);
+    }
+    let discovery_url = format!(
+        "{}/.well-known/openid-configuration",
+        provider.issuer.trim_end_matches('/')
+    );
+    let response = match http_client.get(&discovery_url).send().await {
+        Ok(response) if response.status().is_success() => response,
+        _ => return Err("Issue reaching the identity provider".to_string()),
+    };
+    let metadata: ProviderMetadata = match response.json().await {
+        Ok(metadata) => metadata,
+        Err(_) => return Err("Invalid identity provider metadata".to_string()),
+    };
+    // openid connect discovery 1.0 section 4.3
+    if metadata.issuer != provider.issuer {
+        return Err("Identity provider issuer does not match the configuration".to_string());
+    }
+    Ok(remember(&cache.metadata, &provider.issuer, metadata))
+}
+
+pub async fn exchange_code(
+    http_client: &reqwest::Client,
+    provider: &OidcProvider,
+    metadata: &ProviderMetadata,
+    code: &str,
+    redirect_uri: &str,
+    code_verifier: &str,
+) -> Result<String, String> {
+    let mut form = vec![
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("redirect_uri", redirect_uri),
+        ("client_id", provider.client_id.as_str()),
+        ("code_verifier", code_verifier),
+    ];
+    if let Some(client_secret) = &provider.client_secret {
+        form.push(("client_secret", client_secret));
+    }
+
+    let response = match http_client
+        .post(&metadata.token_endpoint)
+        .form(&form)
+        .send()
+        .await
+    {
+        Ok(response) if response.status().is_success() => response,
+        _ => return Err("The identity provider rejected the authorization code".to_string()),
+    };
+    match response.json::<TokenEndpointResponse>().await {
+        Ok(TokenEndpointResponse {
+            id_token: Some(id_token),
+        }) => Ok(id_token),
+        _ => Err("The identity provider did not return an id token".to_string()),
+    }
+}
+
+async fn fetch_jwks(
+    http_client: &reqwest::Client,
+    cache: &ProviderCache,
+    jwks_uri: &str,
+) -> Result<Arc<JwkSet>, String> {
+    let response = match http_client.get(jwks_uri).send().await {
+        Ok(response) if response.status().is_success() => response,
+        Ok(_) => return Err("The identity provider did not return its keys".to_string()),
+        Err(_) => return Err("Issue reaching the identity provider".to_string()),
+    };
+    match response.json::<JwkSet>().await {
+        Ok(jwks) => Ok(remember(&cache.jwks, jwks_uri, jwks)),
+        Err(_) => Err("Invalid identity provider keys".to_string()),
+    }
+}
+
+fn find_key(jwks: &JwkSet, kid: Option<&str>) -> Option<Jwk> {
+    match kid {
+        Some(kid) => jwks.find(kid).cloned(),
+        None if jwks.keys.len() == 1 => jwks.keys.first().cloned(),
+        None => None,
+    }
+}
+
+async fn signing_key(
+    http_client: &reqwest::Client,
+    cache: &ProviderCache,
+    metadata: &ProviderMetadata,
+    kid: Option<&str>,
+) -> Result<Jwk, String> {
+    if let Some(key) = cached(&cache.jwks, &metadata.jwks_uri)
+        .as_deref()
+        .and_then(|jwks| find_key(jwks, kid))
+    {
+        return Ok(key);
+    }
+    // a kid we have not seen usually means the provider rotated its keys
+    let jwks = fetch_jwks(http_client, cache, &metadata.jwks_uri).await?;
+    match find_key(&jwks, kid) {
+        Some(key) => Ok(key),
+        None => Err("No matching identity provider key".to_string()),
+    }
+}
+
+// checks signature, issuer, audience, expiry and the nonce we sent
+pub async fn validate_id_token(
+    http_client: &reqwest::Client,
+    cache: &ProviderCache,
+    provider: &OidcProvider,
+    metadata: &ProviderMetadata,
+    id_token: &str,
+    nonce: &str,
+) -> Result<ExternalClaims, String> {
+    let header = match decode_header(id_token) {
+        Ok(header) => header,
+        Err(_) => return Err("Malformed id token".to_string()),
+    };
+
+    let key = match header.alg {
+        Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => match &provider.client_secret {
+            Some(client_secret) => DecodingKey::from_secret(client_secret.as_bytes()),
+            None => return Err("Unexpected id token algorithm".to_string()),
+        },
+        _ => {
+            let jwk = signing_key(http_client, cache, metadata, header.kid.as_deref()).await?;
+            match DecodingKey::from_jwk(&jwk) {
+                Ok(key) => key,
+                Err(_) => return Err("Unsupported identity provider key".to_string()),
+            }
+        }
+    };
+
+    let mut validation = Validation::new(header.alg);
+    validation.set_issuer(&[&provider.issuer]);
+    validation.set_audience(&[&provider.client_id]);
+    validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+
+    let claims = match decode::<ExternalClaims>(id_token, &key, &validation) {
+        Ok(token_data) => token_data.claims,
+        Err(_) => return Err("The id token could not be validated".to_string()),
+    };
+    if claims.nonce.as_deref() != Some(nonce) {
+        return Err("The id token nonce does not match".to_string());
+    }
+    Ok(claims)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use actix_web::{http::StatusCode, web, App, HttpResponse, HttpServer};
+
+    use super::*;
+    use crate::helpers::signing_keys::SigningKeys;
+
+    // a tiny identity provider serving discovery, keys and a token endpoint
+    struct MockIdp {
+        issuer: String,
+        signing_keys: SigningKeys,
+        jwks_status: StatusCode,
+        discovery_hits: AtomicUsize,
+        jwks_hits: AtomicUsize,
+    }
+
+    async fn discovery_document(idp: web::Data<MockIdp>) -> HttpResponse {
+        idp.discovery_hits.fetch_add(1, Ordering::SeqCst);
+        HttpResponse::Ok().json(serde_json::json!({
+            "issuer": idp.issuer,
+            "authorization_endpoint": format!("{}/authorize", idp.issuer),
+            "token_endpoint": format!("{}/token", idp.issuer),
+            "jwks_uri": format!("{}/jwks", idp.issuer),
+        }))
+    }
+
+    async fn key_set(idp: web::Data<MockIdp>) -> HttpResponse {
+        idp.jwks_hits.fetch_add(1, Ordering::SeqCst);
+        HttpResponse::build(idp.jwks_status).json(idp.signing_keys.jwks())
+    }
+
+    async fn token_endpoint(
+        idp: web::Data<MockIdp>,
+        form: web::Form<HashMap<String, String>>,
+    ) -> HttpResponse {
+        if form.get("code").map(String::as_str) != Some("good-code")
+            || form.get("code_verifier").map(String::as_str) != Some("verifier")
+        {
+            return HttpResponse::BadRequest().json(serde_json::json!({"error": "invalid_grant"}));
+        }
+        let now = crate::helpers::current_time::current_time_secs().unwrap();
+        let id_token = idp
+            .signing_keys
+            .sign(&serde_json::json!({
+                "iss": idp.issuer,
+                "sub": "external-user",
+                "aud": "client",
+                "exp": now + 300,
+                "iat": now,
+                "email": "ada@example.com",
+                "email_verified": "true",
+                "nonce": "expected-nonce",
+            }))
+            .unwrap();
+        HttpResponse::Ok().json(serde_json::json!({ "id_token": id_token }))
+    }
+
+    async fn start_idp(jwks_status: StatusCode) -> (Arc<MockIdp>, OidcProvider) {
+        let key_file = tempfile::NamedTempFile::new().unwrap();
+        let private_key = openssl::pkey::PKey::generate_ed25519().unwrap();
+        std::fs::write(
+            key_file.path(),
+            private_key.private_key_to_pem_pkcs8().unwrap(),
+        )
+        .unwrap();
+        let signing_keys = SigningKeys::from_config(
+            &serde_json::json!([{
+                "kid": "idp-key",
+                "alg": "EdDSA",
+                "privateKeyFile": key_file.path(),
+            }])
+            .to_string(),
+            "idp-key",
+        )
+        .unwrap();
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let issuer = format!("http://{}", listener.local_addr().unwrap());
+        let idp = Arc::new(MockIdp {
+            issuer: issuer.clone(),
+            signing_keys,
+            jwks_status,
+            discovery_hits: AtomicUsize::new(0),
+            jwks_hits: AtomicUsize::new(0),
+        });
+        let data = web::Data::from(idp.clone());
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(data.clone())
+                .route(
+                    "/.well-known/openid-configuration",
+                    web::get().to(discovery_document),
+                )
+                .route("/jwks", web::get().to(key_set))
+                .route("/token", web::post().to(token_endpoint))
+        })
+        .workers(1)
+        .disable_signals()
+        .listen(listener)
+        .unwrap()
+        .run();
+        actix_web::rt::spawn(server);
+
+        let provider = OidcProvider {
+            name: "mock".to_string(),
+            issuer,
+            client_id: "client".to_string(),
+            client_secret: None,
+            scopes: default_scopes(),
+        };
+        (idp, provider)
+    }
+
+    #[actix_web::test]
+    async fn signs_in_against_a_mock_provider() {
+        let (idp, provider) = start_idp(StatusCode::OK).await;
+        let http_client = reqwest::Client::new();
+        let cache = ProviderCache::default();
+
+        let metadata = discover(&http_client, &cache, &provider).await.unwrap();
+        assert!(exchange_code(
+            &http_client,
+            &provider,
+            &metadata,
+            "bad-code",
+            "https://app/cb",
+            "verifier"
+        )
+        .await
+        .is_err());
+        let id_token = exchange_code(
+            &http_client,
+            &provider,
+            &metadata,
+            "good-code",
+            "https://app/cb",
+            "verifier",
+        )
+        .await
+        .unwrap();
+
+        let claims = validate_id_token(
+            &http_client,
+            &cache,
+            &provider,
+            &metadata,
+            &id_token,
+            "expected-nonce",
+        )
+        .await
+        .unwrap();
+        assert_eq!(claims.sub, "external-user");
+        assert_eq!(claims.verified_email(), Some("ada@example.com"));
+
+        let wrong_nonce = validate_id_token(
+            &http_client,
+            &cache,
+            &provider,
+            &metadata,
+            &id_token,
+            "other-nonce",
+        )
+        .await;
+        assert!(wrong_nonce.is_err());
+
+        // both the discovery document and the keys came from the cache the second time
+        discover(&http_client, &cache, &provider).await.unwrap();
+        assert_eq!(idp.discovery_hits.load(Ordering::SeqCst), 1);
+        assert_eq!(idp.jwks_hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[actix_web::test]
+    async fn rejects_keys_served_with_an_error_status() {
+        let (idp, provider) = start_idp(StatusCode::INTERNAL_SERVER_ERROR).await;
+        let http_client = reqwest::Client::new();
+        let cache = ProviderCache::default();
+
+        let metadata = discover(&http_client, &cache, &provider).await.unwrap();
+        let id_token = exchange_code(
+            &http_client,
+            &provider,
+            &metadata,
+            "good-code",
+            "https://app/cb",
+            "verifier",
+        )
+        .await
+        .unwrap();
+        let validated = validate_id_token(
+            &http_client,
+            &cache,
+            &provider,
+            &metadata,
+            &id_token,
+            "expected-nonce",
+        )
+        .await;
+        assert_eq!(
+            validated.err().as_deref(),
+            Some("The identity provider did not return its keys")
+        );
+        // failures are not cached, the next login asks again
+        assert!(cache.jwks.lock().unwrap().is_empty());
+        assert_eq!(idp.jwks_hits.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,151 @@
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Algorithm, Argon2, Params, Version,
+};
+
+pub fn password_params(
+    memory_kib: u32,
+    iterations: u32,
+    parallelism: u32,
+) -> Result<Params, String> {
+    match Params::new(memory_kib, iterations, parallelism, None) {
+        Ok(params) => Ok(params),
+        Err(err) => Err(format!("Invalid argon2 parameters: {}", err)),
+    }
+}
+
+pub fn hash_password(password: &str, params: &Params) -> Result<String, String> {
+    let salt = SaltString::generate(&mut OsRng);
+    match Argon2::new(Algorithm::Argon2id, Version::V0x13, params.clone())
+        .hash_password(password.as_bytes(), &salt)
+    {
+        Ok(password_hash) => Ok(password_hash.to_string()),
+        Err(_) => Err("Issue hashing the password".to_string()),
+    }
+}
+
+// accounts from before argon2 still carry bcrypt hashes, both are accepted
+pub fn verify_password(password: &str, stored_hash: Option<&str>) -> bool {
+    let stored_hash = match stored_hash {
+        Some(stored_hash) => stored_hash,
+        // accounts created through single sign on have no password
+        None => return false,
+    };
+    if stored_hash.starts_with("$argon2") {
+        return match PasswordHash::new(stored_hash) {
+            // the parameters are read back from the hash itself
+            Ok(parsed_hash) => Argon2::default()
+                .verify_password(password.as_bytes(), &parsed_hash)
+                .is_ok(),
+            Err(_) => false,
+        };
+    }
+    bcrypt::verify(password, stored_hash).unwrap_or(false)
+}
+
+// unknown accounts and ones without a password are checked against a hash made
+// at startup, so a failed sign in takes as long whether or not the account exists
+pub fn verify_password_or_dummy(
+    password: &str,
+    stored_hash: Option<&str>,
+    dummy_hash: &str,
+) -> bool {
+    match stored_hash {
+        Some(stored_hash) => verify_password(password, Some(stored_hash)),
+        None => {
+            let _ = verify_password(password, Some(dummy_hash));
+            false
+        }
+    }
+}
+
+// bcrypt hashes and argon2 hashes made with older parameters get replaced on the next sign in
+pub fn needs_rehash(stored_hash: &str, params: &Params) -> bool {
+    let parsed_hash = match PasswordHash::new(stored_hash) {
+        Ok(parsed_hash) => parsed_hash,
+        Err(_) => return true,
+    };
+    if parsed_hash.algorithm != Algorithm::Argon2id.ident() {
+        return true;
+    }
+    match Params::try_from(&parsed_hash) {
+        Ok(stored_params) => {
+            stored_params.m_cost() != params.m_cost()
+                || stored_params.t_cost() != params.t_cost()
+                || stored_params.p_cost() != params.p_cost()
+        }
+        Err(_) => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // cheap parameters keep the tests fast, production ones come from the env
+    fn test_params() -> Params {
+        password_params(1024, 1, 1).unwrap()
+    }
+
+    #[test]
+    fn verifies_argon2_hashes() {
+        let stored_hash = hash_password("correct horse", &test_params()).unwrap();
+        assert!(stored_hash.starts_with("$argon2id$"));
+        assert!(verify_password("correct horse", Some(&stored_hash)));
+        assert!(!verify_password("wrong horse", Some(&stored_hash)));
+        assert!(!verify_password("correct horse", None));
+        assert!(!verify_password("correct horse", Some("$argon2id$garbage")));
+    }
+
+    #[test]
+    fn the_dummy_hash_never_signs_anyone_in() {
+        let dummy_hash = hash_password("correct horse", &test_params()).unwrap();
+        assert!(!verify_password_or_dummy(
+            "correct horse",
+            None,
+            &dummy_hash
+        ));
+        let stored_hash = hash_password("battery staple", &test_params()).unwrap();
+        assert!(verify_password_or_dummy(
+            "battery staple",
+            Some(&stored_hash),
+            &dummy_hash
+        ));
+        assert!(!verify_password_or_dummy(
+            "correct horse",
+            Some(&stored_hash),
+            &dummy_hash
+        ));
+    }
+
+    #[test]
+    fn verifies_legacy_bcrypt_hashes_and_asks_for_an_upgrade() {
+        let legacy_hash = bcrypt::hash("correct horse", 4).unwrap();
+        assert!(verify_password("correct horse", Some(&legacy_hash)));
+        assert!(!verify_password("wrong horse", Some(&legacy_hash)));
+        assert!(needs_rehash(&legacy_hash, &test_params()));
+
+        let upgraded_hash = hash_password("correct horse", &test_params()).unwrap();
+        assert!(!needs_rehash(&upgraded_hash, &test_params()));
+    }
+
+    #[test]
+    fn asks_for_a_rehash_when_the_parameters_change() {
+        let stored_hash = hash_password("correct horse", &test_params()).unwrap();
+        assert!(needs_rehash(
+            &stored_hash,
+            &password_params(2048, 1, 1).unwrap()
+        ));
+        assert!(needs_rehash(
+            &stored_hash,
+            &password_params(1024, 2, 1).unwrap()
+        ));
+        // argon2i is readable but not what we hash with any more
+        let argon2i_hash = Argon2::new(Algorithm::Argon2i, Version::V0x13, test_params())
+            .hash_password(b"correct horse", &SaltString::generate(&mut OsRng))
+            .unwrap()
+            .to_string();
+        assert!(verify_password("correct horse", Some(&argon2i_hash)));
+        assert!(needs_rehash(&argon2i_hash, &test_params()));
+    }
+}
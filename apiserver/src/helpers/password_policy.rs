@@ -0,0 +1,182 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use sha1::{Digest, Sha1};
+
+// one false alarm in a thousand keeps the filter around 1.8 bytes per password
+const FALSE_POSITIVE_RATE: f64 = 0.001;
+
+// a bloom filter over the sha1 of every password in a breach corpus, it can
+// wrongly flag a safe password now and then but never misses a listed one
+pub struct BreachedPasswords {
+    bits: Vec<u64>,
+    bit_count: u64,
+    hash_count: u64,
+    pub entries: u64,
+}
+
+// accepts the hibp download format of "SHA1:count" as well as plain passwords,
+// a plain password that happens to be 40 hex characters is read as a hash
+fn line_digest(line: &str) -> Option<[u8; 20]> {
+    let line = line.trim_end_matches(['\r', '\n']);
+    if line.is_empty() {
+        return None;
+    }
+    let candidate = line.split(':').next().unwrap_or(line);
+    if candidate.len() == 40 {
+        let mut digest = [0u8; 20];
+        if hex::decode_to_slice(candidate, &mut digest).is_ok() {
+            return Some(digest);
+        }
+    }
+    Some(Sha1::digest(line.as_bytes()).into())
+}
+
+impl BreachedPasswords {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let open = || match File::open(path) {
+            Ok(file) => Ok(BufReader::new(file)),
+            Err(err) => Err(format!("Issue opening {}: {}", path.display(), err)),
+        };
+
+        // the first pass only sizes the filter
+        let entries = open()?
+            .lines()
+            .map_while(Result::ok)
+            .filter(|line| !line.trim().is_empty())
+            .count()
+            .max(1) as f64;
+        let bit_count = ((-entries * FALSE_POSITIVE_RATE.ln()) / 2_f64.ln().powi(2))
+            .ceil()
+            .max(64.0) as u64;
+        let hash_count = ((bit_count as f64 / entries) * 2_f64.ln())
+            .round()
+            .clamp(1.0, 16.0) as u64;
+
+        let mut breached_passwords = BreachedPasswords {
+            bits: vec![0; bit_count.div_ceil(64) as usize],
+            bit_count,
+            hash_count,
+            entries: 0,
+        };
+        for line in open()?.lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(err) => return Err(format!("Issue reading {}: {}", path.display(), err)),
+            };
+            if let Some(digest) = line_digest(&line) {
+                breached_passwords.insert(&digest);
+            }
+        }
+        Ok(breached_passwords)
+    }
+
+    // double hashing, the sha1 is already uniform so its halves serve as the two hashes
+    fn bit_positions(&self, digest: &[u8; 20]) -> impl Iterator<Item = u64> + '_ {
+        let first = u64::from_be_bytes(digest[0..8].try_into().unwrap_or_default());
+        let second = u64::from_be_bytes(digest[8..16].try_into().unwrap_or_default()) | 1;
+        (0..self.hash_count)
+            .map(move |round| first.wrapping_add(round.wrapping_mul(second)) % self.bit_count)
+    }
+
+    fn insert(&mut self, digest: &[u8; 20]) {
+        let positions: Vec<u64> = self.bit_positions(digest).collect();
+        for position in positions {
+            self.bits[(position / 64) as usize] |= 1 << (position % 64);
+        }
+        self.entries += 1;
+    }
+
+    pub fn contains(&self, password: &str) -> bool {
+        let digest: [u8; 20] = Sha1::digest(password.as_bytes()).into();
+        self.bit_positions(&digest)
+            .all(|position| self.bits[(position / 64) as usize] & (1 << (position % 64)) != 0)
+    }
+}
+
+// every problem with a new password, worded so the user knows what to change
+pub fn password_problems(
+    password: &str,
+    email: &str,
+    breached_passwords: Option<&BreachedPasswords>,
+    min_score: u8,
+) -> Vec<String> {
+    let mut problems: Vec<String> = Vec::new();
+
+    if breached_passwords.is_some_and(|breached_passwords| breached_passwords.contains(password)) {
+        problems.push(
+            "This password has appeared in a data breach, choose one you have not used elsewhere"
+                .to_string(),
+        );
+    }
+
+    let local_part = email.split('@').next().unwrap_or(email);
+    let estimate = zxcvbn::zxcvbn(password, &[email, local_part]);
+    if u8::from(estimate.score()) < min_score {
+        problems.push("Password is too easy to guess".to_string());
+        if let Some(feedback) = estimate.feedback() {
+            if let Some(warning) = feedback.warning() {
+                problems.push(warning.to_string());
+            }
+            for suggestion in feedback.suggestions() {
+                problems.push(suggestion.to_string());
+            }
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    fn corpus(lines: &str) -> BreachedPasswords {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(lines.as_bytes()).unwrap();
+        BreachedPasswords::load(file.path()).unwrap()
+    }
+
+    #[test]
+    fn reads_plain_and_hibp_lines() {
+        // sha1 of "password1" in the hibp download format
+        let breached_passwords = corpus(
+            "hunter2\r\n\nE38AD214943DAAD1D64C102FAEC29DE4AFE9DA3D:2413945\ncorrect horse\n",
+        );
+        assert_eq!(breached_passwords.entries, 3);
+        assert!(breached_passwords.contains("hunter2"));
+        assert!(breached_passwords.contains("password1"));
+        assert!(breached_passwords.contains("correct horse"));
+        assert!(!breached_passwords.contains("Hunter2"));
+        assert!(!breached_passwords.contains("tr0ub4dor&3 staple"));
+    }
+
+    #[test]
+    fn a_missing_corpus_is_an_error() {
+        assert!(BreachedPasswords::load(Path::new("/nonexistent/breached.txt")).is_err());
+    }
+
+    #[test]
+    fn lists_every_problem_with_a_password() {
+        let breached_passwords = corpus("hunter2\n");
+        let problems =
+            password_problems("hunter2", "ada@example.com", Some(&breached_passwords), 3);
+        assert!(problems[0].contains("data breach"));
+        assert!(problems.contains(&"Password is too easy to guess".to_string()));
+
+        // the email is part of the dictionary, so it is no help as a password
+        assert!(!password_problems("ada@example.com", "ada@example.com", None, 3).is_empty());
+
+        assert!(password_problems(
+            "quietly-orbiting-mango-lantern-47",
+            "ada@example.com",
+            Some(&breached_passwords),
+            3
+        )
+        .is_empty());
+    }
+}
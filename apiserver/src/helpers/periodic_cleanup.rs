@@ -0,0 +1,50 @@
+use std::time::Duration;
+
+use log::{error, info};
+use sqlx::{Pool, Postgres};
+
+const CLEANUP_INTERVAL_SECS: u64 = 3600;
+
+// housekeeping that should not wait for a request to trip over stale rows
+pub fn spawn_periodic_cleanup(pool: Pool<Postgres>) {
+    actix_web::rt::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(CLEANUP_INTERVAL_SECS));
+        loop {
+            interval.tick().await;
+            match super::build_export::remove_expired_exports(&pool).await {
+                Ok(0) => {}
+                Ok(removed) => info!("Removed {} expired export archives", removed),
+                Err(err_string) => error!("Issue removing expired exports: {}", err_string),
+            }
+            match super::build_export::fail_stale_exports(&pool, None).await {
+                Ok(0) => {}
+                Ok(failed) => info!("Marked {} abandoned exports as failed", failed),
+                Err(err_string) => error!("Issue failing abandoned exports: {}", err_string),
+            }
+            if let Err(err_string) = remove_expired_login_attempts(&pool).await {
+                error!("Issue removing expired login attempts: {}", err_string);
+            }
+        }
+    });
+}
+
+// abandoned single sign on attempts, link confirmations and sign in links nobody opened
+async fn remove_expired_login_attempts(pool: &Pool<Postgres>) -> Result<(), String> {
+    let now = super::current_time::current_time_secs()?;
+    for statement in [
+        "delete from oidc_logins where expires_at<=$1",
+        "delete from identity_link_requests where expires_at<=$1",
+        "delete from magic_links where expires_at<=$1",
+        "delete from decoy_locks where locked_until<=$1",
+    ] {
+        if sqlx::query(statement)
+            .bind(now)
+            .execute(pool)
+            .await
+            .is_err()
+        {
+            return Err("Issue talking to the database".to_string());
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,254 @@
+use std::collections::HashMap;
+
+use rand::Rng;
+
+// what a limit is counted against
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RateLimitKey {
+    Ip,
+    // falls back to the ip on routes where nobody is signed in
+    User,
+    // the email field of a json body or the xml-rpc user hash, falls back to the ip
+    Email,
+}
+
+#[derive(Debug, Clone)]
+pub struct RateLimitPolicy {
+    pub name: &'static str,
+    pub key: RateLimitKey,
+    pub limit: u32,
+    pub window_secs: u64,
+}
+
+impl RateLimitPolicy {
+    fn new(name: &'static str, key: RateLimitKey, limit: u32, window_secs: u64) -> Self {
+        RateLimitPolicy {
+            name,
+            key,
+            limit,
+            window_secs,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RateLimitPolicies {
+    pub signin_ip: RateLimitPolicy,
+    pub signin_email: RateLimitPolicy,
+    pub signup_ip: RateLimitPolicy,
+    pub second_factor_ip: RateLimitPolicy,
+    pub public_ip: RateLimitPolicy,
+    pub api_user: RateLimitPolicy,
+    // refresh and oauth token exchanges
+    pub token_ip: RateLimitPolicy,
+    // unlock and export download links carrying a secret token
+    pub link_ip: RateLimitPolicy,
+    // single sign on, each login reaches out to the identity provider
+    pub oidc_ip: RateLimitPolicy,
+}
+
+#[derive(serde::Deserialize)]
+struct PolicyOverride {
+    limit: u32,
+    #[serde(rename = "windowSecs")]
+    window_secs: u64,
+}
+
+impl RateLimitPolicies {
+    // RATE_LIMITS is a json object of policy name to {"limit", "windowSecs"}
+    pub fn from_overrides(raw: &str) -> Result<Self, String> {
+        let mut policies = RateLimitPolicies {
+            signin_ip: RateLimitPolicy::new("signin-ip", RateLimitKey::Ip, 30, 60),
+            signin_email: RateLimitPolicy::new("signin-email", RateLimitKey::Email, 10, 300),
+            signup_ip: RateLimitPolicy::new("signup-ip", RateLimitKey::Ip, 10, 3600),
+            second_factor_ip: RateLimitPolicy::new("second-factor-ip", RateLimitKey::Ip, 10, 60),
+            public_ip: RateLimitPolicy::new("public-ip", RateLimitKey::Ip, 600, 60),
+            api_user: RateLimitPolicy::new("api-user", RateLimitKey::User, 300, 60),
+            token_ip: RateLimitPolicy::new("token-ip", RateLimitKey::Ip, 60, 60),
+            link_ip: RateLimitPolicy::new("link-ip", RateLimitKey::Ip, 20, 60),
+            oidc_ip: RateLimitPolicy::new("oidc-ip", RateLimitKey::Ip, 30, 60),
+        };
+
+        let overrides: HashMap<String, PolicyOverride> = match serde_json::from_str(raw) {
+            Ok(overrides) => overrides,
+            Err(err) => return Err(format!("Invalid rate limit configuration: {}", err)),
+        };
+        for (name, policy_override) in overrides {
+            if policy_override.limit == 0 || policy_override.window_secs == 0 {
+                return Err(format!(
+                    "Rate limit {:?} needs a limit and window above zero",
+                    name
+                ));
+            }
+            let policy = match policies.by_name(&name) {
+                Some(policy) => policy,
+                None => return Err(format!("Unknown rate limit {:?}", name)),
+            };
+            policy.limit = policy_override.limit;
+            policy.window_secs = policy_override.window_secs;
+        }
+        Ok(policies)
+    }
+
+    fn by_name(&mut self, name: &str) -> Option<&mut RateLimitPolicy> {
+        [
+            &mut self.signin_ip,
+            &mut self.signin_email,
+            &mut self.signup_ip,
+            &mut self.second_factor_ip,
+            &mut self.public_ip,
+            &mut self.api_user,
+            &mut self.token_ip,
+            &mut self.link_ip,
+            &mut self.oidc_ip,
+        ]
+        .into_iter()
+        .find(|policy| policy.name == name)
+    }
+}
+
+pub struct RateLimitDecision {
+    pub allowed: bool,
+    pub limit: u32,
+    pub remaining: u32,
+    // seconds until the oldest counted request leaves the window
+    pub reset_secs: u64,
+}
+
+// sliding window log, each request is a member of a sorted set scored by its time
+const SLIDING_WINDOW_SCRIPT: &str = r"
+local now = tonumber(ARGV[1])
+local window = tonumber(ARGV[2])
+local limit = tonumber(ARGV[3])
+redis.call('ZREMRANGEBYSCORE', KEYS[1], '-inf', now - window)
+local count = redis.call('ZCARD', KEYS[1])
+local allowed = 0
+if count < limit then
+    redis.call('ZADD', KEYS[1], now, ARGV[4])
+    redis.call('PEXPIRE', KEYS[1], window)
+    count = count + 1
+    allowed = 1
+end
+local oldest = redis.call('ZRANGE', KEYS[1], 0, 0, 'WITHSCORES')
+local reset = window
+if oldest[2] then
+    reset = tonumber(oldest[2]) + window - now
+end
+return {allowed, limit - count, reset}
+";
+
+pub fn check_rate_limit(
+    pool: &r2d2::Pool<redis::Client>,
+    policy: &RateLimitPolicy,
+    subject: &str,
+) -> Result<RateLimitDecision, String> {
+    let now_millis = match std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH) {
+        Ok(now) => now.as_millis() as u64,
+        Err(_) => return Err("Issue getting the time".to_string()),
+    };
+    let window_millis = policy.window_secs * 1000;
+    // two requests in the same millisecond still need distinct members
+    let member = format!("{}-{}", now_millis, rand::thread_rng().gen::<u32>());
+
+    let mut redis_conn = match pool.get() {
+        Ok(redis_conn) => redis_conn,
+        Err(_) => return Err("Issue talking to redis".to_string()),
+    };
+    let result_res: Result<(i64, i64, i64), _> = redis::Script::new(SLIDING_WINDOW_SCRIPT)
+        .key(format!("ratelimit:{}:{}", policy.name, subject))
+        .arg(now_millis)
+        .arg(window_millis)
+        .arg(policy.limit)
+        .arg(member)
+        .invoke(&mut *redis_conn);
+    match result_res {
+        Ok(result) => Ok(decision_from_script(policy, result)),
+        Err(_) => Err("Issue talking to redis".to_string()),
+    }
+}
+
+fn decision_from_script(
+    policy: &RateLimitPolicy,
+    (allowed, remaining, reset_millis): (i64, i64, i64),
+) -> RateLimitDecision {
+    RateLimitDecision {
+        allowed: allowed == 1,
+        limit: policy.limit,
+        remaining: remaining.max(0) as u32,
+        // round up so clients never retry a moment too early
+        reset_secs: (reset_millis.max(0) as u64).div_ceil(1000),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(limit: u32, window_secs: u64) -> RateLimitPolicy {
+        RateLimitPolicy::new("test", RateLimitKey::Ip, limit, window_secs)
+    }
+
+    #[test]
+    fn script_results_round_the_reset_up() {
+        let decision = decision_from_script(&policy(5, 60), (1, 3, 59_001));
+        assert!(decision.allowed);
+        assert_eq!(decision.remaining, 3);
+        assert_eq!(decision.reset_secs, 60);
+
+        let decision = decision_from_script(&policy(5, 60), (0, -1, -5));
+        assert!(!decision.allowed);
+        assert_eq!(decision.remaining, 0);
+        assert_eq!(decision.reset_secs, 0);
+    }
+
+    #[test]
+    fn overrides_only_touch_known_policies() {
+        let policies = RateLimitPolicies::from_overrides(
+            r#"{"signin-email": {"limit": 5, "windowSecs": 120}}"#,
+        )
+        .unwrap();
+        assert_eq!(policies.signin_email.limit, 5);
+        assert_eq!(policies.signin_email.window_secs, 120);
+        assert_eq!(policies.signin_ip.limit, 30);
+        assert!(
+            RateLimitPolicies::from_overrides(r#"{"nope": {"limit": 1, "windowSecs": 1}}"#)
+                .is_err()
+        );
+        assert!(RateLimitPolicies::from_overrides(
+            r#"{"signin-ip": {"limit": 0, "windowSecs": 60}}"#
+        )
+        .is_err());
+    }
+
+    // runs the lua script against a real server, run with REDIS_URL set and --ignored
+    #[test]
+    #[ignore = "needs a redis server at REDIS_URL"]
+    fn sliding_window_limits_and_resets() {
+        let redis_url =
+            std::env::var("REDIS_URL").expect("REDIS_URL should point at a redis server");
+        let pool = r2d2::Pool::builder()
+            .max_size(1)
+            .build(redis::Client::open(redis_url).unwrap())
+            .unwrap();
+        let subject = format!("test-{}", rand::thread_rng().gen::<u64>());
+        let short = policy(2, 1);
+
+        let first = check_rate_limit(&pool, &short, &subject).unwrap();
+        assert!(first.allowed);
+        assert_eq!(first.remaining, 1);
+        assert_eq!(first.reset_secs, 1);
+        assert!(check_rate_limit(&pool, &short, &subject).unwrap().allowed);
+
+        let refused = check_rate_limit(&pool, &short, &subject).unwrap();
+        assert!(!refused.allowed);
+        assert_eq!(refused.remaining, 0);
+        // this is what goes out as retry-after
+        assert_eq!(refused.reset_secs, 1);
+
+        // once the window slides past the first requests they stop counting
+        std::thread::sleep(std::time::Duration::from_millis(1100));
+        let after_reset = check_rate_limit(&pool, &short, &subject).unwrap();
+        assert!(after_reset.allowed);
+        assert_eq!(after_reset.remaining, 1);
+    }
+}
@@ -0,0 +1,398 @@
+use crate::models::public_profile_model::VisibleProfile;
+
+// `/{hash}` alone answers with the avatar, the page people open lives under .html
+pub fn profile_url(public_url: &str, hash: &str) -> String {
+    format!("{}/{}.html", public_url, hash)
+}
+
+// the public view of a profile as served on the hash routes
+pub struct RenderableProfile {
+    pub hash: String,
+    pub profile_url: String,
+    pub thumbnail_url: Option<String>,
+    pub details: VisibleProfile,
+}
+
+#[derive(serde::Serialize)]
+struct GravatarPhoto {
+    value: String,
+    #[serde(rename = "type")]
+    photo_type: String,
+}
+
+#[derive(serde::Serialize)]
+struct GravatarName {
+    formatted: String,
+}
+
+#[derive(serde::Serialize)]
+struct GravatarUrl {
+    value: String,
+    title: String,
+}
+
+#[derive(serde::Serialize)]
+struct GravatarEntry {
+    id: String,
+    hash: String,
+    #[serde(rename = "requestHash")]
+    request_hash: String,
+    #[serde(rename = "profileUrl")]
+    profile_url: String,
+    #[serde(rename = "thumbnailUrl", skip_serializing_if = "Option::is_none")]
+    thumbnail_url: Option<String>,
+    photos: Vec<GravatarPhoto>,
+    #[serde(rename = "displayName", skip_serializing_if = "Option::is_none")]
+    display_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<GravatarName>,
+    #[serde(rename = "aboutMe", skip_serializing_if = "Option::is_none")]
+    about_me: Option<String>,
+    #[serde(rename = "currentLocation", skip_serializing_if = "Option::is_none")]
+    current_location: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pronouns: Option<String>,
+    #[serde(rename = "job_title", skip_serializing_if = "Option::is_none")]
+    job_title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    company: Option<String>,
+    urls: Vec<GravatarUrl>,
+}
+
+#[derive(serde::Serialize)]
+struct GravatarResponse {
+    entry: Vec<GravatarEntry>,
+}
+
+pub fn render_json(profile: RenderableProfile) -> Result<String, String> {
+    let response = GravatarResponse {
+        entry: vec![GravatarEntry {
+            id: profile.hash.clone(),
+            hash: profile.hash.clone(),
+            request_hash: profile.hash,
+            profile_url: profile.profile_url,
+            photos: profile
+                .thumbnail_url
+                .iter()
+                .map(|url| GravatarPhoto {
+                    value: url.clone(),
+                    photo_type: "thumbnail".to_string(),
+                })
+                .collect(),
+            thumbnail_url: profile.thumbnail_url,
+            name: profile
+                .details
+                .display_name
+                .as_ref()
+                .map(|display_name| GravatarName {
+                    formatted: display_name.clone(),
+                }),
+            display_name: profile.details.display_name,
+            about_me: profile.details.about_me,
+            current_location: profile.details.location,
+            pronouns: profile.details.pronouns,
+            job_title: profile.details.job_title,
+            company: profile.details.company,
+            urls: profile
+                .details
+                .links
+                .into_iter()
+                .map(|link| GravatarUrl {
+                    value: link.url,
+                    title: link.label,
+                })
+                .collect(),
+        }],
+    };
+
+    match serde_json::to_string(&response) {
+        Ok(json) => Ok(json),
+        Err(_) => Err("Issue serializing the profile".to_string()),
+    }
+}
+
+// xml 1.0 has no escape for most control characters, so they are dropped like in vcards
+pub fn escape_xml(value: &str) -> String {
+    value
+        .chars()
+        .filter(|character| !character.is_control() || matches!(character, '\t' | '\n' | '\r'))
+        .collect::<String>()
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn xml_element(tag: &str, value: &str) -> String {
+    format!("<{}>{}</{}>", tag, escape_xml(value), tag)
+}
+
+pub fn render_xml(profile: RenderableProfile) -> String {
+    let mut entry = String::new();
+    entry.push_str(&xml_element("id", &profile.hash));
+    entry.push_str(&xml_element("hash", &profile.hash));
+    entry.push_str(&xml_element("requestHash", &profile.hash));
+    entry.push_str(&xml_element("profileUrl", &profile.profile_url));
+    if let Some(thumbnail_url) = &profile.thumbnail_url {
+        entry.push_str(&xml_element("thumbnailUrl", thumbnail_url));
+        entry.push_str(&format!(
+            "<photos>{}{}</photos>",
+            xml_element("value", thumbnail_url),
+            xml_element("type", "thumbnail")
+        ));
+    }
+    if let Some(display_name) = &profile.details.display_name {
+        entry.push_str(&format!(
+            "<name>{}</name>",
+            xml_element("formatted", display_name)
+        ));
+        entry.push_str(&xml_element("displayName", display_name));
+    }
+    let optional_fields = [
+        ("aboutMe", &profile.details.about_me),
+        ("currentLocation", &profile.details.location),
+        ("pronouns", &profile.details.pronouns),
+        ("job_title", &profile.details.job_title),
+        ("company", &profile.details.company),
+    ];
+    for (tag, value) in optional_fields {
+        if let Some(value) = value {
+            entry.push_str(&xml_element(tag, value));
+        }
+    }
+    for link in profile.details.links.iter() {
+        entry.push_str(&format!(
+            "<urls>{}{}</urls>",
+            xml_element("value", &link.url),
+            xml_element("title", &link.label)
+        ));
+    }
+
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<response><entry>{}</entry></response>",
+        entry
+    )
+}
+
+// the page behind profile_url and the qr code, every value goes through the xml
+// escaping which covers html text and quoted attributes as well
+pub fn render_html(profile: RenderableProfile) -> String {
+    let title = profile
+        .details
+        .display_name
+        .clone()
+        .unwrap_or("Gravatar profile".to_string());
+    let mut body = String::new();
+    if let Some(thumbnail_url) = &profile.thumbnail_url {
+        body.push_str(&format!(
+            "<img src=\"{}\" alt=\"\" width=\"160\" height=\"160\">",
+            escape_xml(thumbnail_url)
+        ));
+    }
+    body.push_str(&format!("<h1>{}</h1>", escape_xml(&title)));
+    let optional_fields = [
+        &profile.details.pronouns,
+        &profile.details.job_title,
+        &profile.details.company,
+        &profile.details.location,
+        &profile.details.about_me,
+    ];
+    for value in optional_fields.into_iter().flatten() {
+        body.push_str(&format!("<p>{}</p>", escape_xml(value)));
+    }
+    if !profile.details.links.is_empty() {
+        body.push_str("<ul>");
+        for link in profile.details.links.iter() {
+            // link urls were checked to be http or https when saved
+            body.push_str(&format!(
+                "<li><a href=\"{}\" rel=\"nofollow noopener\">{}</a></li>",
+                escape_xml(&link.url),
+                escape_xml(&link.label)
+            ));
+        }
+        body.push_str("</ul>");
+    }
+
+    format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{}</title></head><body>{}</body></html>",
+        escape_xml(&title),
+        body
+    )
+}
+
+// a bare carriage return would otherwise end the line and start a new property
+fn escape_vcard(value: &str) -> String {
+    value
+        .replace("\r\n", "\n")
+        .replace('\r', "\n")
+        .chars()
+        .filter(|character| *character == '\n' || !character.is_control())
+        .collect::<String>()
+        .replace('\\', "\\\\")
+        .replace(',', "\\,")
+        .replace(';', "\\;")
+        .replace('\n', "\\n")
+}
+
+// rfc 6868 caret encoding for quoted parameter values
+fn escape_vcard_parameter(value: &str) -> String {
+    let mut escaped = String::new();
+    for character in value.replace("\r\n", "\n").replace('\r', "\n").chars() {
+        match character {
+            '^' => escaped.push_str("^^"),
+            '\n' => escaped.push_str("^n"),
+            '"' => escaped.push_str("^'"),
+            character if character.is_control() => {}
+            character => escaped.push(character),
+        }
+    }
+    escaped
+}
+
+// urls are not escaped in vcard, but must still stay on their own line
+fn strip_controls(value: &str) -> String {
+    value
+        .chars()
+        .filter(|character| !character.is_control())
+        .collect()
+}
+
+// vCard lines longer than 75 octets are folded with CRLF followed by a space
+fn fold_vcard_line(line: &str) -> String {
+    let mut folded = String::new();
+    let mut line_length = 0;
+    for character in line.chars() {
+        let character_length = character.len_utf8();
+        if line_length + character_length > 75 {
+            folded.push_str("\r\n ");
+            line_length = 1;
+        }
+        folded.push(character);
+        line_length += character_length;
+    }
+    folded.push_str("\r\n");
+    folded
+}
+
+pub fn render_vcard(profile: RenderableProfile) -> String {
+    let mut lines = vec!["BEGIN:VCARD".to_string(), "VERSION:4.0".to_string()];
+    lines.push(format!(
+        "FN:{}",
+        escape_vcard(
+            profile
+                .details
+                .display_name
+                .as_ref()
+                .unwrap_or(&profile.hash)
+        )
+    ));
+    if let Some(pronouns) = &profile.details.pronouns {
+        lines.push(format!("PRONOUNS:{}", escape_vcard(pronouns)));
+    }
+    if let Some(job_title) = &profile.details.job_title {
+        lines.push(format!("TITLE:{}", escape_vcard(job_title)));
+    }
+    if let Some(company) = &profile.details.company {
+        lines.push(format!("ORG:{}", escape_vcard(company)));
+    }
+    if let Some(location) = &profile.details.location {
+        lines.push(format!(
+            "ADR;LABEL=\"{}\":;;;;;;",
+            escape_vcard_parameter(location)
+        ));
+    }
+    if let Some(about_me) = &profile.details.about_me {
+        lines.push(format!("NOTE:{}", escape_vcard(about_me)));
+    }
+    if let Some(thumbnail_url) = &profile.thumbnail_url {
+        lines.push(format!("PHOTO:{}", strip_controls(thumbnail_url)));
+    }
+    lines.push(format!(
+        "URL;TYPE=profile:{}",
+        strip_controls(&profile.profile_url)
+    ));
+    for link in profile.details.links.iter() {
+        lines.push(format!("URL:{}", strip_controls(&link.url)));
+    }
+    lines.push(format!("UID:urn:gravatar:{}", profile.hash));
+    lines.push("END:VCARD".to_string());
+
+    lines.iter().map(|line| fold_vcard_line(line)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::public_profile_model::ProfileLink;
+
+    fn profile(location: &str, about_me: &str) -> RenderableProfile {
+        RenderableProfile {
+            hash: "abc".to_string(),
+            profile_url: profile_url("https://avatars.example.com", "abc"),
+            thumbnail_url: None,
+            details: VisibleProfile {
+                display_name: Some("Ada".to_string()),
+                about_me: Some(about_me.to_string()),
+                location: Some(location.to_string()),
+                pronouns: None,
+                job_title: None,
+                company: None,
+                links: vec![ProfileLink {
+                    label: "site".to_string(),
+                    url: "https://example.com/\r\nNOTE:x".to_string(),
+                }],
+            },
+        }
+    }
+
+    // every physical line is either a property or a folded continuation
+    fn property_names(vcard: &str) -> Vec<String> {
+        vcard
+            .split("\r\n")
+            .filter(|line| !line.is_empty() && !line.starts_with(' '))
+            .map(|line| line.split([':', ';']).next().unwrap_or("").to_string())
+            .collect()
+    }
+
+    #[test]
+    fn location_uses_caret_encoding() {
+        let vcard = render_vcard(profile("Caf\"e ^ Town\nNorth", "about"));
+        assert!(vcard.contains("ADR;LABEL=\"Caf^'e ^^ Town^nNorth\":;;;;;;\r\n"));
+    }
+
+    #[test]
+    fn carriage_returns_cannot_start_properties() {
+        let vcard = render_vcard(profile("Town\rEMAIL:evil@example.com", "hi\rTEL:123"));
+        assert_eq!(
+            property_names(&vcard),
+            vec!["BEGIN", "VERSION", "FN", "ADR", "NOTE", "URL", "URL", "UID", "END"]
+        );
+        assert!(vcard.contains("NOTE:hi\\nTEL:123\r\n"));
+    }
+
+    #[test]
+    fn profile_url_points_at_the_profile_route() {
+        assert_eq!(
+            profile_url("https://avatars.example.com", "abc"),
+            "https://avatars.example.com/abc.html"
+        );
+    }
+
+    #[test]
+    fn xml_drops_characters_it_cannot_represent() {
+        let xml = render_xml(profile("Town\u{0}\u{1b}", "tab\there\u{8}"));
+        assert!(xml.contains("<currentLocation>Town</currentLocation>"));
+        assert!(xml.contains("<aboutMe>tab\there</aboutMe>"));
+        assert!(!xml.chars().any(|character| character == '\u{0}'));
+    }
+
+    #[test]
+    fn html_escapes_user_fields() {
+        let html = render_html(profile("<script>alert(1)</script>", "\"quoted\""));
+        assert!(!html.contains("<script>"));
+        assert!(html.contains("&lt;script&gt;alert(1)&lt;/script&gt;"));
+        assert!(html.contains("<p>&quot;quoted&quot;</p>"));
+        assert!(html.contains("<title>Ada</title>"));
+    }
+}
@@ -0,0 +1,500 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use redis::Commands;
+use sqlx::{Pool, Postgres};
+
+use super::{current_time::current_time_secs, hash_token::hash_token};
+
+// remembers which access token is live for each session, a session that is
+// missing, revoked or expired is treated the same by every implementation
+#[async_trait::async_trait]
+pub trait SessionStore: Send + Sync {
+    async fn store(
+        &self,
+        session_id: i64,
+        access_token: &str,
+        expires_at: i64,
+    ) -> Result<(), String>;
+    async fn verify(&self, session_id: i64, access_token: &str) -> Result<bool, String>;
+    async fn remove(&self, session_id: i64) -> Result<(), String>;
+
+    // only the durable store keeps track of sessions another store missed a write for
+    async fn mark_stale(&self, _session_id: i64, _until: i64) -> Result<(), String> {
+        Ok(())
+    }
+    async fn clear_stale(&self, _session_id: i64) -> Result<(), String> {
+        Ok(())
+    }
+    async fn stale_sessions(&self) -> Result<Vec<(i64, i64)>, String> {
+        Ok(Vec::new())
+    }
+}
+
+fn session_key(session_id: i64) -> String {
+    format!("session:{}", session_id)
+}
+
+pub struct RedisSessionStore {
+    pool: r2d2::Pool<redis::Client>,
+}
+
+impl RedisSessionStore {
+    pub fn new(pool: r2d2::Pool<redis::Client>) -> Self {
+        RedisSessionStore { pool }
+    }
+
+    // r2d2 and the redis client block, so they run on the blocking thread pool
+    // instead of stalling the worker while a checkout times out
+    async fn run<T, F>(&self, command: F) -> Result<T, String>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut redis::Connection) -> redis::RedisResult<T> + Send + 'static,
+    {
+        let pool = self.pool.clone();
+        let command_res = actix_web::web::block(move || match pool.get() {
+            Ok(mut redis_conn) => command(&mut redis_conn).ok(),
+            Err(_) => None,
+        })
+        .await;
+        match command_res {
+            Ok(Some(result)) => Ok(result),
+            _ => Err("Issue talking to redis".to_string()),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl SessionStore for RedisSessionStore {
+    async fn store(
+        &self,
+        session_id: i64,
+        access_token: &str,
+        expires_at: i64,
+    ) -> Result<(), String> {
+        let ttl = expires_at - current_time_secs()?;
+        if ttl <= 0 {
+            return Ok(());
+        }
+        let token_hash = hash_token(access_token);
+        self.run(move |redis_conn| {
+            redis_conn.set_ex::<_, _, ()>(session_key(session_id), token_hash, ttl as u64)
+        })
+        .await
+    }
+
+    async fn verify(&self, session_id: i64, access_token: &str) -> Result<bool, String> {
+        let stored = self
+            .run(move |redis_conn| redis_conn.get::<_, Option<String>>(session_key(session_id)))
+            .await?;
+        Ok(stored == Some(hash_token(access_token)))
+    }
+
+    async fn remove(&self, session_id: i64) -> Result<(), String> {
+        self.run(move |redis_conn| redis_conn.del::<_, ()>(session_key(session_id)))
+            .await
+    }
+}
+
+pub struct PostgresSessionStore {
+    pool: Pool<Postgres>,
+}
+
+impl PostgresSessionStore {
+    pub fn new(pool: Pool<Postgres>) -> Self {
+        PostgresSessionStore { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl SessionStore for PostgresSessionStore {
+    async fn store(
+        &self,
+        session_id: i64,
+        access_token: &str,
+        expires_at: i64,
+    ) -> Result<(), String> {
+        let update_res = sqlx::query(
+            "update sessions set access_token_hash=$1, access_expires_at=$2 where id=$3",
+        )
+        .bind(hash_token(access_token))
+        .bind(expires_at)
+        .bind(session_id)
+        .execute(&self.pool)
+        .await;
+        match update_res {
+            Err(_) => Err("Issue talking to the database".to_string()),
+            Ok(_) => Ok(()),
+        }
+    }
+
+    async fn verify(&self, session_id: i64, access_token: &str) -> Result<bool, String> {
+        let session_res = sqlx::query(
+            "select id from sessions where id=$1 and access_token_hash=$2
+            and access_expires_at>$3 and revoked_at is null",
+        )
+        .bind(session_id)
+        .bind(hash_token(access_token))
+        .bind(current_time_secs()?)
+        .fetch_optional(&self.pool)
+        .await;
+        match session_res {
+            Err(_) => Err("Issue talking to the database".to_string()),
+            Ok(session) => Ok(session.is_some()),
+        }
+    }
+
+    async fn remove(&self, session_id: i64) -> Result<(), String> {
+        let update_res = sqlx::query(
+            "update sessions set access_token_hash=null, access_expires_at=null where id=$1",
+        )
+        .bind(session_id)
+        .execute(&self.pool)
+        .await;
+        match update_res {
+            Err(_) => Err("Issue talking to the database".to_string()),
+            Ok(_) => Ok(()),
+        }
+    }
+
+    async fn mark_stale(&self, session_id: i64, until: i64) -> Result<(), String> {
+        let update_res = sqlx::query(
+            "update sessions set primary_stale_until=greatest(coalesce(primary_stale_until, 0), $1) where id=$2",
+        )
+        .bind(until)
+        .bind(session_id)
+        .execute(&self.pool)
+        .await;
+        match update_res {
+            Err(_) => Err("Issue talking to the database".to_string()),
+            Ok(_) => Ok(()),
+        }
+    }
+
+    async fn clear_stale(&self, session_id: i64) -> Result<(), String> {
+        let update_res = sqlx::query("update sessions set primary_stale_until=null where id=$1")
+            .bind(session_id)
+            .execute(&self.pool)
+            .await;
+        match update_res {
+            Err(_) => Err("Issue talking to the database".to_string()),
+            Ok(_) => Ok(()),
+        }
+    }
+
+    async fn stale_sessions(&self) -> Result<Vec<(i64, i64)>, String> {
+        let now = current_time_secs()?;
+        // entries past their lifetime have expired out of the primary on their own
+        let _ = sqlx::query(
+            "update sessions set primary_stale_until=null where primary_stale_until<=$1",
+        )
+        .bind(now)
+        .execute(&self.pool)
+        .await;
+        let stale_res = sqlx::query_as::<_, (i64, i64)>(
+            "select id, primary_stale_until from sessions where primary_stale_until>$1",
+        )
+        .bind(now)
+        .fetch_all(&self.pool)
+        .await;
+        match stale_res {
+            Err(_) => Err("Issue talking to the database".to_string()),
+            Ok(stale) => Ok(stale),
+        }
+    }
+}
+
+// writes go to both stores so the fallback is always current, reads only fall
+// back when the primary cannot be reached, never when it reports a miss
+pub struct FallbackSessionStore {
+    primary: Box<dyn SessionStore>,
+    fallback: Box<dyn SessionStore>,
+    // sessions whose primary entry could not be rewritten or deleted, mapped to
+    // when that stale entry would have expired on its own, a copy is kept in the
+    // fallback so restarts and other instances pick it up on the next retry
+    stale: Mutex<HashMap<i64, i64>>,
+    // a stale entry can outlive the rewrite by at most one access token lifetime
+    access_token_lifetime_secs: i64,
+}
+
+impl FallbackSessionStore {
+    pub fn new(
+        primary: Box<dyn SessionStore>,
+        fallback: Box<dyn SessionStore>,
+        access_token_lifetime_secs: i64,
+    ) -> Self {
+        FallbackSessionStore {
+            primary,
+            fallback,
+            stale: Mutex::new(HashMap::new()),
+            access_token_lifetime_secs,
+        }
+    }
+
+    async fn mark_stale(&self, session_id: i64) {
+        let until = current_time_secs().unwrap_or(0) + self.access_token_lifetime_secs;
+        self.stale.lock().unwrap().insert(session_id, until);
+        if let Err(err_string) = self.fallback.mark_stale(session_id, until).await {
+            log::error!(
+                "Issue recording stale session {}: {}",
+                session_id,
+                err_string
+            );
+        }
+    }
+
+    // the marker may come from another instance, so the fallback copy is cleared
+    // even when this one never saw it, otherwise a retry would delete the fresh entry
+    async fn clear_stale(&self, session_id: i64) {
+        self.stale.lock().unwrap().remove(&session_id);
+        if let Err(err_string) = self.fallback.clear_stale(session_id).await {
+            log::error!(
+                "Issue clearing stale session {}: {}",
+                session_id,
+                err_string
+            );
+        }
+    }
+
+    // picks up what this or another instance recorded before, run at startup and
+    // with every retry
+    pub async fn load_stale(&self) {
+        match self.fallback.stale_sessions().await {
+            Ok(persisted) => {
+                let mut stale = self.stale.lock().unwrap();
+                for (session_id, until) in persisted {
+                    let entry = stale.entry(session_id).or_insert(until);
+                    *entry = (*entry).max(until);
+                }
+            }
+            Err(err_string) => log::error!("Issue loading stale sessions: {}", err_string),
+        }
+    }
+
+    fn is_stale(&self, session_id: i64) -> bool {
+        let now = current_time_secs().unwrap_or(0);
+        let mut stale = self.stale.lock().unwrap();
+        stale.retain(|_, until| *until > now);
+        stale.contains_key(&session_id)
+    }
+
+    // deletes the stale primary entries again, run periodically until they are gone
+    pub async fn retry_stale(&self) {
+        self.load_stale().await;
+        let session_ids: Vec<i64> = {
+            let now = current_time_secs().unwrap_or(0);
+            let mut stale = self.stale.lock().unwrap();
+            stale.retain(|_, until| *until > now);
+            stale.keys().copied().collect()
+        };
+        for session_id in session_ids {
+            if self.primary.remove(session_id).await.is_err() {
+                // still unreachable, the rest would only wait on the same timeout
+                return;
+            }
+            self.stale.lock().unwrap().remove(&session_id);
+            if let Err(err_string) = self.fallback.clear_stale(session_id).await {
+                log::error!(
+                    "Issue clearing stale session {}: {}",
+                    session_id,
+                    err_string
+                );
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl SessionStore for FallbackSessionStore {
+    async fn store(
+        &self,
+        session_id: i64,
+        access_token: &str,
+        expires_at: i64,
+    ) -> Result<(), String> {
+        self.fallback
+            .store(session_id, access_token, expires_at)
+            .await?;
+        match self
+            .primary
+            .store(session_id, access_token, expires_at)
+            .await
+        {
+            Ok(()) => self.clear_stale(session_id).await,
+            // the primary may still hold the token this one replaced
+            Err(err_string) => {
+                log::warn!("Session store primary unavailable: {}", err_string);
+                self.mark_stale(session_id).await;
+            }
+        }
+        Ok(())
+    }
+
+    async fn verify(&self, session_id: i64, access_token: &str) -> Result<bool, String> {
+        // the primary cannot be trusted for a session it missed a write for
+        if self.is_stale(session_id) {
+            return self.fallback.verify(session_id, access_token).await;
+        }
+        match self.primary.verify(session_id, access_token).await {
+            Ok(valid) => Ok(valid),
+            Err(err_string) => {
+                log::warn!("Session store primary unavailable: {}", err_string);
+                self.fallback.verify(session_id, access_token).await
+            }
+        }
+    }
+
+    async fn remove(&self, session_id: i64) -> Result<(), String> {
+        self.fallback.remove(session_id).await?;
+        // a revoked session must not come back once the primary is reachable again
+        if let Err(err_string) = self.primary.remove(session_id).await {
+            log::warn!("Session store primary unavailable: {}", err_string);
+            self.mark_stale(session_id).await;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    };
+
+    use super::*;
+
+    // an in memory store that can be switched off to simulate an outage
+    #[derive(Clone, Default)]
+    struct MemoryStore {
+        sessions: Arc<Mutex<HashMap<i64, String>>>,
+        stale: Arc<Mutex<HashMap<i64, i64>>>,
+        down: Arc<AtomicBool>,
+    }
+
+    impl MemoryStore {
+        fn check(&self) -> Result<(), String> {
+            match self.down.load(Ordering::SeqCst) {
+                true => Err("down".to_string()),
+                false => Ok(()),
+            }
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl SessionStore for MemoryStore {
+        async fn store(&self, session_id: i64, access_token: &str, _: i64) -> Result<(), String> {
+            self.check()?;
+            self.sessions
+                .lock()
+                .unwrap()
+                .insert(session_id, hash_token(access_token));
+            Ok(())
+        }
+
+        async fn verify(&self, session_id: i64, access_token: &str) -> Result<bool, String> {
+            self.check()?;
+            Ok(self.sessions.lock().unwrap().get(&session_id) == Some(&hash_token(access_token)))
+        }
+
+        async fn remove(&self, session_id: i64) -> Result<(), String> {
+            self.check()?;
+            self.sessions.lock().unwrap().remove(&session_id);
+            Ok(())
+        }
+
+        async fn mark_stale(&self, session_id: i64, until: i64) -> Result<(), String> {
+            self.check()?;
+            self.stale.lock().unwrap().insert(session_id, until);
+            Ok(())
+        }
+
+        async fn clear_stale(&self, session_id: i64) -> Result<(), String> {
+            self.check()?;
+            self.stale.lock().unwrap().remove(&session_id);
+            Ok(())
+        }
+
+        async fn stale_sessions(&self) -> Result<Vec<(i64, i64)>, String> {
+            self.check()?;
+            Ok(self
+                .stale
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(session_id, until)| (*session_id, *until))
+                .collect())
+        }
+    }
+
+    fn stores() -> (FallbackSessionStore, MemoryStore, MemoryStore) {
+        let primary = MemoryStore::default();
+        let fallback = MemoryStore::default();
+        let store =
+            FallbackSessionStore::new(Box::new(primary.clone()), Box::new(fallback.clone()), 900);
+        (store, primary, fallback)
+    }
+
+    #[actix_web::test]
+    async fn revocation_survives_a_primary_outage() {
+        let (store, primary, _) = stores();
+        store.store(1, "token", i64::MAX).await.unwrap();
+
+        primary.down.store(true, Ordering::SeqCst);
+        store.remove(1).await.unwrap();
+        primary.down.store(false, Ordering::SeqCst);
+
+        // the primary still holds the token, but the session must stay revoked
+        assert!(primary.verify(1, "token").await.unwrap());
+        assert!(!store.verify(1, "token").await.unwrap());
+
+        store.retry_stale().await;
+        assert!(!primary.verify(1, "token").await.unwrap());
+        assert!(!store.verify(1, "token").await.unwrap());
+    }
+
+    #[actix_web::test]
+    async fn revocation_survives_a_restart_during_the_outage() {
+        let (store, primary, fallback) = stores();
+        store.store(1, "token", i64::MAX).await.unwrap();
+
+        primary.down.store(true, Ordering::SeqCst);
+        store.remove(1).await.unwrap();
+        primary.down.store(false, Ordering::SeqCst);
+
+        // a new process starts with an empty in memory set
+        let restarted =
+            FallbackSessionStore::new(Box::new(primary.clone()), Box::new(fallback.clone()), 900);
+        restarted.load_stale().await;
+        assert!(!restarted.verify(1, "token").await.unwrap());
+
+        restarted.retry_stale().await;
+        assert!(!primary.verify(1, "token").await.unwrap());
+        assert!(fallback.stale_sessions().await.unwrap().is_empty());
+    }
+
+    #[actix_web::test]
+    async fn rotated_tokens_are_checked_against_the_fallback_until_rewritten() {
+        let (store, primary, _) = stores();
+        store.store(1, "old", i64::MAX).await.unwrap();
+
+        primary.down.store(true, Ordering::SeqCst);
+        store.store(1, "new", i64::MAX).await.unwrap();
+        primary.down.store(false, Ordering::SeqCst);
+
+        assert!(!store.verify(1, "old").await.unwrap());
+        assert!(store.verify(1, "new").await.unwrap());
+
+        // a successful write makes the primary authoritative again
+        store.store(1, "newer", i64::MAX).await.unwrap();
+        primary.down.store(true, Ordering::SeqCst);
+        assert!(store.verify(1, "newer").await.unwrap());
+    }
+
+    #[actix_web::test]
+    async fn a_primary_miss_is_not_retried_on_the_fallback() {
+        let (store, primary, fallback) = stores();
+        fallback.store(1, "token", i64::MAX).await.unwrap();
+        assert!(!primary.verify(1, "token").await.unwrap());
+        assert!(!store.verify(1, "token").await.unwrap());
+    }
+}
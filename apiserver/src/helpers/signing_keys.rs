@@ -0,0 +1,393 @@
+use std::path::PathBuf;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{
+    decode, decode_header,
+    errors::{Error, ErrorKind},
+    jwk::{
+        AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, KeyAlgorithm,
+        OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType,
+    },
+    Algorithm, DecodingKey, EncodingKey, Header, Validation,
+};
+use openssl::pkey::{Id, PKey, Public};
+
+// one entry of JWT_KEYS, retired keys only need their public half
+#[derive(serde::Deserialize)]
+struct KeyConfig {
+    kid: String,
+    alg: String,
+    #[serde(rename = "privateKeyFile")]
+    private_key_file: Option<PathBuf>,
+    #[serde(rename = "publicKeyFile")]
+    public_key_file: Option<PathBuf>,
+}
+
+struct VerificationKey {
+    kid: Option<String>,
+    algorithm: Algorithm,
+    decoding_key: DecodingKey,
+    // absent for the shared secret, which is never published
+    jwk: Option<Jwk>,
+}
+
+// signs with one active key and accepts any key still listed, so a new key
+// can be introduced and an old one retired without signing anybody out
+pub struct SigningKeys {
+    kid: Option<String>,
+    algorithm: Algorithm,
+    encoding_key: EncodingKey,
+    verification_keys: Vec<VerificationKey>,
+}
+
+fn read_file(path: &PathBuf) -> Result<Vec<u8>, String> {
+    match std::fs::read(path) {
+        Ok(contents) => Ok(contents),
+        Err(err) => Err(format!("Issue reading {}: {}", path.display(), err)),
+    }
+}
+
+fn public_jwk(kid: &str, algorithm: Algorithm, public_key: &PKey<Public>) -> Result<Jwk, String> {
+    let (key_algorithm, parameters) = match algorithm {
+        Algorithm::RS256 => {
+            let rsa = match public_key.rsa() {
+                Ok(rsa) => rsa,
+                Err(_) => return Err(format!("Key {} is not an rsa key", kid)),
+            };
+            (
+                KeyAlgorithm::RS256,
+                AlgorithmParameters::RSA(RSAKeyParameters {
+                    key_type: RSAKeyType::RSA,
+                    n: URL_SAFE_NO_PAD.encode(rsa.n().to_vec()),
+                    e: URL_SAFE_NO_PAD.encode(rsa.e().to_vec()),
+                }),
+            )
+        }
+        Algorithm::EdDSA => {
+            let raw_key = match public_key.id() {
+                Id::ED25519 => public_key.raw_public_key(),
+                _ => return Err(format!("Key {} is not an ed25519 key", kid)),
+            };
+            let raw_key = match raw_key {
+                Ok(raw_key) => raw_key,
+                Err(_) => return Err(format!("Issue reading key {}", kid)),
+            };
+            (
+                KeyAlgorithm::EdDSA,
+                AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                    key_type: OctetKeyPairType::OctetKeyPair,
+                    curve: EllipticCurve::Ed25519,
+                    x: URL_SAFE_NO_PAD.encode(raw_key),
+                }),
+            )
+        }
+        _ => return Err(format!("Key {} should use RS256 or EdDSA", kid)),
+    };
+    Ok(Jwk {
+        common: CommonParameters {
+            public_key_use: Some(PublicKeyUse::Signature),
+            key_algorithm: Some(key_algorithm),
+            key_id: Some(kid.to_string()),
+            ..Default::default()
+        },
+        algorithm: parameters,
+    })
+}
+
+impl SigningKeys {
+    // without configured keys tokens stay hs256 and only this server can verify them
+    pub fn from_secret(secret: &str) -> Self {
+        SigningKeys {
+            kid: None,
+            algorithm: Algorithm::HS256,
+            encoding_key: EncodingKey::from_secret(secret.as_bytes()),
+            verification_keys: vec![VerificationKey {
+                kid: None,
+                algorithm: Algorithm::HS256,
+                decoding_key: DecodingKey::from_secret(secret.as_bytes()),
+                jwk: None,
+            }],
+        }
+    }
+
+    // JWT_KEYS is a json array of {"kid", "alg", "privateKeyFile", "publicKeyFile"}
+    // holding pem files, JWT_ACTIVE_KID picks the one that signs
+    pub fn from_config(raw: &str, active_kid: &str) -> Result<Self, String> {
+        let configs: Vec<KeyConfig> = match serde_json::from_str(raw) {
+            Ok(configs) => configs,
+            Err(err) => return Err(format!("Invalid jwt key configuration: {}", err)),
+        };
+
+        let mut active: Option<(Algorithm, EncodingKey)> = None;
+        let mut verification_keys: Vec<VerificationKey> = Vec::new();
+        for config in configs {
+            let algorithm = match config.alg.as_str() {
+                "RS256" => Algorithm::RS256,
+                "EdDSA" => Algorithm::EdDSA,
+                _ => return Err(format!("Key {} should use RS256 or EdDSA", config.kid)),
+            };
+            if verification_keys
+                .iter()
+                .any(|key| key.kid.as_deref() == Some(config.kid.as_str()))
+            {
+                return Err(format!("Key id {} is listed twice", config.kid));
+            }
+
+            let private_pem = match &config.private_key_file {
+                Some(path) => Some(read_file(path)?),
+                None => None,
+            };
+            // the public half is derived from the private key when both could be given
+            let public_key = match (&private_pem, &config.public_key_file) {
+                (Some(private_pem), _) => PKey::private_key_from_pem(private_pem)
+                    .and_then(|private_key| private_key.public_key_to_pem())
+                    .and_then(|public_pem| PKey::public_key_from_pem(&public_pem)),
+                (None, Some(path)) => PKey::public_key_from_pem(&read_file(path)?),
+                (None, None) => {
+                    return Err(format!(
+                        "Key {} needs a privateKeyFile or publicKeyFile",
+                        config.kid
+                    ))
+                }
+            };
+            let public_key = match public_key {
+                Ok(public_key) => public_key,
+                Err(_) => return Err(format!("Key {} is not a valid pem key", config.kid)),
+            };
+
+            let jwk = public_jwk(&config.kid, algorithm, &public_key)?;
+            let decoding_key = match DecodingKey::from_jwk(&jwk) {
+                Ok(decoding_key) => decoding_key,
+                Err(_) => return Err(format!("Issue loading key {}", config.kid)),
+            };
+
+            if config.kid == active_kid {
+                let private_pem = match &private_pem {
+                    Some(private_pem) => private_pem,
+                    None => {
+                        return Err(format!("Active key {} needs a privateKeyFile", config.kid))
+                    }
+                };
+                let encoding_key = match algorithm {
+                    Algorithm::RS256 => EncodingKey::from_rsa_pem(private_pem),
+                    _ => EncodingKey::from_ed_pem(private_pem),
+                };
+                match encoding_key {
+                    Ok(encoding_key) => active = Some((algorithm, encoding_key)),
+                    Err(_) => return Err(format!("Issue loading key {}", config.kid)),
+                }
+            }
+
+            verification_keys.push(VerificationKey {
+                kid: Some(config.kid),
+                algorithm,
+                decoding_key,
+                jwk: Some(jwk),
+            });
+        }
+
+        match active {
+            Some((algorithm, encoding_key)) => Ok(SigningKeys {
+                kid: Some(active_kid.to_string()),
+                algorithm,
+                encoding_key,
+                verification_keys,
+            }),
+            None => Err(format!("Active key {} is not configured", active_kid)),
+        }
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    pub fn sign<T: serde::Serialize>(&self, claims: &T) -> Result<String, String> {
+        let mut header = Header::new(self.algorithm);
+        header.kid = self.kid.clone();
+        match jsonwebtoken::encode(&header, claims, &self.encoding_key) {
+            Ok(token) => Ok(token),
+            Err(_) => Err("Issue signing the token".to_string()),
+        }
+    }
+
+    // the key is chosen by kid and must match the algorithm it was configured with
+    pub fn verify<T: serde::de::DeserializeOwned>(&self, token: &str) -> Result<T, Error> {
+        let header = decode_header(token)?;
+        let key = match self
+            .verification_keys
+            .iter()
+            .find(|key| key.kid == header.kid)
+        {
+            Some(key) => key,
+            None => return Err(Error::from(ErrorKind::InvalidToken)),
+        };
+        let validation = Validation::new(key.algorithm);
+        Ok(decode::<T>(token, &key.decoding_key, &validation)?.claims)
+    }
+
+    pub fn jwks(&self) -> JwkSet {
+        JwkSet {
+            keys: self
+                .verification_keys
+                .iter()
+                .filter_map(|key| key.jwk.clone())
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::NamedTempFile;
+
+    use super::*;
+
+    #[derive(serde::Serialize, serde::Deserialize)]
+    struct Claims {
+        sub: String,
+        exp: u64,
+    }
+
+    fn claims() -> Claims {
+        Claims {
+            sub: "42".to_string(),
+            exp: jsonwebtoken::get_current_timestamp() + 600,
+        }
+    }
+
+    fn pem_files(private_key: &PKey<openssl::pkey::Private>) -> (NamedTempFile, NamedTempFile) {
+        let private_file = NamedTempFile::new().unwrap();
+        std::fs::write(
+            private_file.path(),
+            private_key.private_key_to_pem_pkcs8().unwrap(),
+        )
+        .unwrap();
+        let public_file = NamedTempFile::new().unwrap();
+        std::fs::write(public_file.path(), private_key.public_key_to_pem().unwrap()).unwrap();
+        (private_file, public_file)
+    }
+
+    #[test]
+    fn verifies_tokens_from_retired_keys_during_a_rotation() {
+        let (old_private, old_public) = pem_files(&PKey::generate_ed25519().unwrap());
+        let (new_private, _) = pem_files(&PKey::generate_ed25519().unwrap());
+
+        let before = SigningKeys::from_config(
+            &serde_json::json!([{ "kid": "old", "alg": "EdDSA", "privateKeyFile": old_private.path() }])
+                .to_string(),
+            "old",
+        )
+        .unwrap();
+        let old_token = before.sign(&claims()).unwrap();
+
+        // the old key keeps only its public half once the new one signs
+        let after = SigningKeys::from_config(
+            &serde_json::json!([
+                { "kid": "new", "alg": "EdDSA", "privateKeyFile": new_private.path() },
+                { "kid": "old", "alg": "EdDSA", "publicKeyFile": old_public.path() },
+            ])
+            .to_string(),
+            "new",
+        )
+        .unwrap();
+        assert_eq!(after.verify::<Claims>(&old_token).unwrap().sub, "42");
+        let new_token = after.sign(&claims()).unwrap();
+        assert_eq!(
+            decode_header(&new_token).unwrap().kid.as_deref(),
+            Some("new")
+        );
+        assert!(before.verify::<Claims>(&new_token).is_err());
+
+        let kids: Vec<Option<String>> = after
+            .jwks()
+            .keys
+            .into_iter()
+            .map(|jwk| jwk.common.key_id)
+            .collect();
+        assert_eq!(kids, vec![Some("new".to_string()), Some("old".to_string())]);
+    }
+
+    #[test]
+    fn rejects_unknown_kids_and_mismatched_algorithms() {
+        let (private_file, public_file) = pem_files(&PKey::generate_ed25519().unwrap());
+        let keys = SigningKeys::from_config(
+            &serde_json::json!([{ "kid": "ed", "alg": "EdDSA", "privateKeyFile": private_file.path() }])
+                .to_string(),
+            "ed",
+        )
+        .unwrap();
+
+        let mut header = Header::new(Algorithm::EdDSA);
+        header.kid = Some("someone-else".to_string());
+        let unknown_kid = jsonwebtoken::encode(
+            &header,
+            &claims(),
+            &EncodingKey::from_ed_pem(&std::fs::read(private_file.path()).unwrap()).unwrap(),
+        )
+        .unwrap();
+        assert!(keys.verify::<Claims>(&unknown_kid).is_err());
+
+        // an hs256 token keyed with the published public key must not pass as the ed25519 key
+        let mut header = Header::new(Algorithm::HS256);
+        header.kid = Some("ed".to_string());
+        let confused = jsonwebtoken::encode(
+            &header,
+            &claims(),
+            &EncodingKey::from_secret(&std::fs::read(public_file.path()).unwrap()),
+        )
+        .unwrap();
+        assert!(keys.verify::<Claims>(&confused).is_err());
+
+        // nor can a token without a kid fall back to the shared secret path
+        let no_kid = SigningKeys::from_secret("shared").sign(&claims()).unwrap();
+        assert!(keys.verify::<Claims>(&no_kid).is_err());
+    }
+
+    #[test]
+    fn the_shared_secret_is_never_published() {
+        let keys = SigningKeys::from_secret("shared");
+        let token = keys.sign(&claims()).unwrap();
+        assert_eq!(keys.verify::<Claims>(&token).unwrap().sub, "42");
+        assert!(SigningKeys::from_secret("other")
+            .verify::<Claims>(&token)
+            .is_err());
+        assert!(keys.jwks().keys.is_empty());
+    }
+
+    #[test]
+    fn rejects_bad_configuration() {
+        let (private_file, public_file) = pem_files(&PKey::generate_ed25519().unwrap());
+        let config = |keys: serde_json::Value| keys.to_string();
+        assert!(SigningKeys::from_config("not json", "ed").is_err());
+        assert!(SigningKeys::from_config(
+            &config(serde_json::json!([{ "kid": "ed", "alg": "HS256", "privateKeyFile": private_file.path() }])),
+            "ed"
+        )
+        .is_err());
+        // an ed25519 key can not be listed as rsa
+        assert!(SigningKeys::from_config(
+            &config(serde_json::json!([{ "kid": "ed", "alg": "RS256", "privateKeyFile": private_file.path() }])),
+            "ed"
+        )
+        .is_err());
+        // the active key has to be able to sign
+        assert!(SigningKeys::from_config(
+            &config(serde_json::json!([{ "kid": "ed", "alg": "EdDSA", "publicKeyFile": public_file.path() }])),
+            "ed"
+        )
+        .is_err());
+        assert!(SigningKeys::from_config(
+            &config(serde_json::json!([
+                { "kid": "ed", "alg": "EdDSA", "privateKeyFile": private_file.path() },
+                { "kid": "ed", "alg": "EdDSA", "publicKeyFile": public_file.path() },
+            ])),
+            "ed"
+        )
+        .is_err());
+        assert!(SigningKeys::from_config(
+            &config(serde_json::json!([{ "kid": "ed", "alg": "EdDSA", "privateKeyFile": private_file.path() }])),
+            "missing"
+        )
+        .is_err());
+    }
+}
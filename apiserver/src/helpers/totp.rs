@@ -0,0 +1,163 @@
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha1::Sha1;
+
+// rfc 6238 defaults, the values every authenticator app understands
+const STEP_SECS: i64 = 30;
+const DIGITS: u32 = 6;
+// accept one step either side to allow for clock drift
+const ALLOWED_DRIFT_STEPS: i64 = 1;
+
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+pub fn generate_secret() -> String {
+    let mut bytes = [0u8; 20];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    BASE32_NOPAD.encode(&bytes)
+}
+
+pub fn otpauth_uri(issuer: &str, account: &str, secret: &str) -> String {
+    let mut uri = match reqwest::Url::parse(&format!("otpauth://totp/{}:{}", issuer, account)) {
+        Ok(uri) => uri,
+        Err(_) => return format!("otpauth://totp/?secret={}", secret),
+    };
+    uri.query_pairs_mut().extend_pairs([
+        ("secret", secret),
+        ("issuer", issuer),
+        ("algorithm", "SHA1"),
+        ("digits", "6"),
+        ("period", "30"),
+    ]);
+    uri.to_string()
+}
+
+// rfc 4226 hotp with dynamic truncation
+fn code_at(secret: &[u8], step: i64) -> Option<u32> {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).ok()?;
+    mac.update(&step.to_be_bytes());
+    let digest = mac.finalize().into_bytes();
+    let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        digest[offset] & 0x7f,
+        digest[offset + 1],
+        digest[offset + 2],
+        digest[offset + 3],
+    ]);
+    Some(binary % 10u32.pow(DIGITS))
+}
+
+// returns the matched time step so callers can refuse to accept it twice
+pub fn verify_code(secret: &str, code: &str, now: i64, last_used_step: Option<i64>) -> Option<i64> {
+    let secret = BASE32_NOPAD.decode(secret.as_bytes()).ok()?;
+    let code = code.trim();
+    if code.len() != DIGITS as usize {
+        return None;
+    }
+    let code: u32 = code.parse().ok()?;
+    let current_step = now / STEP_SECS;
+    (current_step - ALLOWED_DRIFT_STEPS..=current_step + ALLOWED_DRIFT_STEPS).find(|step| {
+        last_used_step.is_none_or(|last_used_step| *step > last_used_step)
+            && code_at(&secret, *step) == Some(code)
+    })
+}
+
+// ten characters of base32 shown as two groups, e.g. abcde-fghij
+pub fn generate_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let mut bytes = [0u8; 10];
+            rand::thread_rng().fill_bytes(&mut bytes);
+            let encoded = BASE32_NOPAD.encode(&bytes).to_lowercase();
+            format!("{}-{}", &encoded[0..5], &encoded[5..10])
+        })
+        .collect()
+}
+
+pub fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|character| character.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the ascii seed "12345678901234567890" from rfc 6238 appendix b
+    const RFC_SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+    #[test]
+    fn matches_the_rfc_6238_vectors() {
+        // the rfc lists eight digits, authenticator apps show the last six
+        for (time, code) in [
+            (59, "287082"),
+            (1111111109, "081804"),
+            (1111111111, "050471"),
+            (1234567890, "005924"),
+            (2000000000, "279037"),
+            (20000000000, "353130"),
+        ] {
+            assert_eq!(
+                verify_code(RFC_SECRET, code, time, None),
+                Some(time / STEP_SECS),
+                "time {}",
+                time
+            );
+        }
+    }
+
+    #[test]
+    fn allows_one_step_of_drift() {
+        let step = 1111111109 / STEP_SECS;
+        let now = step * STEP_SECS;
+        assert_eq!(
+            verify_code(RFC_SECRET, "081804", now + STEP_SECS, None),
+            Some(step)
+        );
+        assert_eq!(
+            verify_code(RFC_SECRET, "081804", now - STEP_SECS, None),
+            Some(step)
+        );
+        assert_eq!(
+            verify_code(RFC_SECRET, "081804", now + 2 * STEP_SECS, None),
+            None
+        );
+        assert_eq!(
+            verify_code(RFC_SECRET, "081804", now - 2 * STEP_SECS, None),
+            None
+        );
+    }
+
+    #[test]
+    fn refuses_a_replayed_or_malformed_code() {
+        let step = 1111111109 / STEP_SECS;
+        assert_eq!(
+            verify_code(RFC_SECRET, "081804", 1111111109, Some(step)),
+            None
+        );
+        assert_eq!(
+            verify_code(RFC_SECRET, "081804", 1111111109, Some(step - 1)),
+            Some(step)
+        );
+        assert_eq!(
+            verify_code(RFC_SECRET, " 081804 ", 1111111109, None),
+            Some(step)
+        );
+        assert_eq!(verify_code(RFC_SECRET, "81804", 1111111109, None), None);
+        assert_eq!(verify_code(RFC_SECRET, "08180a", 1111111109, None), None);
+        assert_eq!(verify_code("not base32!", "081804", 1111111109, None), None);
+    }
+
+    #[test]
+    fn recovery_codes_normalize_to_their_characters() {
+        let codes = generate_recovery_codes();
+        assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+        for code in &codes {
+            assert_eq!(code.len(), 11);
+            assert_eq!(normalize_recovery_code(&code.to_uppercase()).len(), 10);
+        }
+        assert_eq!(normalize_recovery_code(" AbCdE-fGhIj "), "abcdefghij");
+    }
+}
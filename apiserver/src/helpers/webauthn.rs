@@ -0,0 +1,144 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use redis::Commands;
+use sha2::Sha256;
+use webauthn_rs::{
+    prelude::{CredentialID, PasskeyAuthentication, RequestChallengeResponse, Url, Uuid},
+    Webauthn, WebauthnBuilder,
+};
+
+// registration and sign in ceremonies have to finish within this window
+pub const CEREMONY_LIFETIME_SECS: u64 = 300;
+
+// the relying party is the host of the public url, browsers only hand out
+// assertions to pages served from that origin
+pub fn build_webauthn(public_url: &str) -> Result<Webauthn, String> {
+    let origin = match Url::parse(public_url) {
+        Ok(origin) => origin,
+        Err(_) => return Err("Invalid public url".to_string()),
+    };
+    let rp_id = match origin.host_str() {
+        Some(rp_id) => rp_id.to_string(),
+        None => return Err("The public url has no host".to_string()),
+    };
+    let builder = match WebauthnBuilder::new(&rp_id, &origin) {
+        Ok(builder) => builder,
+        Err(_) => return Err("Invalid webauthn relying party".to_string()),
+    };
+    match builder.rp_name("Gravatar").build() {
+        Ok(webauthn) => Ok(webauthn),
+        Err(_) => Err("Invalid webauthn relying party".to_string()),
+    }
+}
+
+// authenticators want a stable opaque handle rather than the email
+pub fn user_handle(user_id: i64) -> Uuid {
+    Uuid::from_u64_pair(0, user_id as u64)
+}
+
+pub fn encode_credential_id(credential_id: &CredentialID) -> String {
+    URL_SAFE_NO_PAD.encode(credential_id.as_ref())
+}
+
+pub fn store_ceremony<T: serde::Serialize>(
+    pool: &r2d2::Pool<redis::Client>,
+    key: &str,
+    state: &T,
+) -> Result<(), String> {
+    let serialized = match serde_json::to_string(state) {
+        Ok(serialized) => serialized,
+        Err(_) => return Err("Issue saving the passkey challenge".to_string()),
+    };
+    let mut redis_conn = match pool.get() {
+        Ok(redis_conn) => redis_conn,
+        Err(_) => return Err("Issue talking to redis".to_string()),
+    };
+    let set_res: Result<(), _> = redis_conn.set_ex(key, serialized, CEREMONY_LIFETIME_SECS);
+    match set_res {
+        Err(_) => Err("Issue talking to redis".to_string()),
+        Ok(_) => Ok(()),
+    }
+}
+
+// unknown addresses and accounts without passkeys get a challenge that lists a
+// made up credential, derived from the address so asking twice gives the same
+// one, and a state no assertion can ever satisfy
+pub fn decoy_authentication(
+    webauthn: &Webauthn,
+    secret: &str,
+    email_sha256: &str,
+) -> Result<(RequestChallengeResponse, PasskeyAuthentication), String> {
+    let (options, state) = match webauthn.start_passkey_authentication(&[]) {
+        Ok(started) => started,
+        Err(_) => return Err("Issue starting the passkey sign in".to_string()),
+    };
+
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac accepts keys of any length");
+    mac.update(b"decoy-passkey:");
+    mac.update(email_sha256.as_bytes());
+    let decoy_id = URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes());
+
+    // the proto types are not exported, so the credential goes in through json
+    let mut options_json = match serde_json::to_value(&options) {
+        Ok(options_json) => options_json,
+        Err(_) => return Err("Issue starting the passkey sign in".to_string()),
+    };
+    options_json["publicKey"]["allowCredentials"] = serde_json::json!([{
+        "type": "public-key",
+        "id": decoy_id,
+        "transports": ["internal", "hybrid"],
+    }]);
+    match serde_json::from_value(options_json) {
+        Ok(options) => Ok((options, state)),
+        Err(_) => Err("Issue starting the passkey sign in".to_string()),
+    }
+}
+
+// reading the state also deletes it so a challenge is only answered once
+pub fn take_ceremony<T: serde::de::DeserializeOwned>(
+    pool: &r2d2::Pool<redis::Client>,
+    key: &str,
+) -> Result<Option<T>, String> {
+    let mut redis_conn = match pool.get() {
+        Ok(redis_conn) => redis_conn,
+        Err(_) => return Err("Issue talking to redis".to_string()),
+    };
+    let stored_res: Result<Option<String>, _> = redis_conn.get_del(key);
+    match stored_res {
+        Err(_) => Err("Issue talking to redis".to_string()),
+        Ok(None) => Ok(None),
+        Ok(Some(stored)) => Ok(serde_json::from_str(&stored).ok()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allowed_ids(options: &RequestChallengeResponse) -> Vec<String> {
+        serde_json::to_value(options).unwrap()["publicKey"]["allowCredentials"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|credential| credential["id"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn decoys_are_stable_per_address() {
+        let webauthn = build_webauthn("https://gravatar.example.com").unwrap();
+        let (first, _) = decoy_authentication(&webauthn, "secret", "aaa").unwrap();
+        let (second, _) = decoy_authentication(&webauthn, "secret", "aaa").unwrap();
+        let (other, _) = decoy_authentication(&webauthn, "secret", "bbb").unwrap();
+
+        assert_eq!(allowed_ids(&first).len(), 1);
+        assert_eq!(allowed_ids(&first), allowed_ids(&second));
+        assert_ne!(allowed_ids(&first), allowed_ids(&other));
+        // every request still gets a fresh challenge
+        assert_ne!(
+            serde_json::to_value(&first).unwrap()["publicKey"]["challenge"],
+            serde_json::to_value(&second).unwrap()["publicKey"]["challenge"]
+        );
+    }
+}
@@ -0,0 +1,366 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use quick_xml::{events::Event, Reader};
+
+use super::render_profile::escape_xml;
+
+#[derive(Debug, Clone)]
+pub enum XmlRpcValue {
+    Int(i64),
+    Boolean(bool),
+    String(String),
+    Double(f64),
+    Base64(Vec<u8>),
+    DateTime(String),
+    Array(Vec<XmlRpcValue>),
+    Struct(Vec<(String, XmlRpcValue)>),
+    Nil,
+}
+
+impl XmlRpcValue {
+    pub fn member(&self, name: &str) -> Option<&XmlRpcValue> {
+        match self {
+            XmlRpcValue::Struct(members) => members
+                .iter()
+                .find(|(member_name, _)| member_name == name)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            XmlRpcValue::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&Vec<XmlRpcValue>> {
+        match self {
+            XmlRpcValue::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&Vec<u8>> {
+        match self {
+            XmlRpcValue::Base64(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+}
+
+pub struct MethodCall {
+    pub method_name: String,
+    pub params: Vec<XmlRpcValue>,
+}
+
+// a minimal element tree, xml-rpc documents are small enough to hold in memory
+struct Element {
+    name: String,
+    text: String,
+    children: Vec<Element>,
+}
+
+impl Element {
+    fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|child| child.name == name)
+    }
+}
+
+fn parse_document(body: &str) -> Result<Element, String> {
+    let mut reader = Reader::from_str(body);
+    let mut stack: Vec<Element> = vec![Element {
+        name: "".to_string(),
+        text: "".to_string(),
+        children: Vec::new(),
+    }];
+
+    loop {
+        match reader.read_event() {
+            Err(_) => return Err("Malformed xml".to_string()),
+            Ok(Event::Eof) => break,
+            Ok(Event::Start(start)) => stack.push(Element {
+                name: String::from_utf8_lossy(start.name().as_ref()).to_string(),
+                text: "".to_string(),
+                children: Vec::new(),
+            }),
+            Ok(Event::Empty(empty)) => {
+                let element = Element {
+                    name: String::from_utf8_lossy(empty.name().as_ref()).to_string(),
+                    text: "".to_string(),
+                    children: Vec::new(),
+                };
+                stack.last_mut().unwrap().children.push(element);
+            }
+            Ok(Event::Text(text)) => {
+                let unescaped = text.unescape();
+                if unescaped.is_err() {
+                    return Err("Malformed xml".to_string());
+                }
+                stack.last_mut().unwrap().text.push_str(&unescaped.unwrap());
+            }
+            Ok(Event::CData(cdata)) => {
+                let text = String::from_utf8_lossy(&cdata.into_inner()).to_string();
+                stack.last_mut().unwrap().text.push_str(&text);
+            }
+            Ok(Event::End(_)) => {
+                if stack.len() < 2 {
+                    return Err("Malformed xml".to_string());
+                }
+                let element = stack.pop().unwrap();
+                stack.last_mut().unwrap().children.push(element);
+            }
+            Ok(_) => {}
+        }
+    }
+
+    if stack.len() != 1 {
+        return Err("Malformed xml".to_string());
+    }
+    match stack.pop().unwrap().children.into_iter().next() {
+        Some(root) => Ok(root),
+        None => Err("Empty document".to_string()),
+    }
+}
+
+fn parse_value(value: &Element) -> Result<XmlRpcValue, String> {
+    // a value without a type element is a string
+    let typed = match value.children.first() {
+        Some(typed) => typed,
+        None => return Ok(XmlRpcValue::String(value.text.clone())),
+    };
+
+    match typed.name.as_str() {
+        "int" | "i4" | "i8" => match typed.text.trim().parse::<i64>() {
+            Ok(number) => Ok(XmlRpcValue::Int(number)),
+            Err(_) => Err("Invalid int value".to_string()),
+        },
+        "boolean" => match typed.text.trim() {
+            "1" => Ok(XmlRpcValue::Boolean(true)),
+            "0" => Ok(XmlRpcValue::Boolean(false)),
+            _ => Err("Invalid boolean value".to_string()),
+        },
+        "string" => Ok(XmlRpcValue::String(typed.text.clone())),
+        "double" => match typed.text.trim().parse::<f64>() {
+            Ok(number) => Ok(XmlRpcValue::Double(number)),
+            Err(_) => Err("Invalid double value".to_string()),
+        },
+        "base64" => {
+            let cleaned: String = typed
+                .text
+                .chars()
+                .filter(|character| !character.is_whitespace())
+                .collect();
+            match STANDARD.decode(cleaned) {
+                Ok(bytes) => Ok(XmlRpcValue::Base64(bytes)),
+                Err(_) => Err("Invalid base64 value".to_string()),
+            }
+        }
+        "dateTime.iso8601" => Ok(XmlRpcValue::DateTime(typed.text.trim().to_string())),
+        "nil" => Ok(XmlRpcValue::Nil),
+        "array" => {
+            let mut values = Vec::new();
+            if let Some(data) = typed.child("data") {
+                for item in data.children.iter().filter(|child| child.name == "value") {
+                    values.push(parse_value(item)?);
+                }
+            }
+            Ok(XmlRpcValue::Array(values))
+        }
+        "struct" => {
+            let mut members = Vec::new();
+            for member in typed.children.iter().filter(|child| child.name == "member") {
+                let name = member.child("name");
+                let member_value = member.child("value");
+                if name.is_none() || member_value.is_none() {
+                    return Err("Invalid struct member".to_string());
+                }
+                members.push((
+                    name.unwrap().text.trim().to_string(),
+                    parse_value(member_value.unwrap())?,
+                ));
+            }
+            Ok(XmlRpcValue::Struct(members))
+        }
+        other => Err(format!("Unsupported value type {}", other)),
+    }
+}
+
+pub fn parse_method_call(body: &str) -> Result<MethodCall, String> {
+    let root = parse_document(body)?;
+    if root.name != "methodCall" {
+        return Err("Expected a methodCall".to_string());
+    }
+
+    let method_name = match root.child("methodName") {
+        Some(method_name) => method_name.text.trim().to_string(),
+        None => return Err("Missing methodName".to_string()),
+    };
+
+    let mut params = Vec::new();
+    if let Some(params_element) = root.child("params") {
+        for param in params_element
+            .children
+            .iter()
+            .filter(|child| child.name == "param")
+        {
+            match param.child("value") {
+                Some(value) => params.push(parse_value(value)?),
+                None => return Err("Missing param value".to_string()),
+            }
+        }
+    }
+
+    Ok(MethodCall {
+        method_name,
+        params,
+    })
+}
+
+fn write_value(value: &XmlRpcValue, out: &mut String) {
+    out.push_str("<value>");
+    match value {
+        XmlRpcValue::Int(number) => out.push_str(&format!("<int>{}</int>", number)),
+        XmlRpcValue::Boolean(flag) => {
+            out.push_str(&format!("<boolean>{}</boolean>", if *flag { 1 } else { 0 }))
+        }
+        XmlRpcValue::String(text) => {
+            out.push_str(&format!("<string>{}</string>", escape_xml(text)))
+        }
+        XmlRpcValue::Double(number) => out.push_str(&format!("<double>{}</double>", number)),
+        XmlRpcValue::Base64(bytes) => {
+            out.push_str(&format!("<base64>{}</base64>", STANDARD.encode(bytes)))
+        }
+        XmlRpcValue::DateTime(date) => out.push_str(&format!(
+            "<dateTime.iso8601>{}</dateTime.iso8601>",
+            escape_xml(date)
+        )),
+        XmlRpcValue::Array(values) => {
+            out.push_str("<array><data>");
+            for item in values {
+                write_value(item, out);
+            }
+            out.push_str("</data></array>");
+        }
+        XmlRpcValue::Struct(members) => {
+            out.push_str("<struct>");
+            for (name, member_value) in members {
+                out.push_str(&format!("<member><name>{}</name>", escape_xml(name)));
+                write_value(member_value, out);
+                out.push_str("</member>");
+            }
+            out.push_str("</struct>");
+        }
+        XmlRpcValue::Nil => out.push_str("<nil/>"),
+    }
+    out.push_str("</value>");
+}
+
+pub fn method_response(value: &XmlRpcValue) -> String {
+    let mut out = "<?xml version=\"1.0\"?>\n<methodResponse><params><param>".to_string();
+    write_value(value, &mut out);
+    out.push_str("</param></params></methodResponse>");
+    out
+}
+
+pub fn fault_response(code: i64, message: &str) -> String {
+    let mut out = "<?xml version=\"1.0\"?>\n<methodResponse><fault>".to_string();
+    write_value(
+        &XmlRpcValue::Struct(vec![
+            ("faultCode".to_string(), XmlRpcValue::Int(code)),
+            (
+                "faultString".to_string(),
+                XmlRpcValue::String(message.to_string()),
+            ),
+        ]),
+        &mut out,
+    );
+    out.push_str("</fault></methodResponse>");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAVE_DATA_CALL: &str = r#"<?xml version="1.0"?>
+<methodCall>
+  <methodName>grav.saveData</methodName>
+  <params>
+    <param>
+      <value><struct>
+        <member><name>password</name><value><string>s3cret &amp; more</string></value></member>
+        <member><name>data</name><value><base64>aGVs
+bG8=</base64></value></member>
+        <member><name>rating</name><value><int>0</int></value></member>
+        <member><name>addresses</name><value><array><data>
+          <value>ada@example.com</value>
+          <value><string>bob@example.com</string></value>
+        </data></array></value></member>
+      </struct></value>
+    </param>
+  </params>
+</methodCall>"#;
+
+    #[test]
+    fn parses_a_method_call() {
+        let call = parse_method_call(SAVE_DATA_CALL).unwrap();
+        assert_eq!(call.method_name, "grav.saveData");
+        let args = &call.params[0];
+        assert_eq!(
+            args.member("password").and_then(|value| value.as_str()),
+            Some("s3cret & more")
+        );
+        assert_eq!(
+            args.member("data").and_then(|value| value.as_bytes()),
+            Some(&b"hello".to_vec())
+        );
+        assert!(matches!(args.member("rating"), Some(XmlRpcValue::Int(0))));
+        let addresses: Vec<&str> = args
+            .member("addresses")
+            .and_then(|value| value.as_array())
+            .unwrap()
+            .iter()
+            .filter_map(|value| value.as_str())
+            .collect();
+        assert_eq!(addresses, vec!["ada@example.com", "bob@example.com"]);
+    }
+
+    #[test]
+    fn rejects_malformed_calls() {
+        assert!(parse_method_call("<methodCall><methodName>x</methodName>").is_err());
+        assert!(parse_method_call("<methodResponse></methodResponse>").is_err());
+        assert!(parse_method_call("<methodCall></methodCall>").is_err());
+        assert!(parse_method_call(
+            "<methodCall><methodName>x</methodName><params><param><value><int>nope</int></value></param></params></methodCall>"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn responses_round_trip_through_the_parser() {
+        let response = method_response(&XmlRpcValue::Struct(vec![(
+            "a<b".to_string(),
+            XmlRpcValue::Array(vec![XmlRpcValue::Boolean(true), XmlRpcValue::Nil]),
+        )]));
+        // reuse the call parser by swapping the envelope
+        let as_call = response
+            .replace("methodResponse>", "methodCall>")
+            .replace("<params>", "<methodName>r</methodName><params>");
+        let parsed = parse_method_call(&as_call).unwrap();
+        let member = parsed.params[0]
+            .member("a<b")
+            .and_then(|value| value.as_array());
+        assert!(matches!(
+            member.map(|values| values.as_slice()),
+            Some([XmlRpcValue::Boolean(true), XmlRpcValue::Nil])
+        ));
+    }
+
+    #[test]
+    fn faults_carry_code_and_escaped_message() {
+        let fault = fault_response(-9, "bad <password>");
+        assert!(fault.contains("<name>faultCode</name><value><int>-9</int></value>"));
+        assert!(fault.contains("<string>bad &lt;password&gt;</string>"));
+    }
+}
@@ -1,16 +1,34 @@
+use actix_multipart::form::MultipartFormConfig;
 use actix_web::{
     middleware::{from_fn, Logger},
     web, App, HttpServer,
 };
 use cloudinary::upload::Upload;
-use helpers::generate_id::Snowflake;
-use log::info;
+use config::{Config, Settings};
+use helpers::{
+    client_ip::TrustedProxies,
+    cors::public_cors,
+    csrf::TrustedOrigins,
+    dns_resolver::{HickorySrvResolver, SrvResolver},
+    generate_id::Snowflake,
+    mailer::Mailer,
+    oidc_client::{OidcProvider, ProviderCache},
+    password_policy::BreachedPasswords,
+    session_store::{FallbackSessionStore, PostgresSessionStore, RedisSessionStore, SessionStore},
+    signing_keys::SigningKeys,
+};
+use log::{info, warn};
+use middlewares::rate_limit::rate_limit;
 use sqlx::{postgres::PgPoolOptions, Pool, Postgres};
 use std::{
     env,
+    path::PathBuf,
     sync::{Arc, Mutex},
+    time::Duration,
 };
+use webauthn_rs::Webauthn;
 
+pub mod config;
 pub mod dbcalls;
 pub mod helpers;
 pub mod middlewares;
@@ -21,49 +39,187 @@ pub mod validation_types;
 
 pub struct AppState {
     pub database_connection_pool: Pool<Postgres>,
+    // also keys the consent form tokens, which never leave this server
     pub access_token_secret: String,
+    pub signing_keys: Arc<SigningKeys>,
     pub snow_flake: Arc<Mutex<Snowflake>>,
     pub redis_conn: r2d2::Pool<redis::Client>,
     pub cloudinary: Arc<Upload>,
+    pub cloudinary_cloud_name: String,
+    pub http_client: reqwest::Client,
+    pub export_dir: PathBuf,
+    pub public_url: String,
+    pub srv_resolver: Arc<dyn SrvResolver>,
+    pub session_store: Arc<dyn SessionStore>,
+    pub oidc_providers: Vec<OidcProvider>,
+    pub oidc_cache: Arc<ProviderCache>,
+    // none when the public url has no domain, browsers refuse passkeys on bare ips
+    pub webauthn: Option<Arc<Webauthn>>,
+    pub mailer: Arc<dyn Mailer>,
+    pub password_params: argon2::Params,
+    // stands in for missing passwords so failed sign ins all cost one argon2 verification
+    pub dummy_password_hash: String,
+    pub breached_passwords: Option<Arc<BreachedPasswords>>,
+    // zxcvbn score from 0 to 4 that new passwords must reach
+    pub min_password_score: u8,
+    // custom schemes native oauth clients may redirect to
+    pub oauth_redirect_schemes: Vec<String>,
+    pub trusted_proxies: TrustedProxies,
+    pub trusted_origins: TrustedOrigins,
+    pub access_token_lifetime_secs: i64,
+    // largest avatar accepted by every upload route
+    pub max_upload_bytes: usize,
+}
+
+// --config picks the toml file and --print-config shows what the server would run with
+struct Args {
+    config_file: Option<PathBuf>,
+    print_config: bool,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = Args {
+        config_file: None,
+        print_config: false,
+    };
+    let mut raw_args = env::args().skip(1);
+    while let Some(arg) = raw_args.next() {
+        match arg.as_str() {
+            "--config" => match raw_args.next() {
+                Some(path) => args.config_file = Some(PathBuf::from(path)),
+                None => return Err("--config needs a path".to_string()),
+            },
+            "--print-config" => args.print_config = true,
+            _ => return Err(format!("Unknown argument {}", arg)),
+        }
+    }
+    Ok(args)
+}
+
+fn exit_with_problems(problems: &[String]) -> ! {
+    eprintln!("Invalid configuration:");
+    for problem in problems {
+        eprintln!("  {}", problem);
+    }
+    std::process::exit(1)
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    dotenvy::dotenv().expect("Issue getting env files");
+    // the env file is optional now that settings can come from config.toml
+    dotenvy::dotenv().ok();
     env_logger::Builder::new().parse_filters("info").init();
 
-    let database_url = env::var("DATABASE_URL").expect("Database url not found in the env file");
-    let redis_url = env::var("REDIS_URL").expect("Redis url not found in the env file");
-    let access_token_secret =
-        env::var("ACCESS_SECRET").expect("Database url not found in the env file");
-    let cloudname =
-        env::var("CLOUDINARY_CLOUD_NAME").expect("Cloudinary cloud name not found in the env file");
-    let cloud_api_key =
-        env::var("CLOUDINARY_API_KEY").expect("Cloudinary api key not found in the env file");
-    let cloud_api_secret =
-        env::var("CLOUDINARY_API_SECRET").expect("Cloudinary api secret not found in the env file");
-    let machine_id: u64 = env::var("MACHINE_ID")
-        .expect("Machine id not found in the env file")
-        .parse()
-        .expect("Invalid machine id");
-
-    if machine_id > 1023 {
-        panic!("Machine id should be between 0 and 1024");
+    let args = parse_args().unwrap_or_else(|err_string| {
+        eprintln!(
+            "{}\nUsage: apiserver [--config <path>] [--print-config]",
+            err_string
+        );
+        std::process::exit(2)
+    });
+    let config = Config::load(args.config_file.as_deref(), &|name| env::var(name).ok())
+        .unwrap_or_else(|problems| exit_with_problems(&problems));
+    if args.print_config {
+        match config.redacted() {
+            Ok(printed) => print!("{}", printed),
+            Err(err_string) => exit_with_problems(&[err_string]),
+        }
+        if let Err(problems) = config.validate() {
+            exit_with_problems(&problems);
+        }
+        return Ok(());
+    }
+    let settings = config
+        .validate()
+        .unwrap_or_else(|problems| exit_with_problems(&problems));
+    let Settings {
+        bind_address,
+        public_url,
+        machine_id,
+        export_dir,
+        dns_nameserver,
+        max_upload_bytes,
+        trusted_proxies,
+        database_url,
+        database_max_connections,
+        redis_url,
+        redis_max_connections,
+        access_token_secret,
+        access_token_lifetime_secs,
+        signing_keys,
+        oauth_redirect_schemes,
+        trusted_origins,
+        cors_policy,
+        password_params,
+        breached_passwords,
+        min_password_score,
+        cloudinary_cloud_name: cloudname,
+        cloudinary_api_key: cloud_api_key,
+        cloudinary_api_secret: cloud_api_secret,
+        mailer,
+        oidc_providers,
+        rate_limits,
+    } = settings;
+
+    let oidc_cache = Arc::new(ProviderCache::default());
+    if let Some(breached_passwords) = &breached_passwords {
+        info!("Loaded {} breached passwords", breached_passwords.entries);
     }
 
-    let upload = Arc::new(Upload::new(cloud_api_key, cloudname, cloud_api_secret));
+    let webauthn = match helpers::webauthn::build_webauthn(&public_url) {
+        Ok(webauthn) => Some(Arc::new(webauthn)),
+        Err(err_string) => {
+            warn!("Passkeys are disabled: {}", err_string);
+            None
+        }
+    };
+
+    let dummy_password_hash = helpers::password::hash_password(
+        &helpers::generate_random_token::generate_random_token(),
+        &password_params,
+    )
+    .expect("Issue hashing the dummy password");
+
+    std::fs::create_dir_all(&export_dir)?;
+
+    let upload = Arc::new(Upload::new(
+        cloud_api_key,
+        cloudname.clone(),
+        cloud_api_secret,
+    ));
+    let http_client = reqwest::Client::new();
+    let srv_resolver: Arc<dyn SrvResolver> =
+        Arc::new(HickorySrvResolver::new(dns_nameserver).expect("Issue creating the dns resolver"));
 
     let pool = PgPoolOptions::new()
-        .max_connections(5)
+        .max_connections(database_max_connections)
         .connect(&database_url)
         .await
         .expect("Issue connecting to the database");
+    helpers::periodic_cleanup::spawn_periodic_cleanup(pool.clone());
 
     let redis_client = redis::Client::open(redis_url).expect("Issue creating redis client");
+    // a short checkout timeout lets requests fall back to postgres quickly when redis is down
     let redis_conn = r2d2::Pool::builder()
-        .max_size(5)
+        .max_size(redis_max_connections)
+        .connection_timeout(Duration::from_millis(500))
         .build(redis_client)
         .expect("Issue connecting to redis");
+    let fallback_session_store = Arc::new(FallbackSessionStore::new(
+        Box::new(RedisSessionStore::new(redis_conn.clone())),
+        Box::new(PostgresSessionStore::new(pool.clone())),
+        access_token_lifetime_secs,
+    ));
+    fallback_session_store.load_stale().await;
+    let retrying_session_store = fallback_session_store.clone();
+    actix_web::rt::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(30));
+        loop {
+            interval.tick().await;
+            retrying_session_store.retry_stale().await;
+        }
+    });
+    let session_store: Arc<dyn SessionStore> = fallback_session_store;
 
     info!("Starting Actix Web server...");
     let snowflake = Arc::new(Mutex::new(Snowflake {
@@ -77,53 +233,372 @@ async fn main() -> std::io::Result<()> {
             .app_data(web::Data::new(AppState {
                 database_connection_pool: pool.clone(),
                 access_token_secret: access_token_secret.clone(),
+                signing_keys: signing_keys.clone(),
                 snow_flake: snowflake.clone(),
                 redis_conn: redis_conn.clone(),
                 cloudinary: upload.clone(),
+                cloudinary_cloud_name: cloudname.clone(),
+                http_client: http_client.clone(),
+                export_dir: export_dir.clone(),
+                public_url: public_url.clone(),
+                srv_resolver: srv_resolver.clone(),
+                session_store: session_store.clone(),
+                oidc_providers: oidc_providers.clone(),
+                oidc_cache: oidc_cache.clone(),
+                webauthn: webauthn.clone(),
+                mailer: mailer.clone(),
+                password_params: password_params.clone(),
+                dummy_password_hash: dummy_password_hash.clone(),
+                breached_passwords: breached_passwords.clone(),
+                min_password_score,
+                oauth_redirect_schemes: oauth_redirect_schemes.clone(),
+                trusted_proxies: trusted_proxies.clone(),
+                trusted_origins: trusted_origins.clone(),
+                access_token_lifetime_secs,
+                max_upload_bytes,
             }))
+            .app_data(MultipartFormConfig::default().total_limit(max_upload_bytes))
             .service(
                 web::scope("/api/v1/user")
-                    .route(
-                        "/signup",
-                        web::post().to(routes::user::create_user::create_user),
+                    .wrap(cors_policy.api())
+                    .service(
+                        web::resource("/signup")
+                            .wrap(from_fn(rate_limit(rate_limits.signup_ip.clone())))
+                            .route(web::post().to(routes::user::create_user::create_user)),
+                    )
+                    .service(
+                        web::resource("/signin")
+                            .wrap(from_fn(rate_limit(rate_limits.signin_email.clone())))
+                            .wrap(from_fn(rate_limit(rate_limits.signin_ip.clone())))
+                            .route(web::post().to(routes::user::login_user::login_user)),
+                    )
+                    .service(
+                        web::resource("/signin/2fa")
+                            .wrap(from_fn(rate_limit(rate_limits.second_factor_ip.clone())))
+                            .route(web::post().to(routes::user::two_factor::verify_login)),
+                    )
+                    .service(
+                        web::resource("/signin/passkey/start")
+                            .wrap(from_fn(rate_limit(rate_limits.signin_email.clone())))
+                            .wrap(from_fn(rate_limit(rate_limits.signin_ip.clone())))
+                            .route(web::post().to(routes::user::passkeys::start_signin)),
+                    )
+                    .service(
+                        web::resource("/signin/passkey/finish")
+                            .wrap(from_fn(rate_limit(rate_limits.second_factor_ip.clone())))
+                            .route(web::post().to(routes::user::passkeys::finish_signin)),
+                    )
+                    .service(
+                        web::resource("/signin/link")
+                            .wrap(from_fn(rate_limit(rate_limits.signin_email.clone())))
+                            .wrap(from_fn(rate_limit(rate_limits.signin_ip.clone())))
+                            .route(web::post().to(routes::user::magic_link::request_magic_link)),
+                    )
+                    // registered before the token route so 2fa is not taken for a token
+                    .service(
+                        web::resource("/signin/link/2fa")
+                            .wrap(from_fn(rate_limit(rate_limits.second_factor_ip.clone())))
+                            .route(
+                                web::post()
+                                    .to(routes::user::magic_link::verify_magic_link_second_factor),
+                            ),
+                    )
+                    .service(
+                        web::resource("/signin/link/{token}")
+                            .wrap(from_fn(rate_limit(rate_limits.link_ip.clone())))
+                            .route(web::get().to(routes::user::magic_link::confirm_magic_link))
+                            .route(
+                                web::post().to(routes::user::magic_link::sign_in_with_magic_link),
+                            ),
+                    )
+                    .service(
+                        web::resource("/signin/refresh")
+                            .wrap(from_fn(rate_limit(rate_limits.token_ip.clone())))
+                            .route(web::post().to(routes::user::refresh_token::refresh_token)),
                     )
-                    .route(
-                        "/signin",
-                        web::post().to(routes::user::login_user::login_user),
+                    .service(
+                        web::scope("/oidc")
+                            .wrap(from_fn(rate_limit(rate_limits.oidc_ip.clone())))
+                            .route(
+                                "/providers",
+                                web::get().to(routes::user::oidc_login::list_providers),
+                            )
+                            .route(
+                                "/link/{token}",
+                                web::get().to(routes::user::oidc_login::confirm_link),
+                            )
+                            .route(
+                                "/{provider}/login",
+                                web::get().to(routes::user::oidc_login::start_login),
+                            )
+                            .route(
+                                "/{provider}/callback",
+                                web::get().to(routes::user::oidc_login::login_callback),
+                            ),
+                    )
+                    .service(
+                        web::resource("/unlock/{token}")
+                            .wrap(from_fn(rate_limit(rate_limits.link_ip.clone())))
+                            .route(web::get().to(routes::user::unlock_account::unlock_account)),
+                    )
+                    .service(
+                        web::resource("/export/download/{token}")
+                            .wrap(from_fn(rate_limit(rate_limits.link_ip.clone())))
+                            .route(web::get().to(routes::user::download_export::download_export)),
                     )
                     .service(
                         web::scope("/protected")
+                            .wrap(from_fn(rate_limit(rate_limits.api_user.clone())))
+                            .wrap(from_fn(middlewares::auth_middleware::require_session))
                             .wrap(from_fn(middlewares::auth_middleware::auth_middleware))
+                            .wrap(from_fn(middlewares::csrf::csrf_protection))
                             .route(
                                 "/currentUser",
                                 web::get().to(routes::user::current_user::get_current_user),
+                            )
+                            .route(
+                                "/export",
+                                web::post().to(routes::user::request_export::request_export),
+                            )
+                            .route(
+                                "/export/{export_id}",
+                                web::get().to(routes::user::export_status::get_export_status),
+                            )
+                            .route(
+                                "/sessions",
+                                web::get().to(routes::user::sessions::list_sessions),
+                            )
+                            .route(
+                                "/sessions/revoke-all",
+                                web::post().to(routes::user::sessions::revoke_all_sessions),
+                            )
+                            .route(
+                                "/sessions/{session_id}",
+                                web::delete().to(routes::user::sessions::revoke_session),
+                            )
+                            .route("/logout", web::post().to(routes::user::sessions::logout))
+                            .service(
+                                web::resource("/reauthenticate")
+                                    .wrap(from_fn(rate_limit(rate_limits.second_factor_ip.clone())))
+                                    .route(
+                                        web::post()
+                                            .to(routes::user::reauthenticate::reauthenticate),
+                                    ),
+                            )
+                            .route(
+                                "/password",
+                                web::post().to(routes::user::change_password::change_password),
+                            )
+                            .route(
+                                "/passkeys",
+                                web::get().to(routes::user::passkeys::list_passkeys),
+                            )
+                            .route(
+                                "/passkeys/register/start",
+                                web::post().to(routes::user::passkeys::start_registration),
+                            )
+                            .route(
+                                "/passkeys/register/finish",
+                                web::post().to(routes::user::passkeys::finish_registration),
+                            )
+                            .route(
+                                "/passkeys/{passkey_id}",
+                                web::delete().to(routes::user::passkeys::delete_passkey),
+                            )
+                            .route(
+                                "/2fa/totp/enroll",
+                                web::post().to(routes::user::two_factor::enroll_totp),
+                            )
+                            .route(
+                                "/2fa/totp/confirm",
+                                web::post().to(routes::user::two_factor::confirm_totp),
+                            )
+                            .route(
+                                "/2fa/disable",
+                                web::post().to(routes::user::two_factor::disable_two_factor),
+                            )
+                            .route(
+                                "/tokens",
+                                web::get().to(routes::user::api_tokens::list_api_tokens),
+                            )
+                            .route(
+                                "/tokens",
+                                web::post().to(routes::user::api_tokens::create_api_token),
+                            )
+                            .route(
+                                "/tokens/{token_id}",
+                                web::delete().to(routes::user::api_tokens::revoke_api_token),
                             ),
                     ),
             )
             .service(
-                web::scope("/api/v1/profile").service(
+                web::scope("/api/v1/profile")
+                    .wrap(cors_policy.api())
+                    .service(
+                        web::scope("/protected")
+                            .wrap(from_fn(rate_limit(rate_limits.api_user.clone())))
+                            .wrap(from_fn(middlewares::auth_middleware::auth_middleware))
+                            .wrap(from_fn(middlewares::csrf::csrf_protection))
+                            .route(
+                                "/add-image",
+                                web::post().to(routes::profile::add_image::add_image),
+                            )
+                            .route(
+                                "/get-images",
+                                web::get().to(routes::profile::get_images::get_imgages),
+                            )
+                            .route(
+                                "/update-image",
+                                web::put()
+                                    .to(routes::profile::update_profile::update_profile_image),
+                            )
+                            .route(
+                                "/details",
+                                web::get().to(routes::profile::get_details::get_details),
+                            )
+                            .route(
+                                "/details",
+                                web::put().to(routes::profile::update_details::update_details),
+                            )
+                            .route(
+                                "/details/visibility",
+                                web::put()
+                                    .to(routes::profile::update_visibility::update_visibility),
+                            )
+                            .route(
+                                "/openid-urls",
+                                web::get().to(routes::profile::openid_urls::get_openid_urls),
+                            )
+                            .route(
+                                "/openid-urls",
+                                web::post().to(routes::profile::openid_urls::add_openid_url),
+                            )
+                            .route(
+                                "/openid-urls/{openid_url_id}",
+                                web::delete().to(routes::profile::openid_urls::delete_openid_url),
+                            ),
+                    ),
+            )
+            .service(
+                web::scope("/v3")
+                    .service(
+                        web::resource("/profiles/{email_hash}")
+                            .wrap(from_fn(rate_limit(rate_limits.public_ip.clone())))
+                            .wrap(public_cors())
+                            .route(web::get().to(routes::v3::get_profile::get_profile)),
+                    )
+                    .service(
+                        web::scope("/me")
+                            .wrap(from_fn(rate_limit(rate_limits.api_user.clone())))
+                            .wrap(from_fn(middlewares::auth_middleware::auth_middleware))
+                            .wrap(from_fn(middlewares::csrf::csrf_protection))
+                            .route(
+                                "/avatars",
+                                web::get().to(routes::v3::list_avatars::list_avatars),
+                            )
+                            .route(
+                                "/avatars",
+                                web::post().to(routes::v3::upload_avatar::upload_avatar),
+                            )
+                            .route(
+                                "/avatars/{image_id}/email",
+                                web::post().to(routes::v3::select_avatar::select_avatar),
+                            ),
+                    ),
+            )
+            .service(
+                web::scope("/api/v1/oauth").wrap(cors_policy.api()).service(
                     web::scope("/protected")
+                        .wrap(from_fn(middlewares::auth_middleware::require_session))
                         .wrap(from_fn(middlewares::auth_middleware::auth_middleware))
+                        .wrap(from_fn(middlewares::csrf::csrf_protection))
+                        .route(
+                            "/clients",
+                            web::get().to(routes::oauth::clients::list_clients),
+                        )
                         .route(
-                            "/add-image",
-                            web::post().to(routes::profile::add_image::add_image),
+                            "/clients",
+                            web::post().to(routes::oauth::clients::register_client),
                         )
                         .route(
-                            "/get-images",
-                            web::get().to(routes::profile::get_images::get_imgages),
+                            "/clients/{id}",
+                            web::delete().to(routes::oauth::clients::delete_client),
                         )
                         .route(
-                            "/update-image",
-                            web::put().to(routes::profile::update_profile::update_profile_image),
+                            "/authorizations",
+                            web::get().to(routes::oauth::authorizations::list_authorizations),
+                        )
+                        .route(
+                            "/authorizations/{client_id}",
+                            web::delete().to(routes::oauth::authorizations::revoke_authorization),
                         ),
                 ),
             )
+            .service(
+                web::scope("/oauth")
+                    .service(
+                        web::resource("/token")
+                            .wrap(from_fn(rate_limit(rate_limits.token_ip.clone())))
+                            .route(web::post().to(routes::oauth::token::token)),
+                    )
+                    .service(
+                        web::resource("/authorize")
+                            .wrap(from_fn(middlewares::auth_middleware::require_session))
+                            .wrap(from_fn(middlewares::auth_middleware::auth_middleware))
+                            .route(web::get().to(routes::oauth::authorize::authorize))
+                            .route(web::post().to(routes::oauth::authorize::submit_consent)),
+                    )
+                    .service(
+                        web::resource("/userinfo")
+                            .wrap(from_fn(middlewares::auth_middleware::auth_middleware))
+                            .route(web::get().to(routes::oauth::userinfo::userinfo))
+                            .route(web::post().to(routes::oauth::userinfo::userinfo)),
+                    ),
+            )
+            .route(
+                "/.well-known/openid-configuration",
+                web::get().to(routes::oauth::discovery::openid_configuration),
+            )
             .route(
-                "/{email_hash}",
-                web::get().to(routes::profile::fetch_image::get_profile_image),
+                "/.well-known/jwks.json",
+                web::get().to(routes::oauth::discovery::jwks),
+            )
+            .service(
+                web::resource("/avatar")
+                    .wrap(from_fn(rate_limit(rate_limits.public_ip.clone())))
+                    .wrap(public_cors())
+                    .route(web::get().to(routes::avatar::federated_avatar::get_federated_avatar)),
+            )
+            .service(
+                web::resource("/avatar/{hash}")
+                    .wrap(from_fn(rate_limit(rate_limits.public_ip.clone())))
+                    .wrap(public_cors())
+                    .route(web::get().to(routes::avatar::get_avatar::get_avatar)),
+            )
+            .service(
+                web::resource("/xmlrpc")
+                    // uploads arrive base64 encoded inside the xml
+                    .app_data(web::PayloadConfig::new(
+                        max_upload_bytes / 3 * 4 + 64 * 1024,
+                    ))
+                    .wrap(from_fn(rate_limit(rate_limits.signin_email.clone())))
+                    .wrap(from_fn(rate_limit(rate_limits.signin_ip.clone())))
+                    .route(web::post().to(routes::xmlrpc::handle_call::handle_xmlrpc_call)),
+            )
+            .service(
+                web::resource("/{email_hash}.{format}")
+                    .wrap(from_fn(rate_limit(rate_limits.public_ip.clone())))
+                    .wrap(public_cors())
+                    .route(web::get().to(routes::profile::fetch_profile_data::get_profile_data)),
+            )
+            .service(
+                web::resource("/{email_hash}")
+                    .wrap(from_fn(rate_limit(rate_limits.public_ip.clone())))
+                    .wrap(public_cors())
+                    .route(web::get().to(routes::profile::fetch_image::get_profile_image)),
             )
     })
-    .bind(("127.0.0.1", 8000))?
+    .bind(bind_address)?
     .run()
     .await
 }
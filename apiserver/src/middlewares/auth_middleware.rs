@@ -1,37 +1,115 @@
 use actix_web::{
     body::BoxBody,
     dev::{ServiceRequest, ServiceResponse},
+    http::header,
     middleware::Next,
     web::Data,
     Error, HttpMessage, HttpResponse,
 };
-use redis::Commands;
 use serde::Serialize;
 
-use crate::{responses::general_error::GeneralError, AppState};
+use crate::{
+    helpers::api_scopes::{API_TOKEN_PREFIX, OAUTH_TOKEN_PREFIX},
+    responses::general_error::GeneralError,
+    AppState,
+};
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 pub struct UserData {
     pub email: String,
     pub user_id: i64,
+    // set for browser and app sign ins
+    pub session_id: Option<i64>,
+    // set for personal access tokens, sessions are not limited by scope
+    pub scopes: Option<Vec<String>>,
 }
 
-pub async fn auth_middleware(
+impl UserData {
+    pub fn has_scope(&self, scope: &str) -> bool {
+        match &self.scopes {
+            None => true,
+            Some(scopes) => scopes.iter().any(|granted| granted == scope),
+        }
+    }
+}
+
+// handlers reachable with a personal access token return this when the scope is missing
+pub fn insufficient_scope(scope: &str) -> HttpResponse {
+    HttpResponse::Forbidden()
+        .insert_header((
+            header::WWW_AUTHENTICATE,
+            format!(
+                "Bearer realm=\"gravatar\", error=\"insufficient_scope\", scope=\"{}\"",
+                scope
+            ),
+        ))
+        .json(GeneralError {
+            message: format!("Token is missing the {} scope", scope),
+        })
+}
+
+// rfc 6750 challenge, the error code is left out when no credentials were sent
+fn bearer_error(
     req: ServiceRequest,
-    next: Next<BoxBody>,
-) -> Result<ServiceResponse<impl actix_web::body::MessageBody>, Error> {
-    if req.cookie("accessToken").is_none() {
-        let error_response = HttpResponse::Unauthorized().json(GeneralError {
-            message: "Unauthorized: Missing accessToken cookie".to_string(),
-        });
-        return Ok(req.into_response(error_response.map_into_boxed_body()));
+    error: Option<&str>,
+    message: &str,
+) -> ServiceResponse<BoxBody> {
+    let mut challenge = "Bearer realm=\"gravatar\"".to_string();
+    if let Some(error) = error {
+        challenge.push_str(&format!(
+            ", error=\"{}\", error_description=\"{}\"",
+            error, message
+        ));
     }
-    if req.cookie("userId").is_none() {
-        let error_response = HttpResponse::Unauthorized().json(GeneralError {
-            message: "Unauthorized: Missing userId cookie".to_string(),
+    let mut error_response = if error == Some("invalid_request") {
+        HttpResponse::BadRequest()
+    } else {
+        HttpResponse::Unauthorized()
+    };
+    let error_response = error_response
+        .insert_header((header::WWW_AUTHENTICATE, challenge))
+        .json(GeneralError {
+            message: message.to_string(),
         });
-        return Ok(req.into_response(error_response.map_into_boxed_body()));
+    req.into_response(error_response.map_into_boxed_body())
+}
+
+// the authorization header wins over the cookie when both are present
+fn extract_token(req: &ServiceRequest) -> Result<Option<String>, String> {
+    if let Some(authorization) = req.headers().get(header::AUTHORIZATION) {
+        let authorization = match authorization.to_str() {
+            Ok(authorization) => authorization,
+            Err(_) => return Err("Malformed Authorization header".to_string()),
+        };
+        return match authorization.split_once(' ') {
+            Some((scheme, token))
+                if scheme.eq_ignore_ascii_case("bearer") && !token.trim().is_empty() =>
+            {
+                Ok(Some(token.trim().to_string()))
+            }
+            _ => Err("Authorization header must use the Bearer scheme".to_string()),
+        };
     }
+    Ok(req
+        .cookie("accessToken")
+        .map(|cookie| cookie.value().to_string()))
+}
+
+pub async fn auth_middleware(
+    req: ServiceRequest,
+    next: Next<BoxBody>,
+) -> Result<ServiceResponse<impl actix_web::body::MessageBody>, Error> {
+    let token = match extract_token(&req) {
+        Ok(Some(token)) => token,
+        Ok(None) => {
+            return Ok(bearer_error(
+                req,
+                None,
+                "Unauthorized: Missing access token",
+            ))
+        }
+        Err(err_string) => return Ok(bearer_error(req, Some("invalid_request"), &err_string)),
+    };
 
     let state = match req.app_data::<Data<AppState>>() {
         Some(data) => data,
@@ -43,60 +121,82 @@ pub async fn auth_middleware(
         }
     };
 
-    let token = req.cookie("accessToken").unwrap().value().to_string();
-    let user_id = req.cookie("userId").unwrap().value().to_string();
-    let token_eval_result =
-        crate::helpers::validate_token::validate_token(&token, &state.access_token_secret);
-
-    if token_eval_result.is_err() {
-        let error_response = HttpResponse::Unauthorized().json(GeneralError {
-            message: token_eval_result.unwrap_err(),
-        });
-        return Ok(req.into_response(error_response.map_into_boxed_body()));
-    }
-
-    let claims = token_eval_result.unwrap();
-    let redis_connection_result = state.redis_conn.get();
-
-    // use redis to authenticate
-    if redis_connection_result.is_ok() {
-        let key = format!("auth:{}", user_id);
-        let token_redis_result: Result<String, _> = redis_connection_result.unwrap().get(key);
-        if let Ok(token_redis) = token_redis_result {
-            if token_redis == token {
-                req.extensions_mut().insert(UserData {
-                    user_id: claims.user_id,
-                    email: claims.email,
-                });
-                return next.call(req).await;
-            } else {
-                let error_response = HttpResponse::Unauthorized().json(GeneralError {
-                    message: "Token did not match, login again".to_string(),
+    if token.starts_with(API_TOKEN_PREFIX) || token.starts_with(OAUTH_TOKEN_PREFIX) {
+        let owner = match crate::dbcalls::find_api_token::find_api_token(&token, state).await {
+            Ok(Some(owner)) => owner,
+            Ok(None) => {
+                return Ok(bearer_error(
+                    req,
+                    Some("invalid_token"),
+                    "Token is revoked, expired or unknown",
+                ))
+            }
+            Err(err_string) => {
+                let error_response = HttpResponse::InternalServerError().json(GeneralError {
+                    message: err_string,
                 });
                 return Ok(req.into_response(error_response.map_into_boxed_body()));
             }
-        }
+        };
+        req.extensions_mut().insert(UserData {
+            user_id: owner.user_id,
+            email: owner.email,
+            session_id: None,
+            scopes: Some(owner.scopes),
+        });
+        return next.call(req).await;
     }
 
-    // redis is not connected so use this alternate way
-    let user_exists =
-        crate::dbcalls::check_user_exists::check_user_exists(claims.user_id, &claims.email, state)
-            .await;
+    let token_eval_result =
+        crate::helpers::validate_token::validate_token(&token, &state.signing_keys);
+
+    let claims = match token_eval_result {
+        Ok(claims) => claims,
+        Err(err_string) => return Ok(bearer_error(req, Some("invalid_token"), &err_string)),
+    };
 
-    match user_exists {
+    // the store reports revoked, expired and unknown sessions alike
+    let session_valid = state.session_store.verify(claims.sid, &token).await;
+    match session_valid {
         Err(err_string) => {
-            let error_response = HttpResponse::BadRequest().json(GeneralError {
+            let error_response = HttpResponse::InternalServerError().json(GeneralError {
                 message: err_string,
             });
             Ok(req.into_response(error_response.map_into_boxed_body()))
         }
-        Ok(_) => {
+        Ok(false) => Ok(bearer_error(
+            req,
+            Some("invalid_token"),
+            "Session is no longer valid, login again",
+        )),
+        Ok(true) => {
+            crate::dbcalls::manage_sessions::touch_session(claims.sid, state).await;
             req.extensions_mut().insert(UserData {
                 user_id: claims.user_id,
                 email: claims.email,
+                session_id: Some(claims.sid),
+                scopes: None,
             });
 
             next.call(req).await
         }
     }
 }
+
+// account management stays out of reach of personal access tokens
+pub async fn require_session(
+    req: ServiceRequest,
+    next: Next<BoxBody>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let has_session = req
+        .extensions()
+        .get::<UserData>()
+        .is_some_and(|user_data| user_data.session_id.is_some());
+    if !has_session {
+        let error_response = HttpResponse::Forbidden().json(GeneralError {
+            message: "This route requires signing in, api tokens are not accepted".to_string(),
+        });
+        return Ok(req.into_response(error_response.map_into_boxed_body()));
+    }
+    next.call(req).await
+}
@@ -0,0 +1,47 @@
+use actix_web::{
+    body::{BoxBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    middleware::Next,
+    web::Data,
+    Error, HttpResponse,
+};
+
+use crate::{
+    helpers::csrf::{check_request, CSRF_COOKIE},
+    responses::general_error::GeneralError,
+    AppState,
+};
+
+// wrap it outside the auth middleware so forged requests are turned away before
+// any session lookup
+pub async fn csrf_protection(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let app_state = match req.app_data::<Data<AppState>>() {
+        Some(app_state) => app_state.clone(),
+        None => {
+            let error_response = HttpResponse::InternalServerError().json(GeneralError {
+                message: "Failed to retrieve application state".to_string(),
+            });
+            return Ok(req.into_response(error_response.map_into_boxed_body()));
+        }
+    };
+
+    let session_cookie = req.cookie("accessToken");
+    let csrf_cookie = req.cookie(CSRF_COOKIE);
+    let check_res = check_request(
+        &app_state.trusted_origins,
+        req.method(),
+        req.headers(),
+        session_cookie.as_ref().map(|cookie| cookie.value()),
+        csrf_cookie.as_ref().map(|cookie| cookie.value()),
+    );
+    if let Err(err_string) = check_res {
+        let error_response = HttpResponse::Forbidden().json(GeneralError {
+            message: err_string,
+        });
+        return Ok(req.into_response(error_response.map_into_boxed_body()));
+    }
+    Ok(next.call(req).await?.map_into_boxed_body())
+}
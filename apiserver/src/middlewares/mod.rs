@@ -1 +1,3 @@
 pub mod auth_middleware;
+pub mod csrf;
+pub mod rate_limit;
@@ -0,0 +1,243 @@
+use std::{future::Future, pin::Pin};
+
+use actix_web::{
+    body::BoxBody,
+    dev::{ServiceRequest, ServiceResponse},
+    http::header::{HeaderName, HeaderValue, RETRY_AFTER},
+    middleware::Next,
+    web::{self, Bytes, Data},
+    Error, HttpMessage, HttpResponse,
+};
+use log::warn;
+
+use crate::{
+    helpers::rate_limit::{check_rate_limit, RateLimitDecision, RateLimitKey, RateLimitPolicy},
+    middlewares::auth_middleware::UserData,
+    responses::general_error::GeneralError,
+    AppState,
+};
+
+type MiddlewareFuture = Pin<Box<dyn Future<Output = Result<ServiceResponse<BoxBody>, Error>>>>;
+
+#[derive(serde::Deserialize)]
+struct EmailField {
+    email: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct AccountQuery {
+    user: String,
+}
+
+// wrap a resource or scope with from_fn(rate_limit(policy)), wrap it inside the
+// auth middleware for user keyed policies so the user is known by then
+pub fn rate_limit(
+    policy: RateLimitPolicy,
+) -> impl Fn(ServiceRequest, Next<BoxBody>) -> MiddlewareFuture + 'static {
+    move |req, next| Box::pin(enforce(policy.clone(), req, next))
+}
+
+fn client_ip(req: &ServiceRequest, app_state: &AppState) -> String {
+    app_state
+        .trusted_proxies
+        .client_ip(req.peer_addr(), req.headers())
+}
+
+// the body is read here and handed back untouched for the handler
+async fn email_from_body(req: &mut ServiceRequest) -> Option<String> {
+    let body = req.extract::<Bytes>().await.ok()?;
+    let email = serde_json::from_slice::<EmailField>(&body)
+        .ok()
+        .and_then(|fields| fields.email);
+    let (_, mut payload) = actix_http::h1::Payload::create(true);
+    payload.unread_data(body);
+    req.set_payload(payload.into());
+    email
+}
+
+// xml-rpc names the account by its email hash in the query instead
+fn account_hash_from_query(req: &ServiceRequest) -> Option<String> {
+    actix_web::web::Query::<AccountQuery>::from_query(req.query_string())
+        .ok()
+        .map(|query| query.into_inner().user.to_lowercase())
+        .filter(|user| !user.is_empty())
+}
+
+async fn subject(
+    policy: &RateLimitPolicy,
+    req: &mut ServiceRequest,
+    app_state: &AppState,
+) -> String {
+    match policy.key {
+        RateLimitKey::Ip => format!("ip:{}", client_ip(req, app_state)),
+        RateLimitKey::User => {
+            let user_id = req
+                .extensions()
+                .get::<UserData>()
+                .map(|user_data| user_data.user_id);
+            match user_id {
+                Some(user_id) => format!("user:{}", user_id),
+                None => format!("ip:{}", client_ip(req, app_state)),
+            }
+        }
+        // hashed so addresses never sit in redis keys
+        RateLimitKey::Email => match email_from_body(req).await {
+            Some(email) => format!(
+                "email:{}",
+                crate::helpers::hash_token::hash_token(&email.trim().to_lowercase())
+            ),
+            None => match account_hash_from_query(req) {
+                Some(account_hash) => format!("account:{}", account_hash),
+                None => format!("ip:{}", client_ip(req, app_state)),
+            },
+        },
+    }
+}
+
+fn add_headers(
+    response: &mut HttpResponse<BoxBody>,
+    policy: &RateLimitPolicy,
+    decision: &RateLimitDecision,
+) {
+    let headers = response.headers_mut();
+    for (name, value) in [
+        ("ratelimit-limit", decision.limit.to_string()),
+        ("ratelimit-remaining", decision.remaining.to_string()),
+        ("ratelimit-reset", decision.reset_secs.to_string()),
+        (
+            "ratelimit-policy",
+            format!("{};w={}", policy.limit, policy.window_secs),
+        ),
+    ] {
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            headers.insert(HeaderName::from_static(name), value);
+        }
+    }
+}
+
+fn limited_response(policy: &RateLimitPolicy, decision: &RateLimitDecision) -> HttpResponse {
+    let mut error_response = HttpResponse::TooManyRequests()
+        .insert_header((RETRY_AFTER, decision.reset_secs.to_string()))
+        .json(GeneralError {
+            message: "Too many requests, try again later".to_string(),
+        });
+    add_headers(&mut error_response, policy, decision);
+    error_response
+}
+
+async fn enforce(
+    policy: RateLimitPolicy,
+    mut req: ServiceRequest,
+    next: Next<BoxBody>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let app_state = match req.app_data::<Data<AppState>>() {
+        Some(app_state) => app_state.clone(),
+        None => return next.call(req).await,
+    };
+    let subject = subject(&policy, &mut req, &app_state).await;
+
+    // the redis calls block, so they run off the worker threads
+    let redis_conn = app_state.redis_conn.clone();
+    let blocking_policy = policy.clone();
+    let decision_res =
+        web::block(move || check_rate_limit(&redis_conn, &blocking_policy, &subject)).await;
+
+    // an unreachable redis lets traffic through rather than taking sign in down with it
+    let decision = match decision_res {
+        Ok(Ok(decision)) => decision,
+        Ok(Err(err_string)) => {
+            warn!("Rate limit {} not applied: {}", policy.name, err_string);
+            return next.call(req).await;
+        }
+        Err(_) => {
+            warn!(
+                "Rate limit {} not applied: the check did not finish",
+                policy.name
+            );
+            return next.call(req).await;
+        }
+    };
+
+    if !decision.allowed {
+        return Ok(req.into_response(limited_response(&policy, &decision)));
+    }
+
+    let mut res = next.call(req).await?;
+    add_headers(res.response_mut(), &policy, &decision);
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+
+    use super::*;
+
+    #[actix_web::test]
+    async fn email_from_body_leaves_the_body_for_the_handler() {
+        let body = r#"{"email": "Ada@Example.com", "password": "secret"}"#;
+        let mut req = TestRequest::post()
+            .insert_header(("content-type", "application/json"))
+            .set_payload(body)
+            .to_srv_request();
+
+        assert_eq!(
+            email_from_body(&mut req).await.as_deref(),
+            Some("Ada@Example.com")
+        );
+        let replayed = req.extract::<Bytes>().await.unwrap();
+        assert_eq!(replayed, Bytes::from(body));
+    }
+
+    #[actix_web::test]
+    async fn email_from_body_tolerates_other_bodies() {
+        let mut req = TestRequest::post()
+            .set_payload("<methodCall/>")
+            .to_srv_request();
+        assert_eq!(email_from_body(&mut req).await, None);
+        let replayed = req.extract::<Bytes>().await.unwrap();
+        assert_eq!(replayed, Bytes::from("<methodCall/>"));
+    }
+
+    #[test]
+    fn limited_responses_say_when_to_retry() {
+        let policy = RateLimitPolicy {
+            name: "signin-ip",
+            key: RateLimitKey::Ip,
+            limit: 30,
+            window_secs: 60,
+        };
+        let decision = RateLimitDecision {
+            allowed: false,
+            limit: 30,
+            remaining: 0,
+            reset_secs: 17,
+        };
+        let response = limited_response(&policy, &decision);
+        assert_eq!(
+            response.status(),
+            actix_web::http::StatusCode::TOO_MANY_REQUESTS
+        );
+        let header = |name: &str| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.to_string())
+        };
+        assert_eq!(header("retry-after").as_deref(), Some("17"));
+        assert_eq!(header("ratelimit-remaining").as_deref(), Some("0"));
+        assert_eq!(header("ratelimit-reset").as_deref(), Some("17"));
+        assert_eq!(header("ratelimit-policy").as_deref(), Some("30;w=60"));
+    }
+
+    #[test]
+    fn account_hash_comes_from_the_user_query() {
+        let req = TestRequest::post()
+            .uri("/xmlrpc?user=ABC123")
+            .to_srv_request();
+        assert_eq!(account_hash_from_query(&req).as_deref(), Some("abc123"));
+        let req = TestRequest::post().uri("/xmlrpc?user=").to_srv_request();
+        assert_eq!(account_hash_from_query(&req), None);
+    }
+}
@@ -0,0 +1,15 @@
+use sqlx::prelude::FromRow;
+
+#[derive(FromRow, serde::Deserialize, Debug, serde::Serialize)]
+pub struct ApiTokenFromDB {
+    pub id: i64,
+    pub user_id: i64,
+    pub name: String,
+    pub token_hash: String,
+    pub scopes: Vec<String>,
+    pub created_at: i64,
+    pub expires_at: Option<i64>,
+    pub last_used_at: Option<i64>,
+    pub revoked_at: Option<i64>,
+    pub oauth_client_id: Option<i64>,
+}
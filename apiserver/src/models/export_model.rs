@@ -0,0 +1,12 @@
+use sqlx::prelude::FromRow;
+
+#[derive(FromRow, serde::Deserialize, Debug, serde::Serialize)]
+pub struct DataExportFromDB {
+    pub id: i64,
+    pub user_id: i64,
+    pub status: String,
+    pub download_token: Option<String>,
+    pub file_path: Option<String>,
+    pub created_at: i64,
+    pub expires_at: Option<i64>,
+}
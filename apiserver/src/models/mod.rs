@@ -1,2 +1,10 @@
+pub mod api_token_model;
+pub mod export_model;
+pub mod oauth_client_model;
+pub mod openid_url_model;
+pub mod passkey_model;
 pub mod profile_model;
+pub mod public_profile_model;
+pub mod refresh_token_model;
+pub mod session_model;
 pub mod user_model;
@@ -0,0 +1,41 @@
+use sqlx::prelude::FromRow;
+
+#[derive(FromRow, serde::Deserialize, Debug, serde::Serialize)]
+pub struct OauthClientFromDB {
+    pub id: i64,
+    pub user_id: i64,
+    pub name: String,
+    pub client_id: String,
+    pub client_secret_hash: Option<String>,
+    pub redirect_uris: Vec<String>,
+    pub created_at: i64,
+}
+
+#[derive(FromRow, serde::Deserialize, Debug, serde::Serialize)]
+pub struct AuthorizationCodeFromDB {
+    pub id: i64,
+    pub code_hash: String,
+    pub client_id: i64,
+    pub user_id: i64,
+    pub redirect_uri: Option<String>,
+    pub scopes: Vec<String>,
+    pub code_challenge: String,
+    pub nonce: Option<String>,
+    pub created_at: i64,
+    pub expires_at: i64,
+    pub used_at: Option<i64>,
+}
+
+#[derive(FromRow, serde::Deserialize, Debug, serde::Serialize)]
+pub struct OauthRefreshTokenFromDB {
+    pub id: i64,
+    pub token_hash: String,
+    pub family_id: i64,
+    pub client_id: i64,
+    pub user_id: i64,
+    pub scopes: Vec<String>,
+    pub created_at: i64,
+    pub expires_at: i64,
+    pub used_at: Option<i64>,
+    pub revoked: bool,
+}
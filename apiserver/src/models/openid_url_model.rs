@@ -0,0 +1,9 @@
+use sqlx::prelude::FromRow;
+
+#[derive(FromRow, serde::Deserialize, Debug, serde::Serialize)]
+pub struct OpenidUrlFromDB {
+    pub id: i64,
+    pub user_id: i64,
+    pub url: String,
+    pub url_hash: String,
+}
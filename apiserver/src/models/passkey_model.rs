@@ -0,0 +1,13 @@
+use sqlx::prelude::FromRow;
+
+#[derive(FromRow, serde::Deserialize, Debug, serde::Serialize)]
+pub struct PasskeyFromDB {
+    pub id: i64,
+    pub user_id: i64,
+    pub name: String,
+    pub credential_id: String,
+    // the webauthn credential as json, including its public key and counter
+    pub passkey: String,
+    pub created_at: i64,
+    pub last_used_at: Option<i64>,
+}
@@ -0,0 +1,134 @@
+use sqlx::prelude::FromRow;
+
+#[derive(FromRow, serde::Deserialize, Debug, serde::Serialize, Default)]
+pub struct PublicProfileFromDB {
+    pub user_id: i64,
+    pub display_name: Option<String>,
+    pub about_me: Option<String>,
+    pub location: Option<String>,
+    pub pronouns: Option<String>,
+    pub job_title: Option<String>,
+    pub company: Option<String>,
+    pub display_name_public: bool,
+    pub about_me_public: bool,
+    pub location_public: bool,
+    pub pronouns_public: bool,
+    pub job_title_public: bool,
+    pub company_public: bool,
+    pub links_public: bool,
+}
+
+#[derive(FromRow, serde::Deserialize, Debug, serde::Serialize, Clone)]
+pub struct ProfileLinkFromDB {
+    pub id: i64,
+    pub user_id: i64,
+    pub label: String,
+    pub url: String,
+    pub position: i32,
+}
+
+#[derive(serde::Serialize)]
+pub struct ProfileLink {
+    pub label: String,
+    pub url: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct ProfileVisibility {
+    pub display_name: bool,
+    pub about_me: bool,
+    pub location: bool,
+    pub pronouns: bool,
+    pub job_title: bool,
+    pub company: bool,
+    pub links: bool,
+}
+
+#[derive(serde::Serialize)]
+pub struct ProfileDetails {
+    pub display_name: Option<String>,
+    pub about_me: Option<String>,
+    pub location: Option<String>,
+    pub pronouns: Option<String>,
+    pub job_title: Option<String>,
+    pub company: Option<String>,
+    pub links: Vec<ProfileLink>,
+    pub visibility: ProfileVisibility,
+}
+
+// only the fields the owner marked as public, used by the public hash routes
+#[derive(serde::Serialize, Default)]
+pub struct VisibleProfile {
+    pub display_name: Option<String>,
+    pub about_me: Option<String>,
+    pub location: Option<String>,
+    pub pronouns: Option<String>,
+    pub job_title: Option<String>,
+    pub company: Option<String>,
+    pub links: Vec<ProfileLink>,
+}
+
+impl PublicProfileFromDB {
+    pub fn empty(user_id: i64) -> Self {
+        PublicProfileFromDB {
+            user_id,
+            display_name_public: true,
+            about_me_public: true,
+            location_public: true,
+            pronouns_public: true,
+            job_title_public: true,
+            company_public: true,
+            links_public: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn into_details(self, links: Vec<ProfileLinkFromDB>) -> ProfileDetails {
+        ProfileDetails {
+            display_name: self.display_name,
+            about_me: self.about_me,
+            location: self.location,
+            pronouns: self.pronouns,
+            job_title: self.job_title,
+            company: self.company,
+            links: links
+                .into_iter()
+                .map(|link| ProfileLink {
+                    label: link.label,
+                    url: link.url,
+                })
+                .collect(),
+            visibility: ProfileVisibility {
+                display_name: self.display_name_public,
+                about_me: self.about_me_public,
+                location: self.location_public,
+                pronouns: self.pronouns_public,
+                job_title: self.job_title_public,
+                company: self.company_public,
+                links: self.links_public,
+            },
+        }
+    }
+
+    pub fn into_visible(self, links: Vec<ProfileLinkFromDB>) -> VisibleProfile {
+        VisibleProfile {
+            display_name: self.display_name.filter(|_| self.display_name_public),
+            about_me: self.about_me.filter(|_| self.about_me_public),
+            location: self.location.filter(|_| self.location_public),
+            pronouns: self.pronouns.filter(|_| self.pronouns_public),
+            job_title: self.job_title.filter(|_| self.job_title_public),
+            company: self.company.filter(|_| self.company_public),
+            links: if self.links_public {
+                links
+                    .into_iter()
+                    .map(|link| ProfileLink {
+                        label: link.label,
+                        url: link.url,
+                    })
+                    .collect()
+            } else {
+                Vec::new()
+            },
+        }
+    }
+}
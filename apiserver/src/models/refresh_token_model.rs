@@ -0,0 +1,13 @@
+use sqlx::prelude::FromRow;
+
+#[derive(FromRow, serde::Deserialize, Debug, serde::Serialize)]
+pub struct RefreshTokenFromDB {
+    pub id: i64,
+    pub user_id: i64,
+    pub session_id: i64,
+    pub token_hash: String,
+    pub created_at: i64,
+    pub expires_at: i64,
+    pub used_at: Option<i64>,
+    pub revoked: bool,
+}
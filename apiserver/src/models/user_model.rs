@@ -5,6 +5,7 @@ pub struct UserFromDB {
     pub id: i64,
     pub email: String,
     pub email_hash: Option<String>,
+    pub email_sha256: String,
     pub active_photo_id: i64,
 }
 
@@ -12,7 +13,9 @@ pub struct UserFromDB {
 pub struct UserFromDBWithPassword {
     pub id: i64,
     pub email: String,
-    pub password: String,
+    pub password: Option<String>,
+    pub locked_until: Option<i64>,
     pub email_hash: Option<String>,
+    pub email_sha256: String,
     pub active_photo_id: i64,
 }
@@ -0,0 +1,6 @@
+// error shape used by the gravatar compatible apis
+#[derive(serde::Serialize)]
+pub struct ApiError {
+    pub error: String,
+    pub code: String,
+}
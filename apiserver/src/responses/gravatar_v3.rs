@@ -0,0 +1,51 @@
+use chrono::{DateTime, SecondsFormat};
+
+use crate::helpers::generate_id::Snowflake;
+
+#[derive(serde::Serialize)]
+pub struct Avatar {
+    pub image_id: String,
+    pub image_url: String,
+    pub rating: String,
+    pub alt_text: String,
+    pub selected: bool,
+    pub updated_date: String,
+}
+
+impl Avatar {
+    pub fn new(image_id: i64, image_url: String, selected: bool) -> Self {
+        Avatar {
+            image_id: image_id.to_string(),
+            image_url,
+            rating: "G".to_string(),
+            alt_text: "".to_string(),
+            selected,
+            updated_date: DateTime::from_timestamp(Snowflake::timestamp_of(image_id), 0)
+                .map(|date| date.to_rfc3339_opts(SecondsFormat::Secs, true))
+                .unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct Link {
+    pub label: String,
+    pub url: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct Profile {
+    pub hash: String,
+    pub display_name: String,
+    pub profile_url: String,
+    pub avatar_url: String,
+    pub avatar_alt_text: String,
+    pub location: String,
+    pub description: String,
+    pub job_title: String,
+    pub company: String,
+    pub verified_accounts: Vec<String>,
+    pub pronunciation: String,
+    pub pronouns: String,
+    pub links: Vec<Link>,
+}
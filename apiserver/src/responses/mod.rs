@@ -1,3 +1,5 @@
+pub mod api_error;
 pub mod done_message;
 pub mod general_error;
+pub mod gravatar_v3;
 pub mod validation_error;
@@ -0,0 +1,220 @@
+use actix_web::{http::header, web, HttpResponse, Responder};
+use rand::Rng;
+
+use crate::{
+    helpers::dns_resolver::{SrvRecord, SrvResolver},
+    responses::general_error::GeneralError,
+    validation_types::avatar::avatar_options::FederatedAvatarQuery,
+    AppState,
+};
+
+// lowest priority wins, ties are broken by a weighted random pick
+fn choose_record(records: &[SrvRecord]) -> Option<&SrvRecord> {
+    let lowest_priority = records.iter().map(|record| record.priority).min()?;
+    let candidates: Vec<&SrvRecord> = records
+        .iter()
+        .filter(|record| record.priority == lowest_priority)
+        .collect();
+
+    let total_weight: u32 = candidates.iter().map(|record| record.weight as u32).sum();
+    if total_weight == 0 {
+        return candidates.first().copied();
+    }
+
+    let mut pick = rand::thread_rng().gen_range(0..total_weight);
+    for record in candidates.iter() {
+        if pick < record.weight as u32 {
+            return Some(record);
+        }
+        pick -= record.weight as u32;
+    }
+    candidates.last().copied()
+}
+
+// srv targets come from whoever runs the domain's dns, so only plain host names
+// are allowed into the redirect
+fn valid_host_name(target: &str) -> bool {
+    !target.is_empty()
+        && target.len() <= 253
+        && target.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label
+                    .chars()
+                    .all(|character| character.is_ascii_alphanumeric() || character == '-')
+        })
+}
+
+// the secure record is preferred, none means the avatar is served locally
+async fn federated_server(
+    resolver: &dyn SrvResolver,
+    domain: &str,
+    own_host: Option<&str>,
+) -> Option<String> {
+    for (service, scheme, default_port) in [
+        ("_avatars-sec._tcp", "https", 443),
+        ("_avatars._tcp", "http", 80),
+    ] {
+        // lookup failures fall back to serving locally
+        let records = resolver
+            .lookup_srv(&format!("{}.{}", service, domain))
+            .await
+            .unwrap_or_default();
+        let record = match choose_record(&records) {
+            Some(record) => record,
+            None => continue,
+        };
+        let target = record.target.to_lowercase();
+        if !valid_host_name(&target) || Some(target.as_str()) == own_host {
+            return None;
+        }
+        return Some(if record.port == default_port {
+            format!("{}://{}", scheme, target)
+        } else {
+            format!("{}://{}:{}", scheme, target, record.port)
+        });
+    }
+    None
+}
+
+pub async fn get_federated_avatar(
+    app_state: web::Data<AppState>,
+    query: web::Query<FederatedAvatarQuery>,
+) -> impl Responder {
+    let domain = match query.email.trim().rsplit_once('@') {
+        Some((local, domain)) if !local.is_empty() && !domain.is_empty() => domain.to_lowercase(),
+        _ => {
+            return HttpResponse::BadRequest().json(GeneralError {
+                message: "Invalid email".to_string(),
+            })
+        }
+    };
+    let hash = crate::helpers::libravatar_hash::email_sha256(&query.email);
+
+    let own_host = reqwest::Url::parse(&app_state.public_url)
+        .ok()
+        .and_then(|url| url.host_str().map(|host| host.to_lowercase()));
+    let base = federated_server(
+        app_state.srv_resolver.as_ref(),
+        &domain,
+        own_host.as_deref(),
+    )
+    .await
+    .unwrap_or(app_state.public_url.clone());
+
+    let url_res = reqwest::Url::parse(&format!("{}/avatar/{}", base, hash));
+    if url_res.is_err() {
+        return HttpResponse::BadGateway().json(GeneralError {
+            message: "Invalid avatar server for this domain".to_string(),
+        });
+    }
+    let mut url = url_res.unwrap();
+    let pairs = query.options().query_pairs();
+    if !pairs.is_empty() {
+        url.query_pairs_mut().extend_pairs(pairs);
+    }
+
+    HttpResponse::Found()
+        .insert_header((header::LOCATION, url.to_string()))
+        .finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::helpers::dns_resolver::StaticSrvResolver;
+
+    fn record(priority: u16, port: u16, target: &str) -> SrvRecord {
+        SrvRecord {
+            priority,
+            weight: 0,
+            port,
+            target: target.to_string(),
+        }
+    }
+
+    fn resolver(records: Vec<(&str, Vec<SrvRecord>)>) -> StaticSrvResolver {
+        StaticSrvResolver {
+            records: records
+                .into_iter()
+                .map(|(name, records)| (name.to_string(), records))
+                .collect::<HashMap<_, _>>(),
+        }
+    }
+
+    #[actix_web::test]
+    async fn prefers_the_secure_record() {
+        let resolver = resolver(vec![
+            (
+                "_avatars-sec._tcp.example.org",
+                vec![
+                    record(10, 443, "backup.example.org"),
+                    record(0, 443, "Avatars.Example.org"),
+                ],
+            ),
+            (
+                "_avatars._tcp.example.org",
+                vec![record(0, 80, "plain.example.org")],
+            ),
+        ]);
+        assert_eq!(
+            federated_server(&resolver, "example.org", Some("gravatar.local")).await,
+            Some("https://avatars.example.org".to_string())
+        );
+    }
+
+    #[actix_web::test]
+    async fn keeps_non_default_ports() {
+        let resolver = resolver(vec![(
+            "_avatars._tcp.example.org",
+            vec![record(0, 8080, "avatars.example.org")],
+        )]);
+        assert_eq!(
+            federated_server(&resolver, "example.org", None).await,
+            Some("http://avatars.example.org:8080".to_string())
+        );
+    }
+
+    #[actix_web::test]
+    async fn misses_are_served_locally() {
+        let resolver = resolver(Vec::new());
+        assert_eq!(federated_server(&resolver, "example.org", None).await, None);
+
+        let own = resolver_for_target("gravatar.local");
+        assert_eq!(
+            federated_server(&own, "example.org", Some("gravatar.local")).await,
+            None
+        );
+    }
+
+    fn resolver_for_target(target: &str) -> StaticSrvResolver {
+        resolver(vec![(
+            "_avatars-sec._tcp.example.org",
+            vec![record(0, 443, target)],
+        )])
+    }
+
+    #[actix_web::test]
+    async fn rejects_targets_that_are_not_host_names() {
+        for target in [
+            "evil.example/path?",
+            "evil.example:1@other.example",
+            "evil.example#frag",
+            "-bad.example",
+            "a..example",
+            "",
+            "spa ce.example",
+        ] {
+            assert_eq!(
+                federated_server(&resolver_for_target(target), "example.org", None).await,
+                None,
+                "{}",
+                target
+            );
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use actix_web::{http::header, web, HttpResponse, Responder};
+
+use crate::{
+    models::user_model::UserFromDB, responses::general_error::GeneralError,
+    validation_types::avatar::avatar_options::AvatarOptions, AppState,
+};
+
+#[derive(serde::Deserialize)]
+pub struct PathParams {
+    pub hash: String,
+}
+
+pub async fn get_avatar(
+    app_state: web::Data<AppState>,
+    path: web::Path<PathParams>,
+    query: web::Query<AvatarOptions>,
+) -> impl Responder {
+    let hash = path.hash.to_lowercase();
+
+    // md5 hashes are 32 characters, sha256 hashes of emails and openid urls are 64
+    let user_from_db_res = match hash.len() {
+        32 => {
+            sqlx::query_as::<_, UserFromDB>("select * from users where email_hash = $1")
+                .bind(&hash)
+                .fetch_optional(&app_state.database_connection_pool)
+                .await
+        }
+        64 => {
+            sqlx::query_as::<_, UserFromDB>(
+                "select * from users where email_sha256 = $1
+                union
+                select users.* from users join openid_urls on openid_urls.user_id = users.id
+                where openid_urls.url_hash = $1",
+            )
+            .bind(&hash)
+            .fetch_optional(&app_state.database_connection_pool)
+            .await
+        }
+        _ => Ok(None),
+    };
+
+    if user_from_db_res.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+
+    if !query.force_default() {
+        if let Some(user) = user_from_db_res.unwrap() {
+            if user.active_photo_id != -1 {
+                return HttpResponse::Found()
+                    .insert_header((
+                        header::LOCATION,
+                        crate::helpers::image_url::image_url(
+                            &app_state.cloudinary_cloud_name,
+                            user.id,
+                            user.active_photo_id,
+                            Some(query.size()),
+                        ),
+                    ))
+                    .finish();
+            }
+        }
+    }
+
+    default_avatar_response(&hash, &query)
+}
+
+fn default_avatar_response(hash: &str, options: &AvatarOptions) -> HttpResponse {
+    let size = options.size();
+    let default = options.d.as_deref().unwrap_or("mm");
+
+    if default == "404" {
+        return HttpResponse::NotFound().json(GeneralError {
+            message: "Not found".to_string(),
+        });
+    }
+
+    if default.starts_with("http://") || default.starts_with("https://") {
+        return HttpResponse::Found()
+            .insert_header((header::LOCATION, default.to_string()))
+            .finish();
+    }
+
+    let png_res = match default {
+        "blank" => crate::helpers::default_avatar::blank(size),
+        "mm" | "mp" => crate::helpers::default_avatar::mystery_person(size),
+        // the remaining generated styles all fall back to an identicon
+        _ => crate::helpers::default_avatar::identicon(hash, size),
+    };
+
+    match png_res {
+        Ok(png) => HttpResponse::Ok().content_type("image/png").body(png),
+        Err(err_string) => HttpResponse::InternalServerError().json(GeneralError {
+            message: err_string,
+        }),
+    }
+}
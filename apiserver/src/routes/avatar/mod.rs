@@ -0,0 +1,2 @@
+pub mod federated_avatar;
+pub mod get_avatar;
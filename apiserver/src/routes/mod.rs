@@ -1,2 +1,6 @@
+pub mod avatar;
+pub mod oauth;
 pub mod profile;
 pub mod user;
+pub mod v3;
+pub mod xmlrpc;
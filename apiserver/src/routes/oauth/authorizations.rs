@@ -0,0 +1,130 @@
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, Responder};
+
+use crate::{
+    middlewares::auth_middleware::UserData,
+    responses::{done_message::GoodResponse, general_error::GeneralError},
+    AppState,
+};
+
+#[derive(serde::Deserialize)]
+pub struct PathParams {
+    pub client_id: String,
+}
+
+#[derive(sqlx::FromRow, serde::Serialize)]
+pub struct AuthorizationResponse {
+    #[serde(rename = "clientId")]
+    pub client_id: String,
+    pub name: String,
+    pub scopes: Vec<String>,
+    #[serde(rename = "createdAt")]
+    pub created_at: i64,
+}
+
+// the apps this user has let in
+pub async fn list_authorizations(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+) -> impl Responder {
+    if req.extensions().get::<UserData>().is_none() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+    let user_data = req.extensions().get::<UserData>().unwrap().clone();
+
+    let authorizations_res = sqlx::query_as::<_, AuthorizationResponse>(
+        "select oauth_clients.client_id, oauth_clients.name, oauth_consents.scopes, oauth_consents.created_at
+        from oauth_consents join oauth_clients on oauth_clients.id = oauth_consents.client_id
+        where oauth_consents.user_id=$1 order by oauth_consents.created_at",
+    )
+    .bind(user_data.user_id)
+    .fetch_all(&app_state.database_connection_pool)
+    .await;
+
+    if authorizations_res.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+
+    HttpResponse::Ok().json(authorizations_res.unwrap())
+}
+
+// forgets the consent and cuts off every token the app holds for this user
+pub async fn revoke_authorization(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    path: web::Path<PathParams>,
+) -> impl Responder {
+    if req.extensions().get::<UserData>().is_none() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+    let user_data = req.extensions().get::<UserData>().unwrap().clone();
+
+    let now = match crate::helpers::current_time::current_time_secs() {
+        Ok(now) => now,
+        Err(err_string) => {
+            return HttpResponse::InternalServerError().json(GeneralError {
+                message: err_string,
+            })
+        }
+    };
+
+    let delete_res = sqlx::query(
+        "delete from oauth_consents where user_id=$1
+        and client_id=(select id from oauth_clients where client_id=$2)",
+    )
+    .bind(user_data.user_id)
+    .bind(&path.client_id)
+    .execute(&app_state.database_connection_pool)
+    .await;
+
+    if delete_res.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+    if delete_res.unwrap().rows_affected() == 0 {
+        return HttpResponse::NotFound().json(GeneralError {
+            message: "Authorization not found".to_string(),
+        });
+    }
+
+    let revoke_res = sqlx::query(
+        "update api_tokens set revoked_at=$1 where user_id=$2 and revoked_at is null
+        and oauth_client_id=(select id from oauth_clients where client_id=$3)",
+    )
+    .bind(now)
+    .bind(user_data.user_id)
+    .bind(&path.client_id)
+    .execute(&app_state.database_connection_pool)
+    .await;
+
+    if revoke_res.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+
+    let revoke_refresh_res = sqlx::query(
+        "update oauth_refresh_tokens set revoked=true where user_id=$1
+        and client_id=(select id from oauth_clients where client_id=$2)",
+    )
+    .bind(user_data.user_id)
+    .bind(&path.client_id)
+    .execute(&app_state.database_connection_pool)
+    .await;
+
+    if revoke_refresh_res.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+
+    HttpResponse::Ok().json(GoodResponse {
+        message: "Authorization revoked".to_string(),
+    })
+}
@@ -0,0 +1,347 @@
+use actix_web::{http::header, web, HttpMessage, HttpRequest, HttpResponse, Responder};
+
+use crate::{
+    helpers::oauth::{
+        consent_token, parse_scopes, redirect_to_client, redirect_uri_allowed, render_consent_page,
+        render_error_page, verify_consent_token, ConsentRequest, AUTHORIZATION_CODE_LIFETIME_SECS,
+    },
+    middlewares::auth_middleware::UserData,
+    models::oauth_client_model::OauthClientFromDB,
+    AppState,
+};
+
+// shared by the query string of the first visit and the posted consent form
+#[derive(serde::Deserialize)]
+pub struct AuthorizeParams {
+    pub response_type: Option<String>,
+    pub client_id: Option<String>,
+    pub redirect_uri: Option<String>,
+    pub scope: Option<String>,
+    pub state: Option<String>,
+    pub code_challenge: Option<String>,
+    pub code_challenge_method: Option<String>,
+    pub nonce: Option<String>,
+    pub prompt: Option<String>,
+    pub decision: Option<String>,
+    pub consent_token: Option<String>,
+}
+
+struct ValidRequest {
+    client: OauthClientFromDB,
+    redirect_uri: String,
+    scopes: Vec<String>,
+    code_challenge: String,
+}
+
+#[derive(sqlx::FromRow)]
+struct ConsentFromDB {
+    scopes: Vec<String>,
+}
+
+fn error_page(message: &str) -> HttpResponse {
+    HttpResponse::BadRequest()
+        .content_type("text/html; charset=utf-8")
+        .body(render_error_page(message))
+}
+
+fn redirect_error(
+    redirect_uri: &str,
+    state: &Option<String>,
+    error: &str,
+    description: &str,
+) -> HttpResponse {
+    let mut params = vec![("error", error), ("error_description", description)];
+    if let Some(state) = state {
+        params.push(("state", state));
+    }
+    redirect_to_client(redirect_uri, &params)
+}
+
+// client and redirect uri problems are shown to the user, anything after that
+// is reported back to the client as rfc 6749 section 4.1.2.1 describes
+async fn validate_request(
+    app_state: &AppState,
+    params: &AuthorizeParams,
+) -> Result<ValidRequest, HttpResponse> {
+    let client_id = match &params.client_id {
+        Some(client_id) => client_id,
+        None => return Err(error_page("The client_id parameter is missing")),
+    };
+
+    let client_res =
+        sqlx::query_as::<_, OauthClientFromDB>("select * from oauth_clients where client_id=$1")
+            .bind(client_id)
+            .fetch_optional(&app_state.database_connection_pool)
+            .await;
+    if client_res.is_err() {
+        return Err(error_page("Issue talking to the database"));
+    }
+    let client = match client_res.unwrap() {
+        Some(client) => client,
+        None => return Err(error_page("The application is not registered")),
+    };
+
+    let redirect_uri = match &params.redirect_uri {
+        Some(redirect_uri) if client.redirect_uris.contains(redirect_uri) => redirect_uri.clone(),
+        Some(_) => {
+            return Err(error_page(
+                "The redirect uri is not registered for this application",
+            ))
+        }
+        None if client.redirect_uris.len() == 1 => client.redirect_uris[0].clone(),
+        None => return Err(error_page("The redirect_uri parameter is missing")),
+    };
+    // clients registered before the scheme rules existed may still list unsafe uris
+    if !redirect_uri_allowed(&redirect_uri, &app_state.oauth_redirect_schemes) {
+        return Err(error_page(
+            "The redirect uri is not allowed, the application should register an https uri",
+        ));
+    }
+
+    if params.response_type.as_deref() != Some("code") {
+        return Err(redirect_error(
+            &redirect_uri,
+            &params.state,
+            "unsupported_response_type",
+            "Only the code response type is supported",
+        ));
+    }
+
+    let scopes = match parse_scopes(params.scope.as_deref().unwrap_or("")) {
+        Ok(scopes) => scopes,
+        Err(err_string) => {
+            return Err(redirect_error(
+                &redirect_uri,
+                &params.state,
+                "invalid_scope",
+                &err_string,
+            ))
+        }
+    };
+
+    let code_challenge = match (
+        &params.code_challenge,
+        params.code_challenge_method.as_deref(),
+    ) {
+        (Some(code_challenge), Some("S256")) if !code_challenge.is_empty() => {
+            code_challenge.clone()
+        }
+        _ => {
+            return Err(redirect_error(
+                &redirect_uri,
+                &params.state,
+                "invalid_request",
+                "A code_challenge with the S256 method is required",
+            ))
+        }
+    };
+
+    Ok(ValidRequest {
+        client,
+        redirect_uri,
+        scopes,
+        code_challenge,
+    })
+}
+
+async fn issue_code(
+    app_state: &AppState,
+    user_id: i64,
+    request: &ValidRequest,
+    params: &AuthorizeParams,
+) -> HttpResponse {
+    let id_res = app_state.snow_flake.lock().unwrap().generate_id();
+    if id_res.is_err() {
+        return redirect_error(
+            &request.redirect_uri,
+            &params.state,
+            "server_error",
+            "Issue generating the id",
+        );
+    }
+    let now = match crate::helpers::current_time::current_time_secs() {
+        Ok(now) => now,
+        Err(err_string) => {
+            return redirect_error(
+                &request.redirect_uri,
+                &params.state,
+                "server_error",
+                &err_string,
+            )
+        }
+    };
+
+    let code = crate::helpers::generate_random_token::generate_random_token();
+    let insert_res = sqlx::query(
+        "insert into oauth_authorization_codes(id, code_hash, client_id, user_id, redirect_uri,
+        scopes, code_challenge, nonce, created_at, expires_at)
+        values($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",
+    )
+    .bind(id_res.unwrap() as i64)
+    .bind(crate::helpers::hash_token::hash_token(&code))
+    .bind(request.client.id)
+    .bind(user_id)
+    // only a redirect_uri the client sent has to be repeated at the token endpoint
+    .bind(&params.redirect_uri)
+    .bind(&request.scopes)
+    .bind(&request.code_challenge)
+    .bind(&params.nonce)
+    .bind(now)
+    .bind(now + AUTHORIZATION_CODE_LIFETIME_SECS)
+    .execute(&app_state.database_connection_pool)
+    .await;
+
+    if insert_res.is_err() {
+        return redirect_error(
+            &request.redirect_uri,
+            &params.state,
+            "server_error",
+            "Issue talking to the database",
+        );
+    }
+
+    let mut redirect_params = vec![("code", code.as_str())];
+    if let Some(state) = &params.state {
+        redirect_params.push(("state", state));
+    }
+    redirect_to_client(&request.redirect_uri, &redirect_params)
+}
+
+pub async fn authorize(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    params: web::Query<AuthorizeParams>,
+) -> impl Responder {
+    if req.extensions().get::<UserData>().is_none() {
+        return error_page("Issue talking to the database");
+    }
+    let user_data = req.extensions().get::<UserData>().unwrap().clone();
+
+    let request = match validate_request(&app_state, &params).await {
+        Ok(request) => request,
+        Err(error_response) => return error_response,
+    };
+
+    // skip the screen when everything asked for was already granted
+    let consent_res = sqlx::query_as::<_, ConsentFromDB>(
+        "select scopes from oauth_consents where user_id=$1 and client_id=$2",
+    )
+    .bind(user_data.user_id)
+    .bind(request.client.id)
+    .fetch_optional(&app_state.database_connection_pool)
+    .await;
+    if consent_res.is_err() {
+        return error_page("Issue talking to the database");
+    }
+    let already_granted = consent_res.unwrap().is_some_and(|consent| {
+        request
+            .scopes
+            .iter()
+            .all(|scope| consent.scopes.contains(scope))
+    });
+    if already_granted && params.prompt.as_deref() != Some("consent") {
+        return issue_code(&app_state, user_data.user_id, &request, &params).await;
+    }
+
+    let scope = request.scopes.join(" ");
+    let consent_token = consent_token(
+        &app_state.access_token_secret,
+        &ConsentRequest {
+            user_id: user_data.user_id,
+            session_id: user_data.session_id.unwrap_or_default(),
+            client_id: &request.client.client_id,
+            redirect_uri: params.redirect_uri.as_deref(),
+            scopes: &request.scopes,
+        },
+    );
+    let mut fields = vec![
+        ("response_type", "code"),
+        ("client_id", request.client.client_id.as_str()),
+        ("scope", scope.as_str()),
+        ("code_challenge", request.code_challenge.as_str()),
+        ("code_challenge_method", "S256"),
+        ("consent_token", consent_token.as_str()),
+    ];
+    if let Some(redirect_uri) = &params.redirect_uri {
+        fields.push(("redirect_uri", redirect_uri));
+    }
+    if let Some(state) = &params.state {
+        fields.push(("state", state));
+    }
+    if let Some(nonce) = &params.nonce {
+        fields.push(("nonce", nonce));
+    }
+
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .insert_header((header::X_FRAME_OPTIONS, "DENY"))
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .body(render_consent_page(
+            &request.client.name,
+            &request.scopes,
+            &fields,
+        ))
+}
+
+pub async fn submit_consent(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    params: web::Form<AuthorizeParams>,
+) -> impl Responder {
+    if req.extensions().get::<UserData>().is_none() {
+        return error_page("Issue talking to the database");
+    }
+    let user_data = req.extensions().get::<UserData>().unwrap().clone();
+
+    let request = match validate_request(&app_state, &params).await {
+        Ok(request) => request,
+        Err(error_response) => return error_response,
+    };
+
+    let consent_request = ConsentRequest {
+        user_id: user_data.user_id,
+        session_id: user_data.session_id.unwrap_or_default(),
+        client_id: &request.client.client_id,
+        redirect_uri: params.redirect_uri.as_deref(),
+        scopes: &request.scopes,
+    };
+    let consent_valid = params.consent_token.as_deref().is_some_and(|presented| {
+        verify_consent_token(&app_state.access_token_secret, &consent_request, presented)
+    });
+    if !consent_valid {
+        return error_page("The consent form has expired, start again from the application");
+    }
+
+    if params.decision.as_deref() != Some("allow") {
+        return redirect_error(
+            &request.redirect_uri,
+            &params.state,
+            "access_denied",
+            "The user denied the request",
+        );
+    }
+
+    let now = match crate::helpers::current_time::current_time_secs() {
+        Ok(now) => now,
+        Err(err_string) => return error_page(&err_string),
+    };
+
+    // grants only ever grow, revoking an app removes the row
+    let consent_res = sqlx::query(
+        "insert into oauth_consents(user_id, client_id, scopes, created_at) values($1, $2, $3, $4)
+        on conflict (user_id, client_id) do update set scopes = array(
+            select distinct unnest(oauth_consents.scopes || excluded.scopes)
+        )",
+    )
+    .bind(user_data.user_id)
+    .bind(request.client.id)
+    .bind(&request.scopes)
+    .bind(now)
+    .execute(&app_state.database_connection_pool)
+    .await;
+    if consent_res.is_err() {
+        return error_page("Issue talking to the database");
+    }
+
+    issue_code(&app_state, user_data.user_id, &request, &params).await
+}
@@ -0,0 +1,216 @@
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, Responder};
+use validator::Validate;
+
+use crate::{
+    middlewares::auth_middleware::UserData,
+    models::oauth_client_model::OauthClientFromDB,
+    responses::{done_message::GoodResponse, general_error::GeneralError},
+    AppState,
+};
+
+#[derive(serde::Deserialize)]
+pub struct PathParams {
+    pub id: i64,
+}
+
+#[derive(serde::Serialize)]
+pub struct ClientResponse {
+    pub id: i64,
+    pub name: String,
+    #[serde(rename = "clientId")]
+    pub client_id: String,
+    #[serde(rename = "redirectUris")]
+    pub redirect_uris: Vec<String>,
+    pub confidential: bool,
+    #[serde(rename = "createdAt")]
+    pub created_at: i64,
+}
+
+#[derive(serde::Serialize)]
+pub struct RegisteredClientResponse {
+    #[serde(flatten)]
+    pub details: ClientResponse,
+    // only ever returned here, the database keeps a hash
+    #[serde(rename = "clientSecret")]
+    pub client_secret: Option<String>,
+}
+
+impl From<OauthClientFromDB> for ClientResponse {
+    fn from(client: OauthClientFromDB) -> Self {
+        ClientResponse {
+            id: client.id,
+            name: client.name,
+            client_id: client.client_id,
+            redirect_uris: client.redirect_uris,
+            confidential: client.client_secret_hash.is_some(),
+            created_at: client.created_at,
+        }
+    }
+}
+
+pub async fn list_clients(req: HttpRequest, app_state: web::Data<AppState>) -> impl Responder {
+    if req.extensions().get::<UserData>().is_none() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+    let user_data = req.extensions().get::<UserData>().unwrap().clone();
+
+    let clients_res = sqlx::query_as::<_, OauthClientFromDB>(
+        "select * from oauth_clients where user_id=$1 order by id",
+    )
+    .bind(user_data.user_id)
+    .fetch_all(&app_state.database_connection_pool)
+    .await;
+
+    if clients_res.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+
+    let clients: Vec<ClientResponse> = clients_res
+        .unwrap()
+        .into_iter()
+        .map(ClientResponse::from)
+        .collect();
+    HttpResponse::Ok().json(clients)
+}
+
+pub async fn register_client(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    client_data: web::Json<crate::validation_types::oauth::register_client::RegisterClientData>,
+) -> impl Responder {
+    if let Err(e) = client_data.validate() {
+        let mut validation_errors: Vec<String> = Vec::new();
+        for (_, err) in e.field_errors().iter() {
+            if let Some(message) = &err[0].message {
+                validation_errors.push(message.clone().into_owned());
+            }
+        }
+        return HttpResponse::BadRequest().json(
+            crate::responses::validation_error::ValidationErrorsToBeReturned {
+                errors: validation_errors,
+            },
+        );
+    }
+
+    let disallowed_uris: Vec<String> = client_data
+        .redirect_uris
+        .iter()
+        .filter(|redirect_uri| {
+            !crate::helpers::oauth::redirect_uri_allowed(
+                redirect_uri,
+                &app_state.oauth_redirect_schemes,
+            )
+        })
+        .map(|redirect_uri| {
+            format!(
+                "Redirect uri {} should use https, http on the loopback interface or an allowed custom scheme",
+                redirect_uri
+            )
+        })
+        .collect();
+    if !disallowed_uris.is_empty() {
+        return HttpResponse::BadRequest().json(
+            crate::responses::validation_error::ValidationErrorsToBeReturned {
+                errors: disallowed_uris,
+            },
+        );
+    }
+
+    if req.extensions().get::<UserData>().is_none() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+    let user_data = req.extensions().get::<UserData>().unwrap().clone();
+
+    let id_res = app_state.snow_flake.lock().unwrap().generate_id();
+    if id_res.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue generating the id".to_string(),
+        });
+    }
+
+    let now = match crate::helpers::current_time::current_time_secs() {
+        Ok(now) => now,
+        Err(err_string) => {
+            return HttpResponse::InternalServerError().json(GeneralError {
+                message: err_string,
+            })
+        }
+    };
+
+    let client_id = crate::helpers::generate_random_token::generate_random_token();
+    let client_secret = if client_data.confidential {
+        Some(crate::helpers::generate_random_token::generate_random_token())
+    } else {
+        None
+    };
+
+    let client_res = sqlx::query_as::<_, OauthClientFromDB>(
+        "insert into oauth_clients(id, user_id, name, client_id, client_secret_hash, redirect_uris, created_at)
+        values($1, $2, $3, $4, $5, $6, $7) returning *",
+    )
+    .bind(id_res.unwrap() as i64)
+    .bind(user_data.user_id)
+    .bind(&client_data.name)
+    .bind(&client_id)
+    .bind(
+        client_secret
+            .as_ref()
+            .map(|client_secret| crate::helpers::hash_token::hash_token(client_secret)),
+    )
+    .bind(&client_data.redirect_uris)
+    .bind(now)
+    .fetch_one(&app_state.database_connection_pool)
+    .await;
+
+    if client_res.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+
+    HttpResponse::Created().json(RegisteredClientResponse {
+        details: ClientResponse::from(client_res.unwrap()),
+        client_secret,
+    })
+}
+
+// removing a client also drops its codes, consents and tokens
+pub async fn delete_client(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    path: web::Path<PathParams>,
+) -> impl Responder {
+    if req.extensions().get::<UserData>().is_none() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+    let user_data = req.extensions().get::<UserData>().unwrap().clone();
+
+    let delete_res = sqlx::query("delete from oauth_clients where id=$1 and user_id=$2")
+        .bind(path.id)
+        .bind(user_data.user_id)
+        .execute(&app_state.database_connection_pool)
+        .await;
+
+    if delete_res.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+    if delete_res.unwrap().rows_affected() == 0 {
+        return HttpResponse::NotFound().json(GeneralError {
+            message: "Client not found".to_string(),
+        });
+    }
+
+    HttpResponse::Ok().json(GoodResponse {
+        message: "Client deleted".to_string(),
+    })
+}
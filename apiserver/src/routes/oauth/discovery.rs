@@ -0,0 +1,31 @@
+use actix_web::{http::header, web, HttpResponse, Responder};
+
+use crate::{helpers::api_scopes::OAUTH_SCOPES, AppState};
+
+// public halves of every key that may have signed a live token, so other
+// services can verify access and id tokens without holding a secret
+pub async fn jwks(app_state: web::Data<AppState>) -> impl Responder {
+    HttpResponse::Ok()
+        .insert_header((header::CACHE_CONTROL, "public, max-age=300"))
+        .json(app_state.signing_keys.jwks())
+}
+
+// openid connect discovery 1.0 provider metadata
+pub async fn openid_configuration(app_state: web::Data<AppState>) -> impl Responder {
+    let issuer = &app_state.public_url;
+    HttpResponse::Ok().json(serde_json::json!({
+        "issuer": issuer,
+        "authorization_endpoint": format!("{}/oauth/authorize", issuer),
+        "token_endpoint": format!("{}/oauth/token", issuer),
+        "userinfo_endpoint": format!("{}/oauth/userinfo", issuer),
+        "scopes_supported": OAUTH_SCOPES,
+        "response_types_supported": ["code"],
+        "grant_types_supported": ["authorization_code", "refresh_token"],
+        "subject_types_supported": ["public"],
+        "jwks_uri": format!("{}/.well-known/jwks.json", issuer),
+        "id_token_signing_alg_values_supported": [format!("{:?}", app_state.signing_keys.algorithm())],
+        "token_endpoint_auth_methods_supported": ["client_secret_basic", "client_secret_post", "none"],
+        "code_challenge_methods_supported": ["S256"],
+        "claims_supported": ["sub", "iss", "aud", "exp", "iat", "nonce", "name", "picture", "profile", "email"],
+    }))
+}
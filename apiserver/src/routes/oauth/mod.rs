@@ -0,0 +1,6 @@
+pub mod authorizations;
+pub mod authorize;
+pub mod clients;
+pub mod discovery;
+pub mod token;
+pub mod userinfo;
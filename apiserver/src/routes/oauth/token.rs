@@ -0,0 +1,435 @@
+use actix_web::{
+    http::{header, StatusCode},
+    web, HttpRequest, HttpResponse, Responder,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use log::warn;
+
+use crate::{
+    helpers::{
+        api_scopes::{EMAIL, OAUTH_TOKEN_PREFIX, OPENID},
+        oauth::{
+            parse_scopes, sign_id_token, token_error, verify_pkce, IdTokenClaims,
+            OAUTH_ACCESS_TOKEN_LIFETIME_SECS, OAUTH_REFRESH_TOKEN_LIFETIME_SECS,
+        },
+    },
+    models::{
+        oauth_client_model::{AuthorizationCodeFromDB, OauthClientFromDB, OauthRefreshTokenFromDB},
+        user_model::UserFromDB,
+    },
+    AppState,
+};
+
+#[derive(serde::Deserialize)]
+pub struct TokenParams {
+    pub grant_type: Option<String>,
+    pub code: Option<String>,
+    pub redirect_uri: Option<String>,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+    pub code_verifier: Option<String>,
+    pub refresh_token: Option<String>,
+    pub scope: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+pub struct TokenResponse {
+    pub access_token: String,
+    pub token_type: String,
+    pub expires_in: i64,
+    pub scope: String,
+    pub refresh_token: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id_token: Option<String>,
+}
+
+// what a grant resolves to before tokens are minted
+struct Grant {
+    user_id: i64,
+    scopes: Vec<String>,
+    // refresh tokens rotate within a family, reuse of a spent one revokes it all
+    family_id: i64,
+    // id tokens are only minted for the code exchange, which carries the nonce
+    id_token_nonce: Option<Option<String>>,
+}
+
+fn server_error(description: &str) -> HttpResponse {
+    token_error(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "server_error",
+        description,
+    )
+}
+
+// client_secret_basic wins over client_secret_post when both are sent
+fn client_credentials(req: &HttpRequest, params: &TokenParams) -> (Option<String>, Option<String>) {
+    let basic = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|authorization| authorization.to_str().ok())
+        .and_then(|authorization| authorization.strip_prefix("Basic "))
+        .and_then(|encoded| STANDARD.decode(encoded.trim()).ok())
+        .and_then(|decoded| String::from_utf8(decoded).ok());
+    if let Some(basic) = basic {
+        if let Some((client_id, client_secret)) = basic.split_once(':') {
+            return (Some(client_id.to_string()), Some(client_secret.to_string()));
+        }
+    }
+    (params.client_id.clone(), params.client_secret.clone())
+}
+
+async fn redeem_code(
+    app_state: &AppState,
+    client: &OauthClientFromDB,
+    params: &TokenParams,
+    now: i64,
+) -> Result<Grant, HttpResponse> {
+    let (code, code_verifier) = match (&params.code, &params.code_verifier) {
+        (Some(code), Some(code_verifier)) => (code, code_verifier),
+        _ => {
+            return Err(token_error(
+                StatusCode::BAD_REQUEST,
+                "invalid_request",
+                "The code and code_verifier parameters are required",
+            ))
+        }
+    };
+
+    // a code can be exchanged once, the update also guards against concurrent exchanges
+    let code_res = sqlx::query_as::<_, AuthorizationCodeFromDB>(
+        "update oauth_authorization_codes set used_at=$1
+        where code_hash=$2 and client_id=$3 and used_at is null returning *",
+    )
+    .bind(now)
+    .bind(crate::helpers::hash_token::hash_token(code))
+    .bind(client.id)
+    .fetch_optional(&app_state.database_connection_pool)
+    .await;
+    if code_res.is_err() {
+        return Err(server_error("Issue talking to the database"));
+    }
+    let authorization_code = match code_res.unwrap() {
+        Some(authorization_code) => authorization_code,
+        None => {
+            return Err(token_error(
+                StatusCode::BAD_REQUEST,
+                "invalid_grant",
+                "The authorization code is invalid or was already used",
+            ))
+        }
+    };
+
+    // rfc 6749 section 4.1.3, the redirect_uri must match only when the authorize request had one
+    let redirect_uri_matches = match &authorization_code.redirect_uri {
+        Some(redirect_uri) => params.redirect_uri.as_deref() == Some(redirect_uri.as_str()),
+        None => true,
+    };
+    if authorization_code.expires_at <= now
+        || !redirect_uri_matches
+        || !verify_pkce(code_verifier, &authorization_code.code_challenge)
+    {
+        return Err(token_error(
+            StatusCode::BAD_REQUEST,
+            "invalid_grant",
+            "The authorization code is invalid, expired or does not match the request",
+        ));
+    }
+
+    let family_id_res = app_state.snow_flake.lock().unwrap().generate_id();
+    if family_id_res.is_err() {
+        return Err(server_error("Issue generating the id"));
+    }
+
+    Ok(Grant {
+        user_id: authorization_code.user_id,
+        scopes: authorization_code.scopes,
+        family_id: family_id_res.unwrap() as i64,
+        id_token_nonce: Some(authorization_code.nonce),
+    })
+}
+
+async fn redeem_refresh_token(
+    app_state: &AppState,
+    client: &OauthClientFromDB,
+    params: &TokenParams,
+    now: i64,
+) -> Result<Grant, HttpResponse> {
+    let refresh_token = match &params.refresh_token {
+        Some(refresh_token) => refresh_token,
+        None => {
+            return Err(token_error(
+                StatusCode::BAD_REQUEST,
+                "invalid_request",
+                "The refresh_token parameter is required",
+            ))
+        }
+    };
+    let invalid_grant = || {
+        token_error(
+            StatusCode::BAD_REQUEST,
+            "invalid_grant",
+            "The refresh token is invalid, expired or was revoked",
+        )
+    };
+
+    let stored_res = sqlx::query_as::<_, OauthRefreshTokenFromDB>(
+        "select * from oauth_refresh_tokens where token_hash=$1 and client_id=$2",
+    )
+    .bind(crate::helpers::hash_token::hash_token(refresh_token))
+    .bind(client.id)
+    .fetch_optional(&app_state.database_connection_pool)
+    .await;
+    if stored_res.is_err() {
+        return Err(server_error("Issue talking to the database"));
+    }
+    let stored = match stored_res.unwrap() {
+        Some(stored) => stored,
+        None => return Err(invalid_grant()),
+    };
+    if stored.revoked || stored.expires_at <= now {
+        return Err(invalid_grant());
+    }
+
+    // rfc 6749 section 6, a narrower scope may be asked for but never a wider one
+    let scopes = match &params.scope {
+        Some(scope) => match parse_scopes(scope) {
+            Ok(scopes) if scopes.iter().all(|scope| stored.scopes.contains(scope)) => scopes,
+            _ => {
+                return Err(token_error(
+                    StatusCode::BAD_REQUEST,
+                    "invalid_scope",
+                    "The requested scope exceeds the original grant",
+                ))
+            }
+        },
+        None => stored.scopes.clone(),
+    };
+
+    // a token can be exchanged once, the update also guards against concurrent refreshes
+    let mark_used_res =
+        sqlx::query("update oauth_refresh_tokens set used_at=$1 where id=$2 and used_at is null")
+            .bind(now)
+            .bind(stored.id)
+            .execute(&app_state.database_connection_pool)
+            .await;
+    if mark_used_res.is_err() {
+        return Err(server_error("Issue talking to the database"));
+    }
+
+    if mark_used_res.unwrap().rows_affected() == 0 {
+        warn!(
+            "Oauth refresh token reuse detected for user {} and client {}, revoking the grant",
+            stored.user_id, client.client_id
+        );
+        // the access tokens are not tied to a family, so everything the client holds goes
+        let revoke_refresh_res =
+            sqlx::query("update oauth_refresh_tokens set revoked=true where family_id=$1")
+                .bind(stored.family_id)
+                .execute(&app_state.database_connection_pool)
+                .await;
+        let revoke_access_res = sqlx::query(
+            "update api_tokens set revoked_at=$1
+            where user_id=$2 and oauth_client_id=$3 and revoked_at is null",
+        )
+        .bind(now)
+        .bind(stored.user_id)
+        .bind(client.id)
+        .execute(&app_state.database_connection_pool)
+        .await;
+        if revoke_refresh_res.is_err() || revoke_access_res.is_err() {
+            return Err(server_error("Issue talking to the database"));
+        }
+        return Err(invalid_grant());
+    }
+
+    Ok(Grant {
+        user_id: stored.user_id,
+        scopes,
+        family_id: stored.family_id,
+        id_token_nonce: None,
+    })
+}
+
+async fn insert_refresh_token(
+    app_state: &AppState,
+    client: &OauthClientFromDB,
+    grant: &Grant,
+    now: i64,
+) -> Result<String, String> {
+    let id_res = app_state.snow_flake.lock().unwrap().generate_id();
+    if id_res.is_err() {
+        return Err("Issue generating the id".to_string());
+    }
+    let refresh_token = crate::helpers::generate_random_token::generate_random_token();
+    let insert_res = sqlx::query(
+        "insert into oauth_refresh_tokens(id, token_hash, family_id, client_id, user_id, scopes,
+        created_at, expires_at) values($1, $2, $3, $4, $5, $6, $7, $8)",
+    )
+    .bind(id_res.unwrap() as i64)
+    .bind(crate::helpers::hash_token::hash_token(&refresh_token))
+    .bind(grant.family_id)
+    .bind(client.id)
+    .bind(grant.user_id)
+    .bind(&grant.scopes)
+    .bind(now)
+    .bind(now + OAUTH_REFRESH_TOKEN_LIFETIME_SECS)
+    .execute(&app_state.database_connection_pool)
+    .await;
+    if insert_res.is_err() {
+        return Err("Issue talking to the database".to_string());
+    }
+    Ok(refresh_token)
+}
+
+pub async fn token(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    params: web::Form<TokenParams>,
+) -> impl Responder {
+    let grant_type = match params.grant_type.as_deref() {
+        Some(grant_type @ ("authorization_code" | "refresh_token")) => grant_type,
+        _ => {
+            return token_error(
+                StatusCode::BAD_REQUEST,
+                "unsupported_grant_type",
+                "Only the authorization_code and refresh_token grants are supported",
+            )
+        }
+    };
+
+    let (client_id, client_secret) = client_credentials(&req, &params);
+    let client_id = match client_id {
+        Some(client_id) => client_id,
+        None => {
+            return token_error(
+                StatusCode::UNAUTHORIZED,
+                "invalid_client",
+                "Client authentication failed",
+            )
+        }
+    };
+
+    let client_res =
+        sqlx::query_as::<_, OauthClientFromDB>("select * from oauth_clients where client_id=$1")
+            .bind(&client_id)
+            .fetch_optional(&app_state.database_connection_pool)
+            .await;
+    if client_res.is_err() {
+        return server_error("Issue talking to the database");
+    }
+    let client = match client_res.unwrap() {
+        Some(client) => client,
+        None => {
+            return token_error(
+                StatusCode::UNAUTHORIZED,
+                "invalid_client",
+                "Client authentication failed",
+            )
+        }
+    };
+
+    // public clients have no secret and are held to pkce alone
+    if let Some(client_secret_hash) = &client.client_secret_hash {
+        let presented_hash = client_secret
+            .as_deref()
+            .map(crate::helpers::hash_token::hash_token);
+        if presented_hash.as_ref() != Some(client_secret_hash) {
+            return token_error(
+                StatusCode::UNAUTHORIZED,
+                "invalid_client",
+                "Client authentication failed",
+            );
+        }
+    }
+
+    let now = match crate::helpers::current_time::current_time_secs() {
+        Ok(now) => now,
+        Err(err_string) => return server_error(&err_string),
+    };
+
+    let grant_res = match grant_type {
+        "authorization_code" => redeem_code(&app_state, &client, &params, now).await,
+        _ => redeem_refresh_token(&app_state, &client, &params, now).await,
+    };
+    let grant = match grant_res {
+        Ok(grant) => grant,
+        Err(error_response) => return error_response,
+    };
+
+    let user_res = sqlx::query_as::<_, UserFromDB>(
+        "select id, email, email_hash, email_sha256, active_photo_id from users where id=$1",
+    )
+    .bind(grant.user_id)
+    .fetch_one(&app_state.database_connection_pool)
+    .await;
+    if user_res.is_err() {
+        return server_error("Issue talking to the database");
+    }
+    let user = user_res.unwrap();
+
+    let token_id_res = app_state.snow_flake.lock().unwrap().generate_id();
+    if token_id_res.is_err() {
+        return server_error("Issue generating the id");
+    }
+
+    let access_token = format!(
+        "{}{}",
+        OAUTH_TOKEN_PREFIX,
+        crate::helpers::generate_random_token::generate_random_token()
+    );
+    let insert_res = sqlx::query(
+        "insert into api_tokens(id, user_id, name, token_hash, scopes, created_at, expires_at, oauth_client_id)
+        values($1, $2, $3, $4, $5, $6, $7, $8)",
+    )
+    .bind(token_id_res.unwrap() as i64)
+    .bind(user.id)
+    .bind(&client.name)
+    .bind(crate::helpers::hash_token::hash_token(&access_token))
+    .bind(&grant.scopes)
+    .bind(now)
+    .bind(now + OAUTH_ACCESS_TOKEN_LIFETIME_SECS)
+    .bind(client.id)
+    .execute(&app_state.database_connection_pool)
+    .await;
+    if insert_res.is_err() {
+        return server_error("Issue talking to the database");
+    }
+
+    let refresh_token = match insert_refresh_token(&app_state, &client, &grant, now).await {
+        Ok(refresh_token) => refresh_token,
+        Err(err_string) => return server_error(&err_string),
+    };
+
+    let has_scope = |scope: &str| grant.scopes.iter().any(|granted| granted == scope);
+    let mut id_token = None;
+    if let (Some(nonce), true) = (&grant.id_token_nonce, has_scope(OPENID)) {
+        let claims = IdTokenClaims {
+            iss: app_state.public_url.clone(),
+            sub: user.id.to_string(),
+            aud: client.client_id.clone(),
+            exp: now + OAUTH_ACCESS_TOKEN_LIFETIME_SECS,
+            iat: now,
+            nonce: nonce.clone(),
+            email: if has_scope(EMAIL) {
+                Some(user.email.clone())
+            } else {
+                None
+            },
+        };
+        match sign_id_token(&claims, &app_state.signing_keys) {
+            Ok(signed) => id_token = Some(signed),
+            Err(err_string) => return server_error(&err_string),
+        }
+    }
+
+    HttpResponse::Ok()
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .json(TokenResponse {
+            access_token,
+            token_type: "Bearer".to_string(),
+            expires_in: OAUTH_ACCESS_TOKEN_LIFETIME_SECS,
+            scope: grant.scopes.join(" "),
+            refresh_token,
+            id_token,
+        })
+}
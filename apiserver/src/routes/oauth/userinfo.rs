@@ -0,0 +1,78 @@
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, Responder};
+
+use crate::{
+    helpers::api_scopes::{self, EMAIL, OPENID, PROFILE},
+    middlewares::auth_middleware::UserData,
+    responses::general_error::GeneralError,
+    AppState,
+};
+
+#[derive(sqlx::FromRow)]
+struct UserInfoFromDB {
+    email: String,
+    email_hash: Option<String>,
+    display_name: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+pub struct UserInfoResponse {
+    pub sub: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub picture: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+}
+
+// claims are released per granted scope, signed in users see everything
+pub async fn userinfo(req: HttpRequest, app_state: web::Data<AppState>) -> impl Responder {
+    if req.extensions().get::<UserData>().is_none() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+    let user_data = req.extensions().get::<UserData>().unwrap().clone();
+    if !user_data.has_scope(OPENID) {
+        return crate::middlewares::auth_middleware::insufficient_scope(api_scopes::OPENID);
+    }
+
+    let user_res = sqlx::query_as::<_, UserInfoFromDB>(
+        "select users.email, users.email_hash,
+        case when public_profiles.display_name_public then public_profiles.display_name end as display_name
+        from users left join public_profiles on public_profiles.user_id = users.id
+        where users.id=$1",
+    )
+    .bind(user_data.user_id)
+    .fetch_one(&app_state.database_connection_pool)
+    .await;
+
+    if user_res.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+    let user = user_res.unwrap();
+
+    let mut response = UserInfoResponse {
+        sub: user_data.user_id.to_string(),
+        name: None,
+        picture: None,
+        profile: None,
+        email: None,
+    };
+    if user_data.has_scope(PROFILE) {
+        response.name = user.display_name;
+        if let Some(email_hash) = &user.email_hash {
+            response.picture = Some(format!("{}/avatar/{}", app_state.public_url, email_hash));
+            response.profile = Some(format!("{}/{}", app_state.public_url, email_hash));
+        }
+    }
+    if user_data.has_scope(EMAIL) {
+        response.email = Some(user.email);
+    }
+
+    HttpResponse::Ok().json(response)
+}
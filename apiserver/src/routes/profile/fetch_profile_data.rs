@@ -0,0 +1,108 @@
+use actix_web::{web, HttpResponse, Responder};
+
+use crate::{
+    helpers::render_profile::RenderableProfile, models::user_model::UserFromDB,
+    responses::general_error::GeneralError, AppState,
+};
+
+#[derive(serde::Deserialize)]
+pub struct PathParams {
+    pub email_hash: String,
+    pub format: String,
+}
+
+pub async fn get_profile_data(
+    app_state: web::Data<AppState>,
+    path: web::Path<PathParams>,
+) -> impl Responder {
+    if !["json", "xml", "vcf", "html", "qr"].contains(&path.format.as_str()) {
+        return HttpResponse::NotFound().json(GeneralError {
+            message: "Not found".to_string(),
+        });
+    }
+
+    let user_from_db_res =
+        sqlx::query_as::<_, UserFromDB>("select * from users where email_hash = $1")
+            .bind(&path.email_hash)
+            .fetch_optional(&app_state.database_connection_pool)
+            .await;
+
+    if user_from_db_res.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+
+    let user = match user_from_db_res.unwrap() {
+        Some(user) => user,
+        None => {
+            return HttpResponse::NotFound().json(GeneralError {
+                message: "Not found".to_string(),
+            })
+        }
+    };
+
+    let profile_url =
+        crate::helpers::render_profile::profile_url(&app_state.public_url, &path.email_hash);
+
+    if path.format == "qr" {
+        return match crate::helpers::generate_qr::generate_qr_png(&profile_url, 256) {
+            Ok(png) => HttpResponse::Ok().content_type("image/png").body(png),
+            Err(err_string) => HttpResponse::InternalServerError().json(GeneralError {
+                message: err_string,
+            }),
+        };
+    }
+
+    let details_res =
+        crate::dbcalls::fetch_profile_details::fetch_profile_details(user.id, &app_state).await;
+    if details_res.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+    let (details, links) = details_res.unwrap();
+
+    let thumbnail_url = if user.active_photo_id == -1 {
+        None
+    } else {
+        Some(crate::helpers::image_url::image_url(
+            &app_state.cloudinary_cloud_name,
+            user.id,
+            user.active_photo_id,
+            None,
+        ))
+    };
+
+    let profile = RenderableProfile {
+        hash: path.email_hash.clone(),
+        profile_url,
+        thumbnail_url,
+        details: details.into_visible(links),
+    };
+
+    match path.format.as_str() {
+        "xml" => HttpResponse::Ok()
+            .content_type("application/xml; charset=utf-8")
+            .body(crate::helpers::render_profile::render_xml(profile)),
+        "html" => HttpResponse::Ok()
+            .content_type("text/html; charset=utf-8")
+            // nothing on the page needs scripts, only the avatar image
+            .insert_header((
+                "Content-Security-Policy",
+                "default-src 'none'; img-src https: http:",
+            ))
+            .body(crate::helpers::render_profile::render_html(profile)),
+        "vcf" => HttpResponse::Ok()
+            .content_type("text/vcard; charset=utf-8")
+            .body(crate::helpers::render_profile::render_vcard(profile)),
+        _ => match crate::helpers::render_profile::render_json(profile) {
+            Ok(json) => HttpResponse::Ok()
+                .content_type("application/json")
+                .body(json),
+            Err(err_string) => HttpResponse::InternalServerError().json(GeneralError {
+                message: err_string,
+            }),
+        },
+    }
+}
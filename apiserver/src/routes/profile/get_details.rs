@@ -0,0 +1,33 @@
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, Responder};
+
+use crate::{
+    helpers::api_scopes, middlewares::auth_middleware::UserData,
+    responses::general_error::GeneralError, AppState,
+};
+
+pub async fn get_details(req: HttpRequest, app_state: web::Data<AppState>) -> impl Responder {
+    if req.extensions().get::<UserData>().is_none() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+    let user_data = req.extensions().get::<UserData>().unwrap().clone();
+    // being allowed to edit the profile includes reading it
+    if !user_data.has_scope(api_scopes::PROFILE_READ)
+        && !user_data.has_scope(api_scopes::PROFILE_WRITE)
+    {
+        return crate::middlewares::auth_middleware::insufficient_scope(api_scopes::PROFILE_READ);
+    }
+
+    match crate::dbcalls::fetch_profile_details::fetch_profile_details(
+        user_data.user_id,
+        &app_state,
+    )
+    .await
+    {
+        Err(err_string) => HttpResponse::InternalServerError().json(GeneralError {
+            message: err_string,
+        }),
+        Ok((profile, links)) => HttpResponse::Ok().json(profile.into_details(links)),
+    }
+}
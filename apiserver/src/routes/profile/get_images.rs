@@ -1,8 +1,8 @@
 use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, Responder};
 
 use crate::{
-    middlewares::auth_middleware::UserData, models::profile_model::AllProfiles,
-    responses::general_error::GeneralError, AppState,
+    helpers::api_scopes, middlewares::auth_middleware::UserData,
+    models::profile_model::AllProfiles, responses::general_error::GeneralError, AppState,
 };
 
 pub async fn get_imgages(req: HttpRequest, app_state: web::Data<AppState>) -> impl Responder {
@@ -11,25 +11,19 @@ pub async fn get_imgages(req: HttpRequest, app_state: web::Data<AppState>) -> im
             message: "Issue talking to the database".to_string(),
         });
     }
-    let extensions = req.extensions();
-    let user_data = extensions.get::<UserData>().unwrap();
-
-    let profile_pics_res = sqlx::query_as::<_, AllProfiles>(
-        "select COALESCE(array_agg(id), '{}') as id from profile where user_id=$1",
-    )
-    .bind(user_data.user_id)
-    .fetch_optional(&app_state.database_connection_pool)
-    .await;
-
-    if profile_pics_res.is_err() {
-        return HttpResponse::InternalServerError().json(GeneralError {
-            message: "Issue talking to the database".to_string(),
-        });
+    let user_data = req.extensions().get::<UserData>().unwrap().clone();
+    if !user_data.has_scope(api_scopes::AVATARS_READ) {
+        return crate::middlewares::auth_middleware::insufficient_scope(api_scopes::AVATARS_READ);
     }
 
-    if profile_pics_res.as_ref().unwrap().as_ref().is_none() {
-        return HttpResponse::NotFound().json(());
+    match crate::dbcalls::fetch_profile_images::fetch_profile_images(user_data.user_id, &app_state)
+        .await
+    {
+        Err(err_string) => HttpResponse::InternalServerError().json(GeneralError {
+            message: err_string,
+        }),
+        Ok(profile_pics) => HttpResponse::Ok().json(AllProfiles {
+            id: profile_pics.iter().map(|profile| profile.id).collect(),
+        }),
     }
-
-    HttpResponse::Ok().json(profile_pics_res.unwrap())
 }
@@ -1,4 +1,9 @@
 pub mod add_image;
 pub mod fetch_image;
+pub mod fetch_profile_data;
+pub mod get_details;
 pub mod get_images;
+pub mod openid_urls;
+pub mod update_details;
 pub mod update_profile;
+pub mod update_visibility;
@@ -0,0 +1,148 @@
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, Responder};
+use validator::Validate;
+
+use crate::{
+    helpers::api_scopes, middlewares::auth_middleware::UserData,
+    models::openid_url_model::OpenidUrlFromDB, responses::general_error::GeneralError, AppState,
+};
+
+#[derive(serde::Deserialize)]
+pub struct PathParams {
+    pub openid_url_id: i64,
+}
+
+pub async fn get_openid_urls(req: HttpRequest, app_state: web::Data<AppState>) -> impl Responder {
+    if req.extensions().get::<UserData>().is_none() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+    let user_data = req.extensions().get::<UserData>().unwrap().clone();
+    // being allowed to edit the profile includes reading it
+    if !user_data.has_scope(api_scopes::PROFILE_READ)
+        && !user_data.has_scope(api_scopes::PROFILE_WRITE)
+    {
+        return crate::middlewares::auth_middleware::insufficient_scope(api_scopes::PROFILE_READ);
+    }
+
+    let openid_urls_res = sqlx::query_as::<_, OpenidUrlFromDB>(
+        "select * from openid_urls where user_id=$1 order by id",
+    )
+    .bind(user_data.user_id)
+    .fetch_all(&app_state.database_connection_pool)
+    .await;
+
+    if openid_urls_res.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+
+    HttpResponse::Ok().json(openid_urls_res.unwrap())
+}
+
+pub async fn add_openid_url(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    openid_data: web::Json<crate::validation_types::profile::add_openid_url::AddOpenidUrlData>,
+) -> impl Responder {
+    if let Err(e) = openid_data.validate() {
+        let mut validation_errors: Vec<String> = Vec::new();
+        for (_, err) in e.field_errors().iter() {
+            if let Some(message) = &err[0].message {
+                validation_errors.push(message.clone().into_owned());
+            }
+        }
+        return HttpResponse::BadRequest().json(
+            crate::responses::validation_error::ValidationErrorsToBeReturned {
+                errors: validation_errors,
+            },
+        );
+    }
+
+    if req.extensions().get::<UserData>().is_none() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+    let user_data = req.extensions().get::<UserData>().unwrap().clone();
+    if !user_data.has_scope(api_scopes::PROFILE_WRITE) {
+        return crate::middlewares::auth_middleware::insufficient_scope(api_scopes::PROFILE_WRITE);
+    }
+
+    let url_hash = match crate::helpers::libravatar_hash::openid_url_hash(&openid_data.url) {
+        Ok(url_hash) => url_hash,
+        Err(err_string) => {
+            return HttpResponse::BadRequest().json(GeneralError {
+                message: err_string,
+            })
+        }
+    };
+
+    let openid_url_id_res = app_state.snow_flake.lock().unwrap().generate_id();
+    if openid_url_id_res.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue generating the id".to_string(),
+        });
+    }
+
+    let insert_res = sqlx::query_as::<_, OpenidUrlFromDB>(
+        "insert into openid_urls(id, user_id, url, url_hash) values($1, $2, $3, $4)
+        on conflict (url_hash) do nothing returning *",
+    )
+    .bind(openid_url_id_res.unwrap() as i64)
+    .bind(user_data.user_id)
+    .bind(openid_data.url.trim())
+    .bind(url_hash)
+    .fetch_optional(&app_state.database_connection_pool)
+    .await;
+
+    if insert_res.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+
+    match insert_res.unwrap() {
+        None => HttpResponse::BadRequest().json(GeneralError {
+            message: "Openid url is already registered".to_string(),
+        }),
+        Some(openid_url) => HttpResponse::Ok().json(openid_url),
+    }
+}
+
+pub async fn delete_openid_url(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    path: web::Path<PathParams>,
+) -> impl Responder {
+    if req.extensions().get::<UserData>().is_none() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+    let user_data = req.extensions().get::<UserData>().unwrap().clone();
+    if !user_data.has_scope(api_scopes::PROFILE_WRITE) {
+        return crate::middlewares::auth_middleware::insufficient_scope(api_scopes::PROFILE_WRITE);
+    }
+
+    let delete_res = sqlx::query("delete from openid_urls where id=$1 and user_id=$2")
+        .bind(path.openid_url_id)
+        .bind(user_data.user_id)
+        .execute(&app_state.database_connection_pool)
+        .await;
+
+    if delete_res.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+
+    if delete_res.unwrap().rows_affected() == 0 {
+        return HttpResponse::NotFound().json(GeneralError {
+            message: "Openid url not found".to_string(),
+        });
+    }
+
+    HttpResponse::Ok().json(())
+}
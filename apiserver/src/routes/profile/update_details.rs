@@ -0,0 +1,129 @@
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, Responder};
+use validator::Validate;
+
+use crate::{
+    helpers::api_scopes, middlewares::auth_middleware::UserData,
+    responses::general_error::GeneralError, AppState,
+};
+
+pub async fn update_details(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    details_data: web::Json<crate::validation_types::profile::update_details::UpdateDetailsData>,
+) -> impl Responder {
+    if let Err(e) = details_data.validate() {
+        let mut validation_errors =
+            crate::validation_types::profile::update_details::error_messages(&e);
+        if validation_errors.is_empty() {
+            validation_errors.push("Invalid profile links".to_string())
+        }
+        return HttpResponse::BadRequest().json(
+            crate::responses::validation_error::ValidationErrorsToBeReturned {
+                errors: validation_errors,
+            },
+        );
+    }
+
+    if req.extensions().get::<UserData>().is_none() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+    let user_data = req.extensions().get::<UserData>().unwrap().clone();
+    if !user_data.has_scope(api_scopes::PROFILE_WRITE) {
+        return crate::middlewares::auth_middleware::insufficient_scope(api_scopes::PROFILE_WRITE);
+    }
+
+    let mut link_ids = Vec::new();
+    {
+        let mut snow_flake = app_state.snow_flake.lock().unwrap();
+        for _ in details_data.links.iter() {
+            let link_id_res = snow_flake.generate_id();
+            if link_id_res.is_err() {
+                return HttpResponse::InternalServerError().json(GeneralError {
+                    message: "Issue generating link id".to_string(),
+                });
+            }
+            link_ids.push(link_id_res.unwrap() as i64);
+        }
+    }
+
+    let transaction_res = app_state.database_connection_pool.begin().await;
+    if transaction_res.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue starting the transaction".to_string(),
+        });
+    }
+    let mut transaction = transaction_res.unwrap();
+
+    let upsert_res = sqlx::query(
+        "insert into public_profiles(user_id, display_name, about_me, location, pronouns, job_title, company)
+        values($1, $2, $3, $4, $5, $6, $7)
+        on conflict (user_id) do update set display_name=$2, about_me=$3, location=$4,
+        pronouns=$5, job_title=$6, company=$7",
+    )
+    .bind(user_data.user_id)
+    .bind(&details_data.display_name)
+    .bind(&details_data.about_me)
+    .bind(&details_data.location)
+    .bind(&details_data.pronouns)
+    .bind(&details_data.job_title)
+    .bind(&details_data.company)
+    .execute(&mut *transaction)
+    .await;
+
+    if upsert_res.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue updating the database".to_string(),
+        });
+    }
+
+    // links are replaced as a whole so their order follows the request
+    let delete_res = sqlx::query("delete from profile_links where user_id=$1")
+        .bind(user_data.user_id)
+        .execute(&mut *transaction)
+        .await;
+
+    if delete_res.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue updating the database".to_string(),
+        });
+    }
+
+    for (position, (link, link_id)) in details_data.links.iter().zip(link_ids).enumerate() {
+        let insert_res = sqlx::query(
+            "insert into profile_links(id, user_id, label, url, position) values($1, $2, $3, $4, $5)",
+        )
+        .bind(link_id)
+        .bind(user_data.user_id)
+        .bind(&link.label)
+        .bind(&link.url)
+        .bind(position as i32)
+        .execute(&mut *transaction)
+        .await;
+
+        if insert_res.is_err() {
+            return HttpResponse::InternalServerError().json(GeneralError {
+                message: "Issue updating the database".to_string(),
+            });
+        }
+    }
+
+    if transaction.commit().await.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue updating the database".to_string(),
+        });
+    }
+
+    match crate::dbcalls::fetch_profile_details::fetch_profile_details(
+        user_data.user_id,
+        &app_state,
+    )
+    .await
+    {
+        Err(err_string) => HttpResponse::InternalServerError().json(GeneralError {
+            message: err_string,
+        }),
+        Ok((profile, links)) => HttpResponse::Ok().json(profile.into_details(links)),
+    }
+}
@@ -2,10 +2,8 @@ use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, Responder};
 use validator::Validate;
 
 use crate::{
-    middlewares::auth_middleware::UserData,
-    models::{profile_model::ProfileFromDB, user_model::UserFromDB},
-    responses::general_error::GeneralError,
-    AppState,
+    helpers::api_scopes, middlewares::auth_middleware::UserData,
+    responses::general_error::GeneralError, AppState,
 };
 
 pub async fn update_profile_image(
@@ -36,42 +34,24 @@ pub async fn update_profile_image(
         });
     }
 
-    let extensions = req.extensions();
-    let user_data = extensions.get::<UserData>().unwrap();
-
-    // check if profile exists in the database
-    let profile_exists_res =
-        sqlx::query_as::<_, ProfileFromDB>("select * from profile where user_id=$1 and id=$2")
-            .bind(user_data.user_id)
-            .bind(profile_data.0.profile_id)
-            .fetch_optional(&app_state.database_connection_pool)
-            .await;
-
-    if profile_exists_res.is_err() {
-        return HttpResponse::InternalServerError().json(GeneralError {
-            message: "Issue talking to the database".to_string(),
-        });
-    }
-
-    if profile_exists_res.unwrap().is_none() {
-        return HttpResponse::NotFound().json(GeneralError {
-            message: "Profile not found".to_string(),
-        });
+    let user_data = req.extensions().get::<UserData>().unwrap().clone();
+    if !user_data.has_scope(api_scopes::AVATARS_WRITE) {
+        return crate::middlewares::auth_middleware::insufficient_scope(api_scopes::AVATARS_WRITE);
     }
 
-    let updated_user_res = sqlx::query_as::<_, UserFromDB>(
-        "update users set active_photo_id=$1 where id=$2 returning *",
+    match crate::dbcalls::select_profile_image::select_profile_image(
+        user_data.user_id,
+        profile_data.0.profile_id,
+        &app_state,
     )
-    .bind(profile_data.0.profile_id)
-    .bind(user_data.user_id)
-    .fetch_optional(&app_state.database_connection_pool)
-    .await;
-
-    if updated_user_res.is_err() {
-        return HttpResponse::InternalServerError().json(GeneralError {
-            message: "Issue updating the database".to_string(),
-        });
+    .await
+    {
+        Err(err_string) => HttpResponse::InternalServerError().json(GeneralError {
+            message: err_string,
+        }),
+        Ok(false) => HttpResponse::NotFound().json(GeneralError {
+            message: "Profile not found".to_string(),
+        }),
+        Ok(true) => HttpResponse::Ok().json(()),
     }
-
-    HttpResponse::Ok().json(())
 }
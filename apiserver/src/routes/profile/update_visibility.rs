@@ -0,0 +1,68 @@
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, Responder};
+
+use crate::{
+    helpers::api_scopes, middlewares::auth_middleware::UserData,
+    responses::general_error::GeneralError, AppState,
+};
+
+pub async fn update_visibility(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    visibility_data: web::Json<
+        crate::validation_types::profile::update_visibility::UpdateVisibilityData,
+    >,
+) -> impl Responder {
+    if req.extensions().get::<UserData>().is_none() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+    let user_data = req.extensions().get::<UserData>().unwrap().clone();
+    if !user_data.has_scope(api_scopes::PROFILE_WRITE) {
+        return crate::middlewares::auth_middleware::insufficient_scope(api_scopes::PROFILE_WRITE);
+    }
+
+    // fields left out of the request keep their current visibility
+    let upsert_res = sqlx::query(
+        "insert into public_profiles(user_id, display_name_public, about_me_public, location_public,
+            pronouns_public, job_title_public, company_public, links_public)
+        values($1, coalesce($2, true), coalesce($3, true), coalesce($4, true), coalesce($5, true),
+            coalesce($6, true), coalesce($7, true), coalesce($8, true))
+        on conflict (user_id) do update set
+            display_name_public=coalesce($2, public_profiles.display_name_public),
+            about_me_public=coalesce($3, public_profiles.about_me_public),
+            location_public=coalesce($4, public_profiles.location_public),
+            pronouns_public=coalesce($5, public_profiles.pronouns_public),
+            job_title_public=coalesce($6, public_profiles.job_title_public),
+            company_public=coalesce($7, public_profiles.company_public),
+            links_public=coalesce($8, public_profiles.links_public)",
+    )
+    .bind(user_data.user_id)
+    .bind(visibility_data.display_name)
+    .bind(visibility_data.about_me)
+    .bind(visibility_data.location)
+    .bind(visibility_data.pronouns)
+    .bind(visibility_data.job_title)
+    .bind(visibility_data.company)
+    .bind(visibility_data.links)
+    .execute(&app_state.database_connection_pool)
+    .await;
+
+    if upsert_res.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue updating the database".to_string(),
+        });
+    }
+
+    match crate::dbcalls::fetch_profile_details::fetch_profile_details(
+        user_data.user_id,
+        &app_state,
+    )
+    .await
+    {
+        Err(err_string) => HttpResponse::InternalServerError().json(GeneralError {
+            message: err_string,
+        }),
+        Ok((profile, links)) => HttpResponse::Ok().json(profile.into_details(links)),
+    }
+}
@@ -0,0 +1,204 @@
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, Responder};
+use validator::Validate;
+
+use crate::{
+    helpers::api_scopes::API_TOKEN_PREFIX,
+    middlewares::auth_middleware::UserData,
+    models::api_token_model::ApiTokenFromDB,
+    responses::{done_message::GoodResponse, general_error::GeneralError},
+    AppState,
+};
+
+#[derive(serde::Deserialize)]
+pub struct PathParams {
+    pub token_id: i64,
+}
+
+#[derive(serde::Serialize)]
+pub struct ApiTokenResponse {
+    pub id: i64,
+    pub name: String,
+    pub scopes: Vec<String>,
+    #[serde(rename = "createdAt")]
+    pub created_at: i64,
+    #[serde(rename = "expiresAt")]
+    pub expires_at: Option<i64>,
+    #[serde(rename = "lastUsedAt")]
+    pub last_used_at: Option<i64>,
+}
+
+#[derive(serde::Serialize)]
+pub struct CreatedApiTokenResponse {
+    #[serde(flatten)]
+    pub details: ApiTokenResponse,
+    // only ever returned here, the database keeps a hash
+    pub token: String,
+}
+
+impl From<ApiTokenFromDB> for ApiTokenResponse {
+    fn from(api_token: ApiTokenFromDB) -> Self {
+        ApiTokenResponse {
+            id: api_token.id,
+            name: api_token.name,
+            scopes: api_token.scopes,
+            created_at: api_token.created_at,
+            expires_at: api_token.expires_at,
+            last_used_at: api_token.last_used_at,
+        }
+    }
+}
+
+pub async fn list_api_tokens(req: HttpRequest, app_state: web::Data<AppState>) -> impl Responder {
+    if req.extensions().get::<UserData>().is_none() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+    let user_data = req.extensions().get::<UserData>().unwrap().clone();
+
+    let api_tokens_res = sqlx::query_as::<_, ApiTokenFromDB>(
+        "select * from api_tokens where user_id=$1 and revoked_at is null
+        and oauth_client_id is null order by id",
+    )
+    .bind(user_data.user_id)
+    .fetch_all(&app_state.database_connection_pool)
+    .await;
+
+    if api_tokens_res.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+
+    let api_tokens: Vec<ApiTokenResponse> = api_tokens_res
+        .unwrap()
+        .into_iter()
+        .map(ApiTokenResponse::from)
+        .collect();
+    HttpResponse::Ok().json(api_tokens)
+}
+
+pub async fn create_api_token(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    token_data: web::Json<crate::validation_types::user::create_api_token::CreateApiTokenData>,
+) -> impl Responder {
+    if let Err(e) = token_data.validate() {
+        let mut validation_errors: Vec<String> = Vec::new();
+        for (_, err) in e.field_errors().iter() {
+            if let Some(message) = &err[0].message {
+                validation_errors.push(message.clone().into_owned());
+            }
+        }
+        return HttpResponse::BadRequest().json(
+            crate::responses::validation_error::ValidationErrorsToBeReturned {
+                errors: validation_errors,
+            },
+        );
+    }
+
+    if req.extensions().get::<UserData>().is_none() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+    let user_data = req.extensions().get::<UserData>().unwrap().clone();
+
+    let token_id_res = app_state.snow_flake.lock().unwrap().generate_id();
+    if token_id_res.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue generating the id".to_string(),
+        });
+    }
+
+    let now = match crate::helpers::current_time::current_time_secs() {
+        Ok(now) => now,
+        Err(err_string) => {
+            return HttpResponse::InternalServerError().json(GeneralError {
+                message: err_string,
+            })
+        }
+    };
+
+    let mut scopes = token_data.scopes.clone();
+    scopes.sort();
+    scopes.dedup();
+
+    let token = format!(
+        "{}{}",
+        API_TOKEN_PREFIX,
+        crate::helpers::generate_random_token::generate_random_token()
+    );
+
+    let api_token_res = sqlx::query_as::<_, ApiTokenFromDB>(
+        "insert into api_tokens(id, user_id, name, token_hash, scopes, created_at, expires_at)
+        values($1, $2, $3, $4, $5, $6, $7) returning *",
+    )
+    .bind(token_id_res.unwrap() as i64)
+    .bind(user_data.user_id)
+    .bind(&token_data.name)
+    .bind(crate::helpers::hash_token::hash_token(&token))
+    .bind(&scopes)
+    .bind(now)
+    .bind(token_data.expires_in_days.map(|days| now + days * 86400))
+    .fetch_one(&app_state.database_connection_pool)
+    .await;
+
+    if api_token_res.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+
+    HttpResponse::Created().json(CreatedApiTokenResponse {
+        details: ApiTokenResponse::from(api_token_res.unwrap()),
+        token,
+    })
+}
+
+pub async fn revoke_api_token(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    path: web::Path<PathParams>,
+) -> impl Responder {
+    if req.extensions().get::<UserData>().is_none() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+    let user_data = req.extensions().get::<UserData>().unwrap().clone();
+
+    let now = match crate::helpers::current_time::current_time_secs() {
+        Ok(now) => now,
+        Err(err_string) => {
+            return HttpResponse::InternalServerError().json(GeneralError {
+                message: err_string,
+            })
+        }
+    };
+
+    let revoke_res = sqlx::query(
+        "update api_tokens set revoked_at=$1 where id=$2 and user_id=$3 and revoked_at is null
+        and oauth_client_id is null",
+    )
+    .bind(now)
+    .bind(path.token_id)
+    .bind(user_data.user_id)
+    .execute(&app_state.database_connection_pool)
+    .await;
+
+    if revoke_res.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+    if revoke_res.unwrap().rows_affected() == 0 {
+        return HttpResponse::NotFound().json(GeneralError {
+            message: "Token not found".to_string(),
+        });
+    }
+
+    HttpResponse::Ok().json(GoodResponse {
+        message: "Token revoked".to_string(),
+    })
+}
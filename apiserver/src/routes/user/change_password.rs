@@ -0,0 +1,113 @@
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, Responder};
+use validator::Validate;
+
+use crate::{
+    helpers::password::{hash_password, verify_password},
+    middlewares::auth_middleware::UserData,
+    models::user_model::UserFromDBWithPassword,
+    responses::general_error::GeneralError,
+    AppState,
+};
+
+// every other session is signed out, the caller gets a fresh one
+pub async fn change_password(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    password_data: web::Json<crate::validation_types::user::change_password::ChangePasswordData>,
+) -> impl Responder {
+    if let Err(e) = password_data.validate() {
+        let mut validation_errors: Vec<String> = Vec::new();
+        for (_, err) in e.field_errors().iter() {
+            if let Some(message) = &err[0].message {
+                validation_errors.push(message.clone().into_owned());
+            }
+        }
+        return HttpResponse::BadRequest().json(
+            crate::responses::validation_error::ValidationErrorsToBeReturned {
+                errors: validation_errors,
+            },
+        );
+    }
+
+    if req.extensions().get::<UserData>().is_none() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+    let user_data = req.extensions().get::<UserData>().unwrap().clone();
+
+    let user_res = sqlx::query_as::<_, UserFromDBWithPassword>("select * from users where id = $1")
+        .bind(user_data.user_id)
+        .fetch_one(&app_state.database_connection_pool)
+        .await;
+    if user_res.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+    let user = user_res.unwrap();
+
+    // without a current password to confirm, a first password needs a fresh sign in instead
+    if user.password.is_none() {
+        if let Some(required_response) =
+            crate::routes::user::reauthenticate::require_recent_authentication(
+                &user_data, &app_state,
+            )
+            .await
+        {
+            return required_response;
+        }
+    }
+
+    if user.password.is_some()
+        && !verify_password(
+            password_data.current_password.as_deref().unwrap_or(""),
+            user.password.as_deref(),
+        )
+    {
+        return HttpResponse::BadRequest().json(GeneralError {
+            message: "Incorrect password".to_string(),
+        });
+    }
+
+    let problems = crate::helpers::password_policy::password_problems(
+        &password_data.new_password,
+        &user.email,
+        app_state.breached_passwords.as_deref(),
+        app_state.min_password_score,
+    );
+    if !problems.is_empty() {
+        return HttpResponse::BadRequest().json(
+            crate::responses::validation_error::ValidationErrorsToBeReturned { errors: problems },
+        );
+    }
+
+    let password_hash = match hash_password(&password_data.new_password, &app_state.password_params)
+    {
+        Ok(password_hash) => password_hash,
+        Err(err_string) => {
+            return HttpResponse::InternalServerError().json(GeneralError {
+                message: err_string,
+            })
+        }
+    };
+    let update_res = sqlx::query("update users set password=$1 where id=$2")
+        .bind(password_hash)
+        .bind(user.id)
+        .execute(&app_state.database_connection_pool)
+        .await;
+    if update_res.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+
+    if let Err(err_string) =
+        crate::dbcalls::manage_sessions::revoke_all_sessions(user.id, &app_state).await
+    {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: err_string,
+        });
+    }
+    crate::helpers::issue_tokens::sign_in_response(&req, &app_state, user.id, &user.email).await
+}
@@ -1,8 +1,19 @@
-use crate::{responses::general_error::GeneralError, AppState};
+use crate::{
+    helpers::mailer::send_in_background,
+    responses::{done_message::GoodResponse, general_error::GeneralError},
+    AppState,
+};
 use actix_web::{web, HttpResponse, Responder};
-use bcrypt::hash;
 use validator::Validate;
 
+// new and already registered addresses get the same answer, the difference
+// only shows up in the inbox of whoever owns the address
+fn signup_received() -> HttpResponse {
+    HttpResponse::Accepted().json(GoodResponse {
+        message: "Check your email, we sent the next steps to that address".to_string(),
+    })
+}
+
 pub async fn create_user(
     data: web::Data<AppState>,
     sign_up_data: web::Json<crate::validation_types::user::signup::SignupData>,
@@ -24,11 +35,35 @@ pub async fn create_user(
         );
     }
 
+    let problems = crate::helpers::password_policy::password_problems(
+        &sign_up_data.0.password,
+        &sign_up_data.0.email,
+        data.breached_passwords.as_deref(),
+        data.min_password_score,
+    );
+    if !problems.is_empty() {
+        return HttpResponse::BadRequest().json(
+            crate::responses::validation_error::ValidationErrorsToBeReturned { errors: problems },
+        );
+    }
+
+    // hashed before the lookup so both outcomes take as long
+    let password_hash_result =
+        crate::helpers::password::hash_password(&sign_up_data.0.password, &data.password_params);
+
+    if let Err(err_string) = password_hash_result {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: err_string,
+        });
+    }
+
     // check if user with same email exists
     let user_with_same_email_result = sqlx::query_as::<_, crate::models::user_model::UserFromDB>(
-        "select * from users where email=$1",
+        "select * from users where email_sha256=$1",
     )
-    .bind(&sign_up_data.0.email)
+    .bind(crate::helpers::libravatar_hash::email_sha256(
+        &sign_up_data.0.email,
+    ))
     .fetch_optional(&data.database_connection_pool)
     .await;
 
@@ -38,10 +73,20 @@ pub async fn create_user(
         });
     }
 
-    if user_with_same_email_result.unwrap().is_some() {
-        return HttpResponse::BadRequest().json(GeneralError {
-            message: "User with same email exists in the database".to_string(),
-        });
+    if let Some(existing_user) = user_with_same_email_result.unwrap() {
+        send_in_background(
+            data.mailer.clone(),
+            existing_user.email,
+            "You already have a Gravatar account".to_string(),
+            format!(
+                "Someone tried to sign up to Gravatar with this email address, but it already has an account.\n\n\
+                If that was you, sign in at {} instead. Forgot the password? Ask for a sign in link \
+                and we will email you one.\n\n\
+                If it was not you, ignore this email, your account has not changed.",
+                data.public_url
+            ),
+        );
+        return signup_received();
     }
 
     let user_id_result: Result<u64, String>;
@@ -55,40 +100,53 @@ pub async fn create_user(
         });
     }
 
-    let digest = md5::compute(&sign_up_data.0.email);
-    let email_hash_hex = hex::encode(digest.0);
-
-    let password_hash_result = hash(&sign_up_data.0.password, 12);
-
-    if password_hash_result.is_err() {
-        return HttpResponse::InternalServerError().json(GeneralError {
-            message: "Issue hashing the password".to_string(),
-        });
-    }
+    let email_hash_hex = crate::helpers::libravatar_hash::email_md5(&sign_up_data.0.email);
+    let email_sha256_hex = crate::helpers::libravatar_hash::email_sha256(&sign_up_data.0.email);
 
     let new_user_create_result = sqlx::query_as::<_, crate::models::user_model::UserFromDB>(
-        "insert into users(email, password, email_hash, id) values(
-			$1, $2, $3, $4) returning *
+        "insert into users(email, password, email_hash, id, email_sha256) values(
+			$1, $2, $3, $4, $5) returning *
 		",
     )
     .bind(&sign_up_data.0.email)
     .bind(password_hash_result.unwrap())
     .bind(email_hash_hex)
     .bind(user_id_result.unwrap() as i64)
+    .bind(email_sha256_hex)
     .fetch_optional(&data.database_connection_pool)
     .await;
 
-    if new_user_create_result.is_err() {
+    // a concurrent signup, or the same address in different case, trips the unique hashes
+    if let Err(err) = &new_user_create_result {
+        if err
+            .as_database_error()
+            .is_some_and(|db_err| db_err.is_unique_violation())
+        {
+            return signup_received();
+        }
         return HttpResponse::InternalServerError().json(GeneralError {
             message: "Issue talking to the database".to_string(),
         });
     }
 
-    if new_user_create_result.as_ref().unwrap().is_none() {
-        return HttpResponse::BadRequest().json(GeneralError {
-            message: "User not created".to_string(),
-        });
-    }
+    let new_user = match new_user_create_result.unwrap() {
+        Some(new_user) => new_user,
+        None => {
+            return HttpResponse::BadRequest().json(GeneralError {
+                message: "User not created".to_string(),
+            })
+        }
+    };
 
-    HttpResponse::Ok().json(new_user_create_result.unwrap())
+    send_in_background(
+        data.mailer.clone(),
+        new_user.email,
+        "Welcome to Gravatar".to_string(),
+        format!(
+            "Your Gravatar account is ready, sign in at {} to add your first avatar.\n\n\
+            If you did not sign up, ignore this email or write to us and we will remove the account.",
+            data.public_url
+        ),
+    );
+    signup_received()
 }
@@ -0,0 +1,84 @@
+use actix_web::{
+    http::header::{ContentDisposition, DispositionParam, DispositionType},
+    web, HttpResponse, Responder,
+};
+
+use crate::{
+    models::export_model::DataExportFromDB, responses::general_error::GeneralError, AppState,
+};
+
+#[derive(serde::Deserialize)]
+pub struct PathParams {
+    pub token: String,
+}
+
+pub async fn download_export(
+    app_state: web::Data<AppState>,
+    path: web::Path<PathParams>,
+) -> impl Responder {
+    let export_res = sqlx::query_as::<_, DataExportFromDB>(
+        "select * from data_exports where download_token=$1 and status='ready'",
+    )
+    .bind(crate::helpers::hash_token::hash_token(&path.token))
+    .fetch_optional(&app_state.database_connection_pool)
+    .await;
+
+    if export_res.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+
+    let export = match export_res.unwrap() {
+        Some(export) => export,
+        None => {
+            return HttpResponse::NotFound().json(GeneralError {
+                message: "Export not found".to_string(),
+            })
+        }
+    };
+
+    let now = match crate::helpers::current_time::current_time_secs() {
+        Ok(now) => now,
+        Err(err_string) => {
+            return HttpResponse::InternalServerError().json(GeneralError {
+                message: err_string,
+            })
+        }
+    };
+
+    if export.file_path.is_none() || export.expires_at.unwrap_or(0) <= now {
+        // the link is dead either way, so clean up the archive as well
+        if let Some(file_path) = &export.file_path {
+            let _ = tokio::fs::remove_file(file_path).await;
+        }
+        let _ = sqlx::query(
+            "update data_exports set status='expired', download_token=null, file_path=null where id=$1",
+        )
+        .bind(export.id)
+        .execute(&app_state.database_connection_pool)
+        .await;
+
+        return HttpResponse::Gone().json(GeneralError {
+            message: "Download link has expired".to_string(),
+        });
+    }
+
+    let file_res = tokio::fs::read(export.file_path.unwrap()).await;
+    if file_res.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue reading the export".to_string(),
+        });
+    }
+
+    HttpResponse::Ok()
+        .content_type("application/zip")
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(format!(
+                "gravatar-export-{}.zip",
+                export.id
+            ))],
+        })
+        .body(file_res.unwrap())
+}
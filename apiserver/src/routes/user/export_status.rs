@@ -0,0 +1,106 @@
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, Responder};
+
+use crate::{
+    middlewares::auth_middleware::UserData, models::export_model::DataExportFromDB,
+    responses::general_error::GeneralError, AppState,
+};
+
+#[derive(serde::Deserialize)]
+pub struct PathParams {
+    pub export_id: i64,
+}
+
+#[derive(serde::Serialize)]
+pub struct ExportStatusResponse {
+    #[serde(rename = "exportId")]
+    pub export_id: i64,
+    pub status: String,
+    #[serde(rename = "downloadUrl")]
+    pub download_url: Option<String>,
+    #[serde(rename = "expiresAt")]
+    pub expires_at: Option<i64>,
+}
+
+pub async fn get_export_status(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    path: web::Path<PathParams>,
+) -> impl Responder {
+    if req.extensions().get::<UserData>().is_none() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+    let user_data = req.extensions().get::<UserData>().unwrap().clone();
+
+    let export_res = sqlx::query_as::<_, DataExportFromDB>(
+        "select * from data_exports where id=$1 and user_id=$2",
+    )
+    .bind(path.export_id)
+    .bind(user_data.user_id)
+    .fetch_optional(&app_state.database_connection_pool)
+    .await;
+
+    if export_res.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+
+    let export = match export_res.unwrap() {
+        Some(export) => export,
+        None => {
+            return HttpResponse::NotFound().json(GeneralError {
+                message: "Export not found".to_string(),
+            })
+        }
+    };
+
+    let now = match crate::helpers::current_time::current_time_secs() {
+        Ok(now) => now,
+        Err(err_string) => {
+            return HttpResponse::InternalServerError().json(GeneralError {
+                message: err_string,
+            })
+        }
+    };
+
+    let mut status = export.status;
+    let mut download_url = None;
+    if status == "ready" {
+        let expires_at = export.expires_at.unwrap_or(0);
+        if expires_at <= now {
+            status = "expired".to_string();
+        } else {
+            // the same link on every check, so polling never retires one already handed out
+            let token = crate::helpers::build_export::download_token(
+                &app_state.access_token_secret,
+                export.id,
+                expires_at,
+            );
+            let token_hash = crate::helpers::hash_token::hash_token(&token);
+            // archives built before the link was derived still carry a random digest
+            if export.download_token.as_deref() != Some(token_hash.as_str()) {
+                let update_res =
+                    sqlx::query("update data_exports set download_token=$1 where id=$2")
+                        .bind(&token_hash)
+                        .bind(export.id)
+                        .execute(&app_state.database_connection_pool)
+                        .await;
+                if update_res.is_err() {
+                    return HttpResponse::InternalServerError().json(GeneralError {
+                        message: "Issue talking to the database".to_string(),
+                    });
+                }
+            }
+            download_url = Some(format!("/api/v1/user/export/download/{}", token));
+        }
+    }
+
+    HttpResponse::Ok().json(ExportStatusResponse {
+        export_id: export.id,
+        status,
+        download_url,
+        expires_at: export.expires_at,
+    })
+}
@@ -1,23 +1,57 @@
-use actix_web::{
-    cookie::{Cookie, SameSite},
-    web, HttpResponse, Responder,
-};
-use redis::Commands;
+use actix_web::{http::header, web, HttpRequest, HttpResponse, Responder};
 use validator::Validate;
 
+use crate::{
+    dbcalls::login_protection::{
+        check_login_gate, clear_failed_logins, record_failed_login, LoginGate, LoginSubject,
+    },
+    helpers::password::{hash_password, needs_rehash, verify_password_or_dummy},
+    models::user_model::UserFromDBWithPassword,
+    responses::general_error::GeneralError,
+    AppState,
+};
+
 #[derive(serde::Serialize)]
-pub struct LoginResponse {
-    #[serde(rename = "accessToken")]
-    access_token: String,
-    #[serde(rename = "userId")]
-    user_id: i64,
+pub struct TwoFactorChallengeResponse {
+    #[serde(rename = "twoFactorRequired")]
+    pub two_factor_required: bool,
+    #[serde(rename = "challengeToken")]
+    pub challenge_token: String,
 }
 
-use crate::{
-    models::user_model::UserFromDBWithPassword, responses::general_error::GeneralError, AppState,
-};
+// the same answer for an unknown email and a wrong password, so sign in can
+// not be used to find out which addresses have an account
+fn sign_in_failed() -> HttpResponse {
+    HttpResponse::Unauthorized().json(GeneralError {
+        message: "Incorrect email or password".to_string(),
+    })
+}
+
+// turns a closed gate into the response the client sees
+pub fn gate_response(gate_res: Result<LoginGate, String>) -> Option<HttpResponse> {
+    match gate_res {
+        Err(err_string) => Some(HttpResponse::InternalServerError().json(GeneralError {
+            message: err_string,
+        })),
+        Ok(LoginGate::Locked) => Some(
+            HttpResponse::Locked().json(GeneralError {
+                message: "Account temporarily locked, use the link sent to your email to unlock it"
+                    .to_string(),
+            }),
+        ),
+        Ok(LoginGate::RetryAfter(retry_after)) => Some(
+            HttpResponse::TooManyRequests()
+                .insert_header((header::RETRY_AFTER, retry_after.to_string()))
+                .json(GeneralError {
+                    message: "Too many failed attempts, try again later".to_string(),
+                }),
+        ),
+        Ok(LoginGate::Allowed) => None,
+    }
+}
 
 pub async fn login_user(
+    req: HttpRequest,
     data: web::Data<AppState>,
     sign_in_data: web::Json<crate::validation_types::user::signin::SigninData>,
 ) -> impl Responder {
@@ -38,10 +72,15 @@ pub async fn login_user(
         );
     }
 
-    // find the user from the database
+    let ip = data
+        .trusted_proxies
+        .client_ip(req.peer_addr(), req.headers());
+
+    // by the normalized hash like every other sign in, so the case of the address does not matter
+    let email_sha256 = crate::helpers::libravatar_hash::email_sha256(&sign_in_data.0.email);
     let user_from_db_res =
-        sqlx::query_as::<_, UserFromDBWithPassword>("select * from users where email = $1")
-            .bind(&sign_in_data.0.email)
+        sqlx::query_as::<_, UserFromDBWithPassword>("select * from users where email_sha256 = $1")
+            .bind(&email_sha256)
             .fetch_optional(&data.database_connection_pool)
             .await;
     if user_from_db_res.is_err() {
@@ -49,81 +88,84 @@ pub async fn login_user(
             message: "Issue talking to the database".to_string(),
         });
     }
+    let user_from_db = user_from_db_res.unwrap();
 
-    if user_from_db_res.as_ref().unwrap().is_none() {
-        return HttpResponse::NotFound().json(GeneralError {
-            message: "User not found in the database".to_string(),
-        });
+    // repeated failures slow down and eventually lock the account before any password is checked
+    let subject = match &user_from_db {
+        Some(user) => LoginSubject::account(user),
+        None => LoginSubject::Unknown(&email_sha256),
+    };
+    let gate_res = check_login_gate(&subject, &ip, &data).await;
+    if let Some(closed_response) = gate_response(gate_res) {
+        return closed_response;
     }
 
-    // compare passwords
-    let valid_password_res = bcrypt::verify(
-        &sign_in_data.0.password,
-        &user_from_db_res
-            .as_ref()
-            .unwrap()
-            .as_ref()
-            .unwrap()
-            .password,
-    );
+    let user = match &user_from_db {
+        Some(user) => user,
+        None => {
+            verify_password_or_dummy(&sign_in_data.0.password, None, &data.dummy_password_hash);
+            if let Err(err_string) = record_failed_login(&subject, &ip, &data).await {
+                return HttpResponse::InternalServerError().json(GeneralError {
+                    message: err_string,
+                });
+            }
+            return sign_in_failed();
+        }
+    };
 
-    if valid_password_res.is_err() || !valid_password_res.unwrap() {
-        return HttpResponse::BadRequest().json(GeneralError {
-            message: "Incorrect password".to_string(),
-        });
+    // compare passwords, accounts created through single sign on have none to match
+    if !verify_password_or_dummy(
+        &sign_in_data.0.password,
+        user.password.as_deref(),
+        &data.dummy_password_hash,
+    ) {
+        if let Err(err_string) = record_failed_login(&subject, &ip, &data).await {
+            return HttpResponse::InternalServerError().json(GeneralError {
+                message: err_string,
+            });
+        }
+        return sign_in_failed();
+    }
+    // the plain password is only available now, so older hashes are upgraded here
+    if user
+        .password
+        .as_deref()
+        .is_some_and(|stored_hash| needs_rehash(stored_hash, &data.password_params))
+    {
+        if let Ok(new_hash) = hash_password(&sign_in_data.0.password, &data.password_params) {
+            let _ = sqlx::query("update users set password=$1 where id=$2 and password=$3")
+                .bind(new_hash)
+                .bind(user.id)
+                .bind(&user.password)
+                .execute(&data.database_connection_pool)
+                .await;
+        }
     }
 
-    let token_res = crate::helpers::generate_token::generate_token(
-        &sign_in_data.email,
-        user_from_db_res.as_ref().unwrap().as_ref().unwrap().id,
-        &data.access_token_secret,
-    );
-
-    if token_res.is_err() {
+    // with two factor enabled the password only earns a short lived challenge, and
+    // failures stay counted until the second factor is passed too
+    let two_factor_res = crate::dbcalls::two_factor::is_two_factor_enabled(user.id, &data).await;
+    if two_factor_res.is_err() {
         return HttpResponse::InternalServerError().json(GeneralError {
-            message: "Issue generating the token".to_string(),
+            message: "Issue talking to the database".to_string(),
         });
     }
-
-    let cookie1 = Cookie::build("accessToken", token_res.as_ref().unwrap())
-        .path("/")
-        .secure(true)
-        .http_only(true)
-        .same_site(SameSite::None)
-        .finish();
-
-    let cookie2 = Cookie::build(
-        "userId",
-        format!(
-            "{}",
-            user_from_db_res.as_ref().unwrap().as_ref().unwrap().id
-        ),
-    )
-    .path("/")
-    .secure(true)
-    .http_only(true)
-    .same_site(SameSite::None)
-    .finish();
-
-    let redis_conn = data.redis_conn.get();
-    if redis_conn.is_ok() {
-        let _: () = redis_conn
-            .unwrap()
-            .set(
-                format!(
-                    "auth:{}",
-                    user_from_db_res.as_ref().unwrap().as_ref().unwrap().id
-                ),
-                token_res.as_ref().unwrap(),
-            )
-            .unwrap();
+    if two_factor_res.unwrap() {
+        return match crate::dbcalls::two_factor::create_login_challenge(user.id, &data).await {
+            Ok(challenge_token) => HttpResponse::Ok().json(TwoFactorChallengeResponse {
+                two_factor_required: true,
+                challenge_token,
+            }),
+            Err(err_string) => HttpResponse::InternalServerError().json(GeneralError {
+                message: err_string,
+            }),
+        };
+    }
+    if let Err(err_string) = clear_failed_logins(user.id, &data).await {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: err_string,
+        });
     }
 
-    HttpResponse::Ok()
-        .cookie(cookie1)
-        .cookie(cookie2)
-        .json(LoginResponse {
-            user_id: user_from_db_res.unwrap().unwrap().id,
-            access_token: token_res.unwrap(),
-        })
+    crate::helpers::issue_tokens::sign_in_response(&req, &data, user.id, &user.email).await
 }
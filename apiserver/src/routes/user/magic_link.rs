@@ -0,0 +1,246 @@
+use actix_web::{http::header, http::StatusCode, web, HttpRequest, HttpResponse, Responder};
+use validator::Validate;
+
+use crate::{
+    helpers::{oauth::page, render_profile::escape_xml},
+    responses::{done_message::GoodResponse, general_error::GeneralError},
+    validation_types::user::two_factor::SecondFactorData,
+    AppState,
+};
+
+#[derive(serde::Deserialize)]
+pub struct PathParams {
+    pub token: String,
+}
+
+// posted by the code page when the account has two factor enabled
+#[derive(serde::Deserialize)]
+pub struct LinkSecondFactorForm {
+    pub challenge_token: String,
+    pub code: Option<String>,
+    pub recovery_code: Option<String>,
+}
+
+// the token sits in the url, so it must not leak through the referer or a cache
+fn html_page(status: StatusCode, title: &str, content: &str) -> HttpResponse {
+    HttpResponse::build(status)
+        .content_type("text/html; charset=utf-8")
+        .insert_header((header::X_FRAME_OPTIONS, "DENY"))
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .insert_header((header::REFERRER_POLICY, "no-referrer"))
+        .body(page(title, content))
+}
+
+fn error_page(status: StatusCode, message: &str) -> HttpResponse {
+    html_page(
+        status,
+        "Sign in failed",
+        &format!("<h1>Sign in failed</h1><p>{}</p>", escape_xml(message)),
+    )
+}
+
+fn second_factor_page(challenge_token: &str) -> HttpResponse {
+    html_page(
+        StatusCode::OK,
+        "Two factor authentication",
+        &format!(
+            "<h1>Enter your authenticator code</h1>\
+            <form method=\"post\" action=\"/api/v1/user/signin/link/2fa\">\
+            <input type=\"hidden\" name=\"challenge_token\" value=\"{}\">\
+            <label>Code <input name=\"code\" inputmode=\"numeric\" autocomplete=\"one-time-code\"></label>\
+            <label>Or a recovery code <input name=\"recovery_code\"></label>\
+            <button type=\"submit\">Sign in</button></form>",
+            escape_xml(challenge_token)
+        ),
+    )
+}
+
+// keeps the cookies of a finished sign in and shows the outcome instead of the json
+async fn browser_result(response: HttpResponse) -> HttpResponse {
+    let status = response.status();
+    if status.is_success() {
+        let mut signed_in = HttpResponse::Ok();
+        for cookie in response.cookies() {
+            signed_in.cookie(cookie.into_owned());
+        }
+        return signed_in
+            .content_type("text/html; charset=utf-8")
+            .insert_header((header::CACHE_CONTROL, "no-store"))
+            .body(page(
+                "Signed in",
+                "<h1>You are signed in</h1><p>You can close this tab.</p>",
+            ));
+    }
+    let body = actix_web::body::to_bytes(response.into_body()).await.ok();
+    let message = body
+        .and_then(|body| serde_json::from_slice::<serde_json::Value>(&body).ok())
+        .and_then(|body| {
+            body["message"]
+                .as_str()
+                .or(body["errors"][0].as_str())
+                .map(|message| message.to_string())
+        })
+        .unwrap_or("Sign in failed, ask for a new link".to_string());
+    error_page(status, &message)
+}
+
+// answers the same whether or not the address has an account
+pub async fn request_magic_link(
+    app_state: web::Data<AppState>,
+    magic_link_data: web::Json<crate::validation_types::user::magic_link::MagicLinkData>,
+) -> impl Responder {
+    if magic_link_data.validate().is_err() {
+        return HttpResponse::BadRequest().json(
+            crate::responses::validation_error::ValidationErrorsToBeReturned {
+                errors: vec!["Invalid email".to_string()],
+            },
+        );
+    }
+
+    if let Err(err_string) =
+        crate::dbcalls::magic_link::send_magic_link(&magic_link_data.0.email, &app_state).await
+    {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: err_string,
+        });
+    }
+    HttpResponse::Accepted().json(GoodResponse {
+        message: "If an account uses that email, a sign in link is on its way".to_string(),
+    })
+}
+
+// opened from the email, mail scanners and link previews fetch it too, so it
+// only asks for a click and leaves the token unused
+pub async fn confirm_magic_link(path: web::Path<PathParams>) -> impl Responder {
+    html_page(
+        StatusCode::OK,
+        "Sign in to Gravatar",
+        &format!(
+            "<h1>Sign in to Gravatar</h1>\
+            <form method=\"post\" action=\"/api/v1/user/signin/link/{}\">\
+            <button type=\"submit\">Sign in</button></form>",
+            escape_xml(&path.token)
+        ),
+    )
+}
+
+// the confirm page posts here, the token is only spent now
+pub async fn sign_in_with_magic_link(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    path: web::Path<PathParams>,
+) -> impl Responder {
+    // otherwise another site could sign the browser into an account of its choosing
+    if let Err(err_string) = app_state.trusted_origins.allows(req.headers()) {
+        return error_page(StatusCode::FORBIDDEN, &err_string);
+    }
+
+    let user = match crate::dbcalls::magic_link::redeem_magic_link(&path.token, &app_state).await {
+        Err(err_string) => return error_page(StatusCode::INTERNAL_SERVER_ERROR, &err_string),
+        Ok(None) => {
+            return error_page(
+                StatusCode::BAD_REQUEST,
+                "Sign in link is invalid, expired or already used",
+            )
+        }
+        Ok(Some(user)) => user,
+    };
+
+    match crate::dbcalls::two_factor::is_two_factor_enabled(user.id, &app_state).await {
+        Err(err_string) => error_page(StatusCode::INTERNAL_SERVER_ERROR, &err_string),
+        Ok(true) => {
+            match crate::dbcalls::two_factor::create_login_challenge(user.id, &app_state).await {
+                Ok(challenge_token) => second_factor_page(&challenge_token),
+                Err(err_string) => error_page(StatusCode::INTERNAL_SERVER_ERROR, &err_string),
+            }
+        }
+        Ok(false) => {
+            browser_result(
+                crate::helpers::issue_tokens::sign_in_response(
+                    &req,
+                    &app_state,
+                    user.id,
+                    &user.email,
+                )
+                .await,
+            )
+            .await
+        }
+    }
+}
+
+// the code page posts here, checked the same way as /signin/2fa
+pub async fn verify_magic_link_second_factor(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    form: web::Form<LinkSecondFactorForm>,
+) -> impl Responder {
+    if let Err(err_string) = app_state.trusted_origins.allows(req.headers()) {
+        return error_page(StatusCode::FORBIDDEN, &err_string);
+    }
+
+    // a browser sends both inputs, the one left empty was not used
+    let form = form.into_inner();
+    let second_factor_data = SecondFactorData {
+        challenge_token: form.challenge_token,
+        code: form.code.filter(|code| !code.trim().is_empty()),
+        recovery_code: form
+            .recovery_code
+            .filter(|recovery_code| !recovery_code.trim().is_empty()),
+    };
+    if let Err(e) = second_factor_data.validate() {
+        let message = e
+            .field_errors()
+            .values()
+            .filter_map(|errors| {
+                errors[0]
+                    .message
+                    .as_ref()
+                    .map(|message| message.to_string())
+            })
+            .next()
+            .unwrap_or("Invalid code".to_string());
+        return error_page(StatusCode::BAD_REQUEST, &message);
+    }
+
+    browser_result(
+        crate::routes::user::two_factor::second_factor_response(
+            &req,
+            &app_state,
+            &second_factor_data,
+        )
+        .await,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{
+        test::{self, TestRequest},
+        App,
+    };
+
+    use super::*;
+
+    #[actix_web::test]
+    async fn opening_the_link_only_asks_for_a_click() {
+        let app = test::init_service(App::new().route(
+            "/api/v1/user/signin/link/{token}",
+            web::get().to(confirm_magic_link),
+        ))
+        .await;
+        let request = TestRequest::get()
+            .uri("/api/v1/user/signin/link/abc%22def")
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert!(response.status().is_success());
+        assert_eq!(
+            response.headers().get(header::REFERRER_POLICY).unwrap(),
+            "no-referrer"
+        );
+        let body = String::from_utf8(test::read_body(response).await.to_vec()).unwrap();
+        assert!(body
+            .contains("<form method=\"post\" action=\"/api/v1/user/signin/link/abc&quot;def\">"));
+    }
+}
@@ -1,3 +1,16 @@
+pub mod api_tokens;
+pub mod change_password;
 pub mod create_user;
 pub mod current_user;
+pub mod download_export;
+pub mod export_status;
 pub mod login_user;
+pub mod magic_link;
+pub mod oidc_login;
+pub mod passkeys;
+pub mod reauthenticate;
+pub mod refresh_token;
+pub mod request_export;
+pub mod sessions;
+pub mod two_factor;
+pub mod unlock_account;
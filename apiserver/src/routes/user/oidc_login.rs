@@ -0,0 +1,357 @@
+use actix_web::{
+    cookie::{time::Duration, Cookie, SameSite},
+    http::header,
+    web, HttpRequest, HttpResponse, Responder,
+};
+
+use crate::{
+    dbcalls::link_external_identity::ExternalLogin,
+    helpers::oidc_client::{discover, exchange_code, validate_id_token, OidcProvider},
+    responses::{done_message::GoodResponse, general_error::GeneralError},
+    routes::user::login_user::TwoFactorChallengeResponse,
+    AppState,
+};
+
+const LOGIN_ATTEMPT_LIFETIME_SECS: i64 = 600;
+const STATE_COOKIE: &str = "oidcState";
+const STATE_COOKIE_PATH: &str = "/api/v1/user/oidc";
+
+#[derive(serde::Deserialize)]
+pub struct PathParams {
+    pub provider: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct CallbackParams {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct LinkPathParams {
+    pub token: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct ProviderResponse {
+    pub name: String,
+    #[serde(rename = "loginUrl")]
+    pub login_url: String,
+}
+
+#[derive(sqlx::FromRow)]
+struct LoginAttemptFromDB {
+    provider: String,
+    nonce: String,
+    code_verifier: String,
+    expires_at: i64,
+}
+
+fn find_provider<'a>(app_state: &'a AppState, name: &str) -> Option<&'a OidcProvider> {
+    app_state
+        .oidc_providers
+        .iter()
+        .find(|provider| provider.name == name)
+}
+
+fn redirect_uri(app_state: &AppState, provider: &OidcProvider) -> String {
+    format!(
+        "{}/api/v1/user/oidc/{}/callback",
+        app_state.public_url, provider.name
+    )
+}
+
+pub async fn list_providers(app_state: web::Data<AppState>) -> impl Responder {
+    let providers: Vec<ProviderResponse> = app_state
+        .oidc_providers
+        .iter()
+        .map(|provider| ProviderResponse {
+            name: provider.name.clone(),
+            login_url: format!("/api/v1/user/oidc/{}/login", provider.name),
+        })
+        .collect();
+    HttpResponse::Ok().json(providers)
+}
+
+pub async fn start_login(
+    app_state: web::Data<AppState>,
+    path: web::Path<PathParams>,
+) -> impl Responder {
+    let provider = match find_provider(&app_state, &path.provider) {
+        Some(provider) => provider,
+        None => {
+            return HttpResponse::NotFound().json(GeneralError {
+                message: "Identity provider not found".to_string(),
+            })
+        }
+    };
+
+    let metadata = match discover(&app_state.http_client, &app_state.oidc_cache, provider).await {
+        Ok(metadata) => metadata,
+        Err(err_string) => {
+            return HttpResponse::BadGateway().json(GeneralError {
+                message: err_string,
+            })
+        }
+    };
+
+    let id_res = app_state.snow_flake.lock().unwrap().generate_id();
+    if id_res.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue generating the id".to_string(),
+        });
+    }
+    let now = match crate::helpers::current_time::current_time_secs() {
+        Ok(now) => now,
+        Err(err_string) => {
+            return HttpResponse::InternalServerError().json(GeneralError {
+                message: err_string,
+            })
+        }
+    };
+
+    let state = crate::helpers::generate_random_token::generate_random_token();
+    let nonce = crate::helpers::generate_random_token::generate_random_token();
+    let code_verifier = crate::helpers::generate_random_token::generate_random_token();
+
+    let insert_res = sqlx::query(
+        "insert into oidc_logins(id, state_hash, provider, nonce, code_verifier, created_at, expires_at)
+        values($1, $2, $3, $4, $5, $6, $7)",
+    )
+    .bind(id_res.unwrap() as i64)
+    .bind(crate::helpers::hash_token::hash_token(&state))
+    .bind(&provider.name)
+    .bind(&nonce)
+    .bind(&code_verifier)
+    .bind(now)
+    .bind(now + LOGIN_ATTEMPT_LIFETIME_SECS)
+    .execute(&app_state.database_connection_pool)
+    .await;
+    if insert_res.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+
+    let mut location = match reqwest::Url::parse(&metadata.authorization_endpoint) {
+        Ok(location) => location,
+        Err(_) => {
+            return HttpResponse::BadGateway().json(GeneralError {
+                message: "Invalid identity provider metadata".to_string(),
+            })
+        }
+    };
+    location.query_pairs_mut().extend_pairs([
+        ("response_type", "code"),
+        ("client_id", provider.client_id.as_str()),
+        ("redirect_uri", redirect_uri(&app_state, provider).as_str()),
+        ("scope", provider.scopes.as_str()),
+        ("state", state.as_str()),
+        ("nonce", nonce.as_str()),
+        (
+            "code_challenge",
+            crate::helpers::oauth::pkce_challenge(&code_verifier).as_str(),
+        ),
+        ("code_challenge_method", "S256"),
+    ]);
+
+    // the state cookie ties the callback to the browser that started the login,
+    // lax so it survives the top level redirect back from the provider
+    let state_cookie = Cookie::build(STATE_COOKIE, state)
+        .path(STATE_COOKIE_PATH)
+        .secure(true)
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .max_age(Duration::seconds(LOGIN_ATTEMPT_LIFETIME_SECS))
+        .finish();
+
+    HttpResponse::Found()
+        .insert_header((header::LOCATION, location.to_string()))
+        .cookie(state_cookie)
+        .finish()
+}
+
+// the provider's own second factor says nothing about ours, so totp users get
+// the same challenge a password sign in would, emailed sign in links go through here too
+pub async fn external_sign_in_response(
+    req: &HttpRequest,
+    app_state: &AppState,
+    user_id: i64,
+    email: &str,
+) -> HttpResponse {
+    let two_factor_res =
+        crate::dbcalls::two_factor::is_two_factor_enabled(user_id, app_state).await;
+    if two_factor_res.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+    if two_factor_res.unwrap() {
+        return match crate::dbcalls::two_factor::create_login_challenge(user_id, app_state).await {
+            Ok(challenge_token) => HttpResponse::Ok().json(TwoFactorChallengeResponse {
+                two_factor_required: true,
+                challenge_token,
+            }),
+            Err(err_string) => HttpResponse::InternalServerError().json(GeneralError {
+                message: err_string,
+            }),
+        };
+    }
+    crate::helpers::issue_tokens::sign_in_response(req, app_state, user_id, email).await
+}
+
+pub async fn login_callback(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    path: web::Path<PathParams>,
+    params: web::Query<CallbackParams>,
+) -> impl Responder {
+    let provider = match find_provider(&app_state, &path.provider) {
+        Some(provider) => provider,
+        None => {
+            return HttpResponse::NotFound().json(GeneralError {
+                message: "Identity provider not found".to_string(),
+            })
+        }
+    };
+
+    if let Some(error) = &params.error {
+        return HttpResponse::Unauthorized().json(GeneralError {
+            message: format!("The identity provider refused the login: {}", error),
+        });
+    }
+    let (code, state) = match (&params.code, &params.state) {
+        (Some(code), Some(state)) => (code, state),
+        _ => {
+            return HttpResponse::BadRequest().json(GeneralError {
+                message: "Missing code or state".to_string(),
+            })
+        }
+    };
+    let cookie_state = req
+        .cookie(STATE_COOKIE)
+        .map(|cookie| cookie.value().to_string());
+    if cookie_state.as_deref() != Some(state.as_str()) {
+        return HttpResponse::BadRequest().json(GeneralError {
+            message: "Login state does not match, start again".to_string(),
+        });
+    }
+
+    // each attempt can be completed once
+    let attempt_res = sqlx::query_as::<_, LoginAttemptFromDB>(
+        "delete from oidc_logins where state_hash=$1
+        returning provider, nonce, code_verifier, expires_at",
+    )
+    .bind(crate::helpers::hash_token::hash_token(state))
+    .fetch_optional(&app_state.database_connection_pool)
+    .await;
+    if attempt_res.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+    let now = match crate::helpers::current_time::current_time_secs() {
+        Ok(now) => now,
+        Err(err_string) => {
+            return HttpResponse::InternalServerError().json(GeneralError {
+                message: err_string,
+            })
+        }
+    };
+    let attempt = match attempt_res.unwrap() {
+        Some(attempt) if attempt.provider == provider.name && attempt.expires_at > now => attempt,
+        _ => {
+            return HttpResponse::BadRequest().json(GeneralError {
+                message: "Login attempt expired, start again".to_string(),
+            })
+        }
+    };
+
+    let metadata = match discover(&app_state.http_client, &app_state.oidc_cache, provider).await {
+        Ok(metadata) => metadata,
+        Err(err_string) => {
+            return HttpResponse::BadGateway().json(GeneralError {
+                message: err_string,
+            })
+        }
+    };
+    let id_token = match exchange_code(
+        &app_state.http_client,
+        provider,
+        &metadata,
+        code,
+        &redirect_uri(&app_state, provider),
+        &attempt.code_verifier,
+    )
+    .await
+    {
+        Ok(id_token) => id_token,
+        Err(err_string) => {
+            return HttpResponse::Unauthorized().json(GeneralError {
+                message: err_string,
+            })
+        }
+    };
+    let claims = match validate_id_token(
+        &app_state.http_client,
+        &app_state.oidc_cache,
+        provider,
+        &metadata,
+        &id_token,
+        &attempt.nonce,
+    )
+    .await
+    {
+        Ok(claims) => claims,
+        Err(err_string) => {
+            return HttpResponse::Unauthorized().json(GeneralError {
+                message: err_string,
+            })
+        }
+    };
+
+    let login_res = crate::dbcalls::link_external_identity::link_external_identity(
+        &claims,
+        &provider.name,
+        &app_state,
+    )
+    .await;
+    let mut response = match login_res {
+        Err(err_string) => HttpResponse::Unauthorized().json(GeneralError {
+            message: err_string,
+        }),
+        Ok(ExternalLogin::ConfirmationSent) => HttpResponse::Accepted().json(GoodResponse {
+            message: "This email already has an account with a password, follow the link sent to it to connect this sign in"
+                .to_string(),
+        }),
+        Ok(ExternalLogin::SignedIn(user)) => {
+            external_sign_in_response(&req, &app_state, user.id, &user.email).await
+        }
+    };
+    let _ = response.add_removal_cookie(
+        &Cookie::build(STATE_COOKIE, "")
+            .path(STATE_COOKIE_PATH)
+            .finish(),
+    );
+    response
+}
+
+pub async fn confirm_link(
+    app_state: web::Data<AppState>,
+    path: web::Path<LinkPathParams>,
+) -> impl Responder {
+    match crate::dbcalls::link_external_identity::confirm_identity_link(&path.token, &app_state)
+        .await
+    {
+        Err(err_string) => HttpResponse::InternalServerError().json(GeneralError {
+            message: err_string,
+        }),
+        Ok(false) => HttpResponse::BadRequest().json(GeneralError {
+            message: "Link is invalid or expired".to_string(),
+        }),
+        Ok(true) => HttpResponse::Ok().json(GoodResponse {
+            message: "Sign in connected, you can use it to sign in now".to_string(),
+        }),
+    }
+}
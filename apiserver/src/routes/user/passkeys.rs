@@ -0,0 +1,543 @@
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, Responder};
+use validator::Validate;
+use webauthn_rs::prelude::{
+    CreationChallengeResponse, CredentialID, Passkey, PasskeyAuthentication, PasskeyRegistration,
+    RequestChallengeResponse,
+};
+
+use crate::{
+    helpers::{
+        mailer::send_in_background,
+        webauthn::{
+            decoy_authentication, encode_credential_id, store_ceremony, take_ceremony, user_handle,
+        },
+    },
+    middlewares::auth_middleware::UserData,
+    models::{passkey_model::PasskeyFromDB, user_model::UserFromDB},
+    responses::{done_message::GoodResponse, general_error::GeneralError},
+    validation_types::user::passkey::{
+        PasskeySigninFinishData, PasskeySigninStartData, RegisterPasskeyData,
+    },
+    AppState,
+};
+
+#[derive(serde::Deserialize)]
+pub struct PathParams {
+    pub passkey_id: i64,
+}
+
+#[derive(serde::Serialize)]
+pub struct PasskeyResponse {
+    pub id: i64,
+    pub name: String,
+    #[serde(rename = "createdAt")]
+    pub created_at: i64,
+    #[serde(rename = "lastUsedAt")]
+    pub last_used_at: Option<i64>,
+}
+
+#[derive(serde::Serialize)]
+pub struct PasskeySigninChallengeResponse {
+    #[serde(rename = "challengeId")]
+    pub challenge_id: String,
+    pub options: RequestChallengeResponse,
+}
+
+// login ceremony state carries the user so the assertion cannot be replayed for another account
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SigninCeremony {
+    user_id: i64,
+    state: PasskeyAuthentication,
+}
+
+impl From<PasskeyFromDB> for PasskeyResponse {
+    fn from(passkey: PasskeyFromDB) -> Self {
+        PasskeyResponse {
+            id: passkey.id,
+            name: passkey.name,
+            created_at: passkey.created_at,
+            last_used_at: passkey.last_used_at,
+        }
+    }
+}
+
+fn registration_key(user_id: i64) -> String {
+    format!("webauthn:register:{}", user_id)
+}
+
+fn signin_key(challenge_id: &str) -> String {
+    format!(
+        "webauthn:signin:{}",
+        crate::helpers::hash_token::hash_token(challenge_id)
+    )
+}
+
+fn passkeys_unavailable() -> HttpResponse {
+    HttpResponse::NotFound().json(GeneralError {
+        message: "Passkeys are not available on this server".to_string(),
+    })
+}
+
+async fn fetch_passkeys(user_id: i64, app_state: &AppState) -> Result<Vec<PasskeyFromDB>, String> {
+    let passkeys_res =
+        sqlx::query_as::<_, PasskeyFromDB>("select * from passkeys where user_id=$1 order by id")
+            .bind(user_id)
+            .fetch_all(&app_state.database_connection_pool)
+            .await;
+    match passkeys_res {
+        Err(_) => Err("Issue talking to the database".to_string()),
+        Ok(passkeys) => Ok(passkeys),
+    }
+}
+
+pub async fn list_passkeys(req: HttpRequest, app_state: web::Data<AppState>) -> impl Responder {
+    if req.extensions().get::<UserData>().is_none() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+    let user_data = req.extensions().get::<UserData>().unwrap().clone();
+
+    match fetch_passkeys(user_data.user_id, &app_state).await {
+        Ok(passkeys) => HttpResponse::Ok().json(
+            passkeys
+                .into_iter()
+                .map(PasskeyResponse::from)
+                .collect::<Vec<PasskeyResponse>>(),
+        ),
+        Err(err_string) => HttpResponse::InternalServerError().json(GeneralError {
+            message: err_string,
+        }),
+    }
+}
+
+pub async fn start_registration(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+) -> impl Responder {
+    let webauthn = match app_state.webauthn.as_deref() {
+        Some(webauthn) => webauthn,
+        None => return passkeys_unavailable(),
+    };
+    if req.extensions().get::<UserData>().is_none() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+    let user_data = req.extensions().get::<UserData>().unwrap().clone();
+
+    // a new passkey is a new way in, so a stolen session alone must not be enough to add one
+    if let Some(required_response) =
+        crate::routes::user::reauthenticate::require_recent_authentication(&user_data, &app_state)
+            .await
+    {
+        return required_response;
+    }
+
+    let passkeys = match fetch_passkeys(user_data.user_id, &app_state).await {
+        Ok(passkeys) => passkeys,
+        Err(err_string) => {
+            return HttpResponse::InternalServerError().json(GeneralError {
+                message: err_string,
+            })
+        }
+    };
+    // authenticators already registered here will not create a duplicate credential
+    let exclude_credentials: Vec<CredentialID> = passkeys
+        .iter()
+        .filter_map(|passkey| serde_json::from_str::<Passkey>(&passkey.passkey).ok())
+        .map(|passkey| passkey.cred_id().clone())
+        .collect();
+
+    let (challenge, state): (CreationChallengeResponse, PasskeyRegistration) = match webauthn
+        .start_passkey_registration(
+            user_handle(user_data.user_id),
+            &user_data.email,
+            &user_data.email,
+            Some(exclude_credentials),
+        ) {
+        Ok(started) => started,
+        Err(_) => {
+            return HttpResponse::InternalServerError().json(GeneralError {
+                message: "Issue starting the passkey registration".to_string(),
+            })
+        }
+    };
+
+    if let Err(err_string) = store_ceremony(
+        &app_state.redis_conn,
+        &registration_key(user_data.user_id),
+        &state,
+    ) {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: err_string,
+        });
+    }
+    HttpResponse::Ok().json(challenge)
+}
+
+pub async fn finish_registration(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    register_data: web::Json<RegisterPasskeyData>,
+) -> impl Responder {
+    let webauthn = match app_state.webauthn.as_deref() {
+        Some(webauthn) => webauthn,
+        None => return passkeys_unavailable(),
+    };
+    if let Err(e) = register_data.validate() {
+        let mut validation_errors: Vec<String> = Vec::new();
+        for (_, err) in e.field_errors().iter() {
+            if let Some(message) = &err[0].message {
+                validation_errors.push(message.clone().into_owned());
+            }
+        }
+        return HttpResponse::BadRequest().json(
+            crate::responses::validation_error::ValidationErrorsToBeReturned {
+                errors: validation_errors,
+            },
+        );
+    }
+
+    if req.extensions().get::<UserData>().is_none() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+    let user_data = req.extensions().get::<UserData>().unwrap().clone();
+
+    if let Some(required_response) =
+        crate::routes::user::reauthenticate::require_recent_authentication(&user_data, &app_state)
+            .await
+    {
+        return required_response;
+    }
+
+    let state = match take_ceremony::<PasskeyRegistration>(
+        &app_state.redis_conn,
+        &registration_key(user_data.user_id),
+    ) {
+        Err(err_string) => {
+            return HttpResponse::InternalServerError().json(GeneralError {
+                message: err_string,
+            })
+        }
+        Ok(None) => {
+            return HttpResponse::BadRequest().json(GeneralError {
+                message: "Passkey registration expired, start again".to_string(),
+            })
+        }
+        Ok(Some(state)) => state,
+    };
+
+    let passkey = match webauthn.finish_passkey_registration(&register_data.credential, &state) {
+        Ok(passkey) => passkey,
+        Err(_) => {
+            return HttpResponse::BadRequest().json(GeneralError {
+                message: "The passkey could not be verified".to_string(),
+            })
+        }
+    };
+    let serialized_passkey = match serde_json::to_string(&passkey) {
+        Ok(serialized_passkey) => serialized_passkey,
+        Err(_) => {
+            return HttpResponse::InternalServerError().json(GeneralError {
+                message: "Issue saving the passkey".to_string(),
+            })
+        }
+    };
+
+    let passkey_id_res = app_state.snow_flake.lock().unwrap().generate_id();
+    if passkey_id_res.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue generating the id".to_string(),
+        });
+    }
+    let now = match crate::helpers::current_time::current_time_secs() {
+        Ok(now) => now,
+        Err(err_string) => {
+            return HttpResponse::InternalServerError().json(GeneralError {
+                message: err_string,
+            })
+        }
+    };
+
+    let passkey_res = sqlx::query_as::<_, PasskeyFromDB>(
+        "insert into passkeys(id, user_id, name, credential_id, passkey, created_at)
+        values($1, $2, $3, $4, $5, $6) on conflict (credential_id) do nothing returning *",
+    )
+    .bind(passkey_id_res.unwrap() as i64)
+    .bind(user_data.user_id)
+    .bind(&register_data.name)
+    .bind(encode_credential_id(passkey.cred_id()))
+    .bind(serialized_passkey)
+    .bind(now)
+    .fetch_optional(&app_state.database_connection_pool)
+    .await;
+
+    if passkey_res.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+    match passkey_res.unwrap() {
+        Some(passkey) => {
+            send_in_background(
+                app_state.mailer.clone(),
+                user_data.email.clone(),
+                "A passkey was added to your Gravatar account".to_string(),
+                format!(
+                    "The passkey \"{}\" can now be used to sign in to your account.\n\n\
+                    If you did not add it, remove it from your account settings, sign out all \
+                    sessions and change your password.",
+                    passkey.name
+                ),
+            );
+            HttpResponse::Created().json(PasskeyResponse::from(passkey))
+        }
+        None => HttpResponse::Conflict().json(GeneralError {
+            message: "This passkey is already registered".to_string(),
+        }),
+    }
+}
+
+pub async fn delete_passkey(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    path: web::Path<PathParams>,
+) -> impl Responder {
+    if req.extensions().get::<UserData>().is_none() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+    let user_data = req.extensions().get::<UserData>().unwrap().clone();
+
+    let delete_res = sqlx::query("delete from passkeys where id=$1 and user_id=$2")
+        .bind(path.passkey_id)
+        .bind(user_data.user_id)
+        .execute(&app_state.database_connection_pool)
+        .await;
+
+    if delete_res.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+    if delete_res.unwrap().rows_affected() == 0 {
+        return HttpResponse::NotFound().json(GeneralError {
+            message: "Passkey not found".to_string(),
+        });
+    }
+
+    HttpResponse::Ok().json(GoodResponse {
+        message: "Passkey removed".to_string(),
+    })
+}
+
+pub async fn start_signin(
+    app_state: web::Data<AppState>,
+    signin_data: web::Json<PasskeySigninStartData>,
+) -> impl Responder {
+    let webauthn = match app_state.webauthn.as_deref() {
+        Some(webauthn) => webauthn,
+        None => return passkeys_unavailable(),
+    };
+    if let Err(e) = signin_data.validate() {
+        let mut validation_errors: Vec<String> = Vec::new();
+        for (_, err) in e.field_errors().iter() {
+            if let Some(message) = &err[0].message {
+                validation_errors.push(message.clone().into_owned());
+            }
+        }
+        return HttpResponse::BadRequest().json(
+            crate::responses::validation_error::ValidationErrorsToBeReturned {
+                errors: validation_errors,
+            },
+        );
+    }
+
+    let email_sha256 = crate::helpers::libravatar_hash::email_sha256(&signin_data.email);
+    let user_res = sqlx::query_as::<_, UserFromDB>(
+        "select id, email, email_hash, email_sha256, active_photo_id from users where email_sha256=$1",
+    )
+    .bind(&email_sha256)
+    .fetch_optional(&app_state.database_connection_pool)
+    .await;
+    if user_res.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+    let user = user_res.unwrap();
+
+    let passkeys: Vec<Passkey> = match &user {
+        None => Vec::new(),
+        Some(user) => match fetch_passkeys(user.id, &app_state).await {
+            Ok(passkeys) => passkeys
+                .iter()
+                .filter_map(|passkey| serde_json::from_str::<Passkey>(&passkey.passkey).ok())
+                .collect(),
+            Err(err_string) => {
+                return HttpResponse::InternalServerError().json(GeneralError {
+                    message: err_string,
+                })
+            }
+        },
+    };
+
+    // without passkeys to offer the response looks the same and simply never verifies
+    let started = if passkeys.is_empty() {
+        decoy_authentication(webauthn, &app_state.access_token_secret, &email_sha256)
+    } else {
+        webauthn
+            .start_passkey_authentication(&passkeys)
+            .map_err(|_| "Issue starting the passkey sign in".to_string())
+    };
+    let (options, state) = match started {
+        Ok(started) => started,
+        Err(err_string) => {
+            return HttpResponse::InternalServerError().json(GeneralError {
+                message: err_string,
+            })
+        }
+    };
+    let user_id = match (&user, passkeys.is_empty()) {
+        (Some(user), false) => user.id,
+        _ => 0,
+    };
+
+    let challenge_id = crate::helpers::generate_random_token::generate_random_token();
+    if let Err(err_string) = store_ceremony(
+        &app_state.redis_conn,
+        &signin_key(&challenge_id),
+        &SigninCeremony { user_id, state },
+    ) {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: err_string,
+        });
+    }
+
+    HttpResponse::Ok().json(PasskeySigninChallengeResponse {
+        challenge_id,
+        options,
+    })
+}
+
+// a passkey proves possession and user verification together, so it
+// stands in for both the password and the second factor
+pub async fn finish_signin(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    signin_data: web::Json<PasskeySigninFinishData>,
+) -> impl Responder {
+    let webauthn = match app_state.webauthn.as_deref() {
+        Some(webauthn) => webauthn,
+        None => return passkeys_unavailable(),
+    };
+    if let Err(e) = signin_data.validate() {
+        let mut validation_errors: Vec<String> = Vec::new();
+        for (_, err) in e.field_errors().iter() {
+            if let Some(message) = &err[0].message {
+                validation_errors.push(message.clone().into_owned());
+            }
+        }
+        return HttpResponse::BadRequest().json(
+            crate::responses::validation_error::ValidationErrorsToBeReturned {
+                errors: validation_errors,
+            },
+        );
+    }
+
+    let ceremony = match take_ceremony::<SigninCeremony>(
+        &app_state.redis_conn,
+        &signin_key(&signin_data.challenge_id),
+    ) {
+        Err(err_string) => {
+            return HttpResponse::InternalServerError().json(GeneralError {
+                message: err_string,
+            })
+        }
+        Ok(None) => {
+            return HttpResponse::Unauthorized().json(GeneralError {
+                message: "Sign in expired, start again".to_string(),
+            })
+        }
+        Ok(Some(ceremony)) => ceremony,
+    };
+
+    let result =
+        match webauthn.finish_passkey_authentication(&signin_data.credential, &ceremony.state) {
+            Ok(result) => result,
+            Err(_) => {
+                return HttpResponse::Unauthorized().json(GeneralError {
+                    message: "The passkey could not be verified".to_string(),
+                })
+            }
+        };
+
+    let passkey_res = sqlx::query_as::<_, PasskeyFromDB>(
+        "select * from passkeys where credential_id=$1 and user_id=$2",
+    )
+    .bind(encode_credential_id(result.cred_id()))
+    .bind(ceremony.user_id)
+    .fetch_optional(&app_state.database_connection_pool)
+    .await;
+    if passkey_res.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+    // removed between the two steps
+    let stored_passkey = match passkey_res.unwrap() {
+        Some(stored_passkey) => stored_passkey,
+        None => {
+            return HttpResponse::Unauthorized().json(GeneralError {
+                message: "The passkey could not be verified".to_string(),
+            })
+        }
+    };
+
+    let now = match crate::helpers::current_time::current_time_secs() {
+        Ok(now) => now,
+        Err(err_string) => {
+            return HttpResponse::InternalServerError().json(GeneralError {
+                message: err_string,
+            })
+        }
+    };
+    // keep the signature counter current so cloned authenticators are noticed
+    let mut serialized_passkey = stored_passkey.passkey.clone();
+    if let Ok(mut passkey) = serde_json::from_str::<Passkey>(&stored_passkey.passkey) {
+        if passkey.update_credential(&result) == Some(true) {
+            if let Ok(updated) = serde_json::to_string(&passkey) {
+                serialized_passkey = updated;
+            }
+        }
+    }
+    let update_res = sqlx::query("update passkeys set passkey=$1, last_used_at=$2 where id=$3")
+        .bind(serialized_passkey)
+        .bind(now)
+        .bind(stored_passkey.id)
+        .execute(&app_state.database_connection_pool)
+        .await;
+    if update_res.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+
+    let user_res = sqlx::query_as::<_, UserFromDB>(
+        "select id, email, email_hash, email_sha256, active_photo_id from users where id=$1",
+    )
+    .bind(ceremony.user_id)
+    .fetch_one(&app_state.database_connection_pool)
+    .await;
+    if user_res.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+    let user = user_res.unwrap();
+    crate::helpers::issue_tokens::sign_in_response(&req, &app_state, user.id, &user.email).await
+}
@@ -0,0 +1,163 @@
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, Responder};
+use validator::Validate;
+
+use crate::{
+    dbcalls::{
+        login_protection::{
+            check_login_gate, clear_failed_logins, record_failed_login, LoginSubject,
+        },
+        manage_sessions::{mark_authenticated, recently_authenticated},
+        two_factor::{is_two_factor_enabled, verify_second_factor},
+    },
+    helpers::password::verify_password,
+    middlewares::auth_middleware::UserData,
+    models::user_model::UserFromDBWithPassword,
+    responses::{done_message::GoodResponse, general_error::GeneralError},
+    AppState,
+};
+
+// sensitive changes answer with this until the session has proven itself again
+pub async fn require_recent_authentication(
+    user_data: &UserData,
+    app_state: &AppState,
+) -> Option<HttpResponse> {
+    match recently_authenticated(user_data.user_id, user_data.session_id, app_state).await {
+        Err(err_string) => Some(HttpResponse::InternalServerError().json(GeneralError {
+            message: err_string,
+        })),
+        Ok(false) => Some(HttpResponse::Forbidden().json(GeneralError {
+            message: "Confirm it is you first, re-authenticate and try again".to_string(),
+        })),
+        Ok(true) => None,
+    }
+}
+
+// asks for everything a sign in would, the password and the second factor when enabled
+pub async fn reauthenticate(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    reauthenticate_data: web::Json<
+        crate::validation_types::user::reauthenticate::ReauthenticateData,
+    >,
+) -> impl Responder {
+    if let Err(e) = reauthenticate_data.validate() {
+        let mut validation_errors: Vec<String> = Vec::new();
+        for (_, err) in e.field_errors().iter() {
+            if let Some(message) = &err[0].message {
+                validation_errors.push(message.clone().into_owned());
+            }
+        }
+        return HttpResponse::BadRequest().json(
+            crate::responses::validation_error::ValidationErrorsToBeReturned {
+                errors: validation_errors,
+            },
+        );
+    }
+
+    if req.extensions().get::<UserData>().is_none() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+    let user_data = req.extensions().get::<UserData>().unwrap().clone();
+    let session_id = match user_data.session_id {
+        Some(session_id) => session_id,
+        None => {
+            return HttpResponse::Forbidden().json(GeneralError {
+                message: "Only a signed in session can re-authenticate".to_string(),
+            })
+        }
+    };
+
+    let ip = app_state
+        .trusted_proxies
+        .client_ip(req.peer_addr(), req.headers());
+
+    let user_res = sqlx::query_as::<_, UserFromDBWithPassword>("select * from users where id = $1")
+        .bind(user_data.user_id)
+        .fetch_one(&app_state.database_connection_pool)
+        .await;
+    if user_res.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+    let user = user_res.unwrap();
+
+    // a stolen session guessing the password is held to the sign in limits
+    let gate_res = check_login_gate(&LoginSubject::account(&user), &ip, &app_state).await;
+    if let Some(closed_response) = crate::routes::user::login_user::gate_response(gate_res) {
+        return closed_response;
+    }
+
+    let two_factor_res = is_two_factor_enabled(user.id, &app_state).await;
+    if two_factor_res.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+    let two_factor_enabled = two_factor_res.unwrap();
+
+    if user.password.is_none() && !two_factor_enabled {
+        return HttpResponse::BadRequest().json(GeneralError {
+            message: "This account has no password or authenticator, sign in again through your identity provider instead".to_string(),
+        });
+    }
+
+    let password_valid = user.password.is_none()
+        || verify_password(
+            reauthenticate_data.password.as_deref().unwrap_or(""),
+            user.password.as_deref(),
+        );
+    let second_factor_valid = if password_valid && two_factor_enabled {
+        match verify_second_factor(
+            user.id,
+            reauthenticate_data.code.as_deref(),
+            reauthenticate_data.recovery_code.as_deref(),
+            &app_state,
+        )
+        .await
+        {
+            Err(err_string) => {
+                return HttpResponse::InternalServerError().json(GeneralError {
+                    message: err_string,
+                })
+            }
+            Ok(valid) => valid,
+        }
+    } else {
+        true
+    };
+
+    if !password_valid || !second_factor_valid {
+        if let Err(err_string) =
+            record_failed_login(&LoginSubject::account(&user), &ip, &app_state).await
+        {
+            return HttpResponse::InternalServerError().json(GeneralError {
+                message: err_string,
+            });
+        }
+        return HttpResponse::BadRequest().json(GeneralError {
+            message: if password_valid {
+                "Incorrect code".to_string()
+            } else {
+                "Incorrect password".to_string()
+            },
+        });
+    }
+
+    if let Err(err_string) = clear_failed_logins(user.id, &app_state).await {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: err_string,
+        });
+    }
+    if let Err(err_string) = mark_authenticated(user.id, session_id, &app_state).await {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: err_string,
+        });
+    }
+
+    HttpResponse::Ok().json(GoodResponse {
+        message: "Re-authenticated".to_string(),
+    })
+}
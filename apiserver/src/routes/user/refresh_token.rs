@@ -0,0 +1,143 @@
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use log::warn;
+
+use crate::{
+    models::{refresh_token_model::RefreshTokenFromDB, user_model::UserFromDB},
+    responses::general_error::GeneralError,
+    AppState,
+};
+
+pub async fn refresh_token(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    refresh_data: Option<web::Json<crate::validation_types::user::refresh::RefreshData>>,
+) -> impl Responder {
+    // browsers send the cookie, other clients can post the token in the body
+    let presented_token = refresh_data
+        .and_then(|data| data.0.refresh_token)
+        .or_else(|| {
+            req.cookie("refreshToken")
+                .map(|cookie| cookie.value().to_string())
+        });
+
+    let presented_token = match presented_token {
+        Some(token) => token,
+        None => {
+            return HttpResponse::Unauthorized().json(GeneralError {
+                message: "Unauthorized: Missing refresh token".to_string(),
+            })
+        }
+    };
+
+    let stored_res =
+        sqlx::query_as::<_, RefreshTokenFromDB>("select * from refresh_tokens where token_hash=$1")
+            .bind(crate::helpers::hash_token::hash_token(&presented_token))
+            .fetch_optional(&app_state.database_connection_pool)
+            .await;
+
+    if stored_res.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+
+    let stored = match stored_res.unwrap() {
+        Some(stored) => stored,
+        None => {
+            return HttpResponse::Unauthorized().json(GeneralError {
+                message: "Invalid refresh token".to_string(),
+            })
+        }
+    };
+
+    if stored.revoked {
+        return HttpResponse::Unauthorized().json(GeneralError {
+            message: "Refresh token has been revoked, login again".to_string(),
+        });
+    }
+
+    let now = match crate::helpers::current_time::current_time_secs() {
+        Ok(now) => now,
+        Err(err_string) => {
+            return HttpResponse::InternalServerError().json(GeneralError {
+                message: err_string,
+            })
+        }
+    };
+
+    if stored.expires_at <= now {
+        return HttpResponse::Unauthorized().json(GeneralError {
+            message: "Refresh token expired, login again".to_string(),
+        });
+    }
+
+    // a token can be exchanged once, the update also guards against concurrent refreshes
+    let mark_used_res =
+        sqlx::query("update refresh_tokens set used_at=$1 where id=$2 and used_at is null")
+            .bind(now)
+            .bind(stored.id)
+            .execute(&app_state.database_connection_pool)
+            .await;
+
+    if mark_used_res.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+
+    if mark_used_res.unwrap().rows_affected() == 0 {
+        warn!(
+            "Refresh token reuse detected for user {}, revoking session {}",
+            stored.user_id, stored.session_id
+        );
+        // revoking the session takes the whole token family down with it
+        let revoke_res = crate::dbcalls::manage_sessions::revoke_session(
+            stored.user_id,
+            stored.session_id,
+            &app_state,
+        )
+        .await;
+        if let Err(err_string) = revoke_res {
+            return HttpResponse::InternalServerError().json(GeneralError {
+                message: err_string,
+            });
+        }
+        return HttpResponse::Unauthorized().json(GeneralError {
+            message: "Refresh token reuse detected, login again".to_string(),
+        });
+    }
+
+    let user_res = sqlx::query_as::<_, UserFromDB>("select * from users where id=$1")
+        .bind(stored.user_id)
+        .fetch_optional(&app_state.database_connection_pool)
+        .await;
+
+    if user_res.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+
+    let user = match user_res.unwrap() {
+        Some(user) => user,
+        None => {
+            return HttpResponse::Unauthorized().json(GeneralError {
+                message: "User not found".to_string(),
+            })
+        }
+    };
+
+    match crate::helpers::issue_tokens::issue_tokens(
+        &app_state,
+        user.id,
+        &user.email,
+        stored.session_id,
+    )
+    .await
+    {
+        Err(err_string) => HttpResponse::InternalServerError().json(GeneralError {
+            message: err_string,
+        }),
+        Ok(tokens) => crate::helpers::issue_tokens::tokens_response(tokens),
+    }
+}
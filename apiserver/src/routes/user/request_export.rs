@@ -0,0 +1,99 @@
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, Responder};
+
+use crate::{
+    middlewares::auth_middleware::UserData, models::export_model::DataExportFromDB,
+    responses::general_error::GeneralError, AppState,
+};
+
+pub async fn request_export(req: HttpRequest, app_state: web::Data<AppState>) -> impl Responder {
+    if req.extensions().get::<UserData>().is_none() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+    let user_data = req.extensions().get::<UserData>().unwrap().clone();
+
+    let export_id_res = app_state.snow_flake.lock().unwrap().generate_id();
+    if export_id_res.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue generating export id".to_string(),
+        });
+    }
+
+    let now = match crate::helpers::current_time::current_time_secs() {
+        Ok(now) => now,
+        Err(err_string) => {
+            return HttpResponse::InternalServerError().json(GeneralError {
+                message: err_string,
+            })
+        }
+    };
+
+    // a build that died mid way would otherwise hold the pending slot forever
+    if let Err(err_string) = crate::helpers::build_export::fail_stale_exports(
+        &app_state.database_connection_pool,
+        Some(user_data.user_id),
+    )
+    .await
+    {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: err_string,
+        });
+    }
+
+    // failed builds do not count, the user should be able to try again
+    let recent_res = sqlx::query_scalar::<_, bool>(
+        "select exists(select 1 from data_exports where user_id=$1 and status<>'failed' and (status='pending' or created_at > $2))",
+    )
+    .bind(user_data.user_id)
+    .bind(now - crate::helpers::build_export::EXPORT_REQUEST_COOLDOWN_SECS)
+    .fetch_one(&app_state.database_connection_pool)
+    .await;
+    if recent_res.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+    if recent_res.unwrap() {
+        return HttpResponse::TooManyRequests().json(GeneralError {
+            message: "An export was requested recently, try again later".to_string(),
+        });
+    }
+
+    // the partial unique index settles two requests racing past the check above
+    let export_res = sqlx::query_as::<_, DataExportFromDB>(
+        "insert into data_exports(id, user_id, created_at) values($1, $2, $3) on conflict do nothing returning *",
+    )
+    .bind(export_id_res.unwrap() as i64)
+    .bind(user_data.user_id)
+    .bind(now)
+    .fetch_optional(&app_state.database_connection_pool)
+    .await;
+
+    if export_res.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+
+    let export = match export_res.unwrap() {
+        Some(export) => export,
+        None => {
+            return HttpResponse::TooManyRequests().json(GeneralError {
+                message: "An export was requested recently, try again later".to_string(),
+            })
+        }
+    };
+    actix_web::rt::spawn(crate::helpers::build_export::build_export(
+        app_state.clone(),
+        export.id,
+        user_data.user_id,
+    ));
+
+    HttpResponse::Accepted().json(super::export_status::ExportStatusResponse {
+        export_id: export.id,
+        status: export.status,
+        download_url: None,
+        expires_at: None,
+    })
+}
@@ -0,0 +1,143 @@
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, Responder};
+
+use crate::{
+    middlewares::auth_middleware::UserData,
+    models::session_model::SessionFromDB,
+    responses::{done_message::GoodResponse, general_error::GeneralError},
+    AppState,
+};
+
+#[derive(serde::Deserialize)]
+pub struct PathParams {
+    pub session_id: i64,
+}
+
+#[derive(serde::Serialize)]
+pub struct SessionResponse {
+    pub id: i64,
+    #[serde(rename = "userAgent")]
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    #[serde(rename = "createdAt")]
+    pub created_at: i64,
+    #[serde(rename = "lastSeenAt")]
+    pub last_seen_at: i64,
+    pub current: bool,
+}
+
+pub async fn list_sessions(req: HttpRequest, app_state: web::Data<AppState>) -> impl Responder {
+    if req.extensions().get::<UserData>().is_none() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+    let user_data = req.extensions().get::<UserData>().unwrap().clone();
+
+    let sessions_res = sqlx::query_as::<_, SessionFromDB>(
+        "select * from sessions where user_id=$1 and revoked_at is null order by last_seen_at desc",
+    )
+    .bind(user_data.user_id)
+    .fetch_all(&app_state.database_connection_pool)
+    .await;
+
+    if sessions_res.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+
+    let sessions: Vec<SessionResponse> = sessions_res
+        .unwrap()
+        .into_iter()
+        .map(|session| SessionResponse {
+            current: Some(session.id) == user_data.session_id,
+            id: session.id,
+            user_agent: session.user_agent,
+            ip: session.ip,
+            created_at: session.created_at,
+            last_seen_at: session.last_seen_at,
+        })
+        .collect();
+
+    HttpResponse::Ok().json(sessions)
+}
+
+pub async fn revoke_session(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    path: web::Path<PathParams>,
+) -> impl Responder {
+    if req.extensions().get::<UserData>().is_none() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+    let user_data = req.extensions().get::<UserData>().unwrap().clone();
+
+    let revoke_res = crate::dbcalls::manage_sessions::revoke_session(
+        user_data.user_id,
+        path.session_id,
+        &app_state,
+    )
+    .await;
+
+    match revoke_res {
+        Err(err_string) => HttpResponse::InternalServerError().json(GeneralError {
+            message: err_string,
+        }),
+        Ok(false) => HttpResponse::NotFound().json(GeneralError {
+            message: "Session not found".to_string(),
+        }),
+        Ok(true) => HttpResponse::Ok().json(GoodResponse {
+            message: "Session revoked".to_string(),
+        }),
+    }
+}
+
+// log out everywhere, including the device making the request
+pub async fn revoke_all_sessions(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+) -> impl Responder {
+    if req.extensions().get::<UserData>().is_none() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+    let user_data = req.extensions().get::<UserData>().unwrap().clone();
+
+    let revoke_res =
+        crate::dbcalls::manage_sessions::revoke_all_sessions(user_data.user_id, &app_state).await;
+    if let Err(err_string) = revoke_res {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: err_string,
+        });
+    }
+
+    crate::helpers::issue_tokens::clear_tokens_response()
+}
+
+pub async fn logout(req: HttpRequest, app_state: web::Data<AppState>) -> impl Responder {
+    if req.extensions().get::<UserData>().is_none() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+    let user_data = req.extensions().get::<UserData>().unwrap().clone();
+
+    if let Some(session_id) = user_data.session_id {
+        let revoke_res = crate::dbcalls::manage_sessions::revoke_session(
+            user_data.user_id,
+            session_id,
+            &app_state,
+        )
+        .await;
+        if let Err(err_string) = revoke_res {
+            return HttpResponse::InternalServerError().json(GeneralError {
+                message: err_string,
+            });
+        }
+    }
+
+    crate::helpers::issue_tokens::clear_tokens_response()
+}
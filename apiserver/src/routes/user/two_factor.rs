@@ -0,0 +1,369 @@
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, Responder};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use validator::Validate;
+
+use crate::{
+    dbcalls::login_protection::{
+        check_login_gate, clear_failed_logins, record_failed_login, LoginSubject,
+    },
+    dbcalls::two_factor::{
+        fetch_totp_credential, finish_login_challenge, replace_recovery_codes,
+        take_login_challenge, verify_second_factor,
+    },
+    helpers::{password::verify_password, totp},
+    middlewares::auth_middleware::UserData,
+    models::user_model::UserFromDBWithPassword,
+    responses::{done_message::GoodResponse, general_error::GeneralError},
+    validation_types::user::two_factor::{ConfirmTotpData, DisableTwoFactorData, SecondFactorData},
+    AppState,
+};
+
+const TOTP_ISSUER: &str = "Gravatar";
+
+#[derive(serde::Serialize)]
+pub struct EnrollTotpResponse {
+    pub secret: String,
+    #[serde(rename = "otpauthUri")]
+    pub otpauth_uri: String,
+    #[serde(rename = "qrCode")]
+    pub qr_code: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct RecoveryCodesResponse {
+    // only ever returned here, the database keeps hashes
+    #[serde(rename = "recoveryCodes")]
+    pub recovery_codes: Vec<String>,
+}
+
+fn validation_errors_response(e: validator::ValidationErrors) -> HttpResponse {
+    let mut validation_errors: Vec<String> = Vec::new();
+    for (_, err) in e.field_errors().iter() {
+        if let Some(message) = &err[0].message {
+            validation_errors.push(message.clone().into_owned());
+        }
+    }
+    if validation_errors.is_empty() {
+        validation_errors.push("Invalid data".to_string())
+    }
+    HttpResponse::BadRequest().json(
+        crate::responses::validation_error::ValidationErrorsToBeReturned {
+            errors: validation_errors,
+        },
+    )
+}
+
+// starting again before confirming replaces the pending secret
+pub async fn enroll_totp(req: HttpRequest, app_state: web::Data<AppState>) -> impl Responder {
+    if req.extensions().get::<UserData>().is_none() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+    let user_data = req.extensions().get::<UserData>().unwrap().clone();
+
+    match fetch_totp_credential(user_data.user_id, &app_state).await {
+        Err(err_string) => {
+            return HttpResponse::InternalServerError().json(GeneralError {
+                message: err_string,
+            })
+        }
+        Ok(Some(credential)) if credential.confirmed_at.is_some() => {
+            return HttpResponse::BadRequest().json(GeneralError {
+                message: "Two factor authentication is already enabled".to_string(),
+            })
+        }
+        Ok(_) => {}
+    }
+
+    let now = match crate::helpers::current_time::current_time_secs() {
+        Ok(now) => now,
+        Err(err_string) => {
+            return HttpResponse::InternalServerError().json(GeneralError {
+                message: err_string,
+            })
+        }
+    };
+    let secret = totp::generate_secret();
+    let upsert_res = sqlx::query(
+        "insert into totp_credentials(user_id, secret, created_at) values($1, $2, $3)
+        on conflict (user_id) do update set secret=$2, created_at=$3, confirmed_at=null, last_used_step=null",
+    )
+    .bind(user_data.user_id)
+    .bind(&secret)
+    .bind(now)
+    .execute(&app_state.database_connection_pool)
+    .await;
+    if upsert_res.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+
+    let otpauth_uri = totp::otpauth_uri(TOTP_ISSUER, &user_data.email, &secret);
+    let qr_png = match crate::helpers::generate_qr::generate_qr_png(&otpauth_uri, 256) {
+        Ok(qr_png) => qr_png,
+        Err(err_string) => {
+            return HttpResponse::InternalServerError().json(GeneralError {
+                message: err_string,
+            })
+        }
+    };
+
+    HttpResponse::Ok().json(EnrollTotpResponse {
+        secret,
+        otpauth_uri,
+        qr_code: format!("data:image/png;base64,{}", STANDARD.encode(qr_png)),
+    })
+}
+
+pub async fn confirm_totp(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    confirm_data: web::Json<ConfirmTotpData>,
+) -> impl Responder {
+    if let Err(e) = confirm_data.validate() {
+        return validation_errors_response(e);
+    }
+    if req.extensions().get::<UserData>().is_none() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+    let user_data = req.extensions().get::<UserData>().unwrap().clone();
+
+    let credential = match fetch_totp_credential(user_data.user_id, &app_state).await {
+        Err(err_string) => {
+            return HttpResponse::InternalServerError().json(GeneralError {
+                message: err_string,
+            })
+        }
+        Ok(None) => {
+            return HttpResponse::BadRequest().json(GeneralError {
+                message: "Start the enrollment first".to_string(),
+            })
+        }
+        Ok(Some(credential)) if credential.confirmed_at.is_some() => {
+            return HttpResponse::BadRequest().json(GeneralError {
+                message: "Two factor authentication is already enabled".to_string(),
+            })
+        }
+        Ok(Some(credential)) => credential,
+    };
+
+    let now = match crate::helpers::current_time::current_time_secs() {
+        Ok(now) => now,
+        Err(err_string) => {
+            return HttpResponse::InternalServerError().json(GeneralError {
+                message: err_string,
+            })
+        }
+    };
+    let step = match totp::verify_code(&credential.secret, &confirm_data.code, now, None) {
+        Some(step) => step,
+        None => {
+            return HttpResponse::BadRequest().json(GeneralError {
+                message: "Incorrect code".to_string(),
+            })
+        }
+    };
+
+    let confirm_res = sqlx::query(
+        "update totp_credentials set confirmed_at=$1, last_used_step=$2
+        where user_id=$3 and confirmed_at is null",
+    )
+    .bind(now)
+    .bind(step)
+    .bind(user_data.user_id)
+    .execute(&app_state.database_connection_pool)
+    .await;
+    if confirm_res.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+
+    match replace_recovery_codes(user_data.user_id, &app_state).await {
+        Ok(recovery_codes) => HttpResponse::Ok().json(RecoveryCodesResponse { recovery_codes }),
+        Err(err_string) => HttpResponse::InternalServerError().json(GeneralError {
+            message: err_string,
+        }),
+    }
+}
+
+// turning protection off needs the password as well as a second factor,
+// so a stolen session alone cannot do it
+pub async fn disable_two_factor(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    disable_data: web::Json<DisableTwoFactorData>,
+) -> impl Responder {
+    if let Err(e) = disable_data.validate() {
+        return validation_errors_response(e);
+    }
+    if req.extensions().get::<UserData>().is_none() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+    let user_data = req.extensions().get::<UserData>().unwrap().clone();
+
+    let user_res = sqlx::query_as::<_, UserFromDBWithPassword>("select * from users where id = $1")
+        .bind(user_data.user_id)
+        .fetch_one(&app_state.database_connection_pool)
+        .await;
+    if user_res.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+    // accounts created through single sign on rely on the second factor alone
+    let user = user_res.unwrap();
+    if user.password.is_some()
+        && !verify_password(
+            disable_data.password.as_deref().unwrap_or(""),
+            user.password.as_deref(),
+        )
+    {
+        return HttpResponse::BadRequest().json(GeneralError {
+            message: "Incorrect password".to_string(),
+        });
+    }
+
+    match verify_second_factor(
+        user_data.user_id,
+        disable_data.code.as_deref(),
+        disable_data.recovery_code.as_deref(),
+        &app_state,
+    )
+    .await
+    {
+        Err(err_string) => {
+            return HttpResponse::InternalServerError().json(GeneralError {
+                message: err_string,
+            })
+        }
+        Ok(false) => {
+            return HttpResponse::BadRequest().json(GeneralError {
+                message: "Incorrect code".to_string(),
+            })
+        }
+        Ok(true) => {}
+    }
+
+    let mut transaction = match app_state.database_connection_pool.begin().await {
+        Ok(transaction) => transaction,
+        Err(_) => {
+            return HttpResponse::InternalServerError().json(GeneralError {
+                message: "Issue talking to the database".to_string(),
+            })
+        }
+    };
+    let credential_res = sqlx::query("delete from totp_credentials where user_id=$1")
+        .bind(user_data.user_id)
+        .execute(&mut *transaction)
+        .await;
+    let codes_res = sqlx::query("delete from recovery_codes where user_id=$1")
+        .bind(user_data.user_id)
+        .execute(&mut *transaction)
+        .await;
+    if credential_res.is_err() || codes_res.is_err() || transaction.commit().await.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+
+    HttpResponse::Ok().json(GoodResponse {
+        message: "Two factor authentication disabled".to_string(),
+    })
+}
+
+// second step of a password sign in for accounts with two factor enabled
+pub async fn verify_login(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    second_factor_data: web::Json<SecondFactorData>,
+) -> impl Responder {
+    if let Err(e) = second_factor_data.validate() {
+        return validation_errors_response(e);
+    }
+    second_factor_response(&req, &app_state, &second_factor_data).await
+}
+
+// shared with the browser form that follows an emailed sign in link
+pub async fn second_factor_response(
+    req: &HttpRequest,
+    app_state: &AppState,
+    second_factor_data: &SecondFactorData,
+) -> HttpResponse {
+    let ip = app_state
+        .trusted_proxies
+        .client_ip(req.peer_addr(), req.headers());
+
+    let user_id = match take_login_challenge(&second_factor_data.challenge_token, app_state).await {
+        Err(err_string) => {
+            return HttpResponse::InternalServerError().json(GeneralError {
+                message: err_string,
+            })
+        }
+        Ok(None) => {
+            return HttpResponse::Unauthorized().json(GeneralError {
+                message: "Sign in expired, start again".to_string(),
+            })
+        }
+        Ok(Some(user_id)) => user_id,
+    };
+
+    let user_res = sqlx::query_as::<_, UserFromDBWithPassword>("select * from users where id = $1")
+        .bind(user_id)
+        .fetch_one(&app_state.database_connection_pool)
+        .await;
+    if user_res.is_err() {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: "Issue talking to the database".to_string(),
+        });
+    }
+    let user = user_res.unwrap();
+
+    // codes are guessed against the same budget as passwords, so fresh challenges buy nothing
+    let gate_res = check_login_gate(&LoginSubject::account(&user), &ip, app_state).await;
+    if let Some(closed_response) = crate::routes::user::login_user::gate_response(gate_res) {
+        return closed_response;
+    }
+
+    match verify_second_factor(
+        user_id,
+        second_factor_data.code.as_deref(),
+        second_factor_data.recovery_code.as_deref(),
+        app_state,
+    )
+    .await
+    {
+        Err(err_string) => {
+            return HttpResponse::InternalServerError().json(GeneralError {
+                message: err_string,
+            })
+        }
+        Ok(false) => {
+            if let Err(err_string) =
+                record_failed_login(&LoginSubject::account(&user), &ip, app_state).await
+            {
+                return HttpResponse::InternalServerError().json(GeneralError {
+                    message: err_string,
+                });
+            }
+            return HttpResponse::Unauthorized().json(GeneralError {
+                message: "Incorrect code".to_string(),
+            });
+        }
+        Ok(true) => {}
+    }
+    finish_login_challenge(&second_factor_data.challenge_token, app_state).await;
+    if let Err(err_string) = clear_failed_logins(user.id, app_state).await {
+        return HttpResponse::InternalServerError().json(GeneralError {
+            message: err_string,
+        });
+    }
+
+    crate::helpers::issue_tokens::sign_in_response(req, app_state, user.id, &user.email).await
+}
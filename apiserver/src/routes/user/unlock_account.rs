@@ -0,0 +1,29 @@
+use actix_web::{web, HttpResponse, Responder};
+
+use crate::{
+    responses::{done_message::GoodResponse, general_error::GeneralError},
+    AppState,
+};
+
+#[derive(serde::Deserialize)]
+pub struct PathParams {
+    pub token: String,
+}
+
+// opened from the link in the lockout email
+pub async fn unlock_account(
+    app_state: web::Data<AppState>,
+    path: web::Path<PathParams>,
+) -> impl Responder {
+    match crate::dbcalls::login_protection::unlock_account(&path.token, &app_state).await {
+        Err(err_string) => HttpResponse::InternalServerError().json(GeneralError {
+            message: err_string,
+        }),
+        Ok(false) => HttpResponse::BadRequest().json(GeneralError {
+            message: "Unlock link is invalid or expired".to_string(),
+        }),
+        Ok(true) => HttpResponse::Ok().json(GoodResponse {
+            message: "Account unlocked, you can sign in again".to_string(),
+        }),
+    }
+}
@@ -0,0 +1,93 @@
+use actix_web::{web, HttpResponse, Responder};
+
+use crate::{
+    models::user_model::UserFromDB,
+    responses::{
+        api_error::ApiError,
+        gravatar_v3::{Link, Profile},
+    },
+    AppState,
+};
+
+#[derive(serde::Deserialize)]
+pub struct PathParams {
+    pub email_hash: String,
+}
+
+pub async fn get_profile(
+    app_state: web::Data<AppState>,
+    path: web::Path<PathParams>,
+) -> impl Responder {
+    // v3 identifies profiles by the sha256 hash, the md5 one still works like on the avatar routes
+    let user_from_db_res = sqlx::query_as::<_, UserFromDB>(
+        "select * from users where email_sha256 = $1 or email_hash = $1",
+    )
+    .bind(path.email_hash.to_lowercase())
+    .fetch_optional(&app_state.database_connection_pool)
+    .await;
+
+    if user_from_db_res.is_err() {
+        return HttpResponse::InternalServerError().json(ApiError {
+            error: "Issue talking to the database".to_string(),
+            code: "internal_error".to_string(),
+        });
+    }
+
+    let user = match user_from_db_res.unwrap() {
+        Some(user) => user,
+        None => {
+            return HttpResponse::NotFound().json(ApiError {
+                error: "Profile not found".to_string(),
+                code: "not_found".to_string(),
+            })
+        }
+    };
+
+    let details_res =
+        crate::dbcalls::fetch_profile_details::fetch_profile_details(user.id, &app_state).await;
+    if details_res.is_err() {
+        return HttpResponse::InternalServerError().json(ApiError {
+            error: "Issue talking to the database".to_string(),
+            code: "internal_error".to_string(),
+        });
+    }
+    let (details, links) = details_res.unwrap();
+    let visible = details.into_visible(links);
+
+    let avatar_url = if user.active_photo_id == -1 {
+        "".to_string()
+    } else {
+        crate::helpers::image_url::image_url(
+            &app_state.cloudinary_cloud_name,
+            user.id,
+            user.active_photo_id,
+            None,
+        )
+    };
+
+    HttpResponse::Ok().json(Profile {
+        hash: path.email_hash.clone(),
+        display_name: visible.display_name.unwrap_or_default(),
+        profile_url: crate::helpers::render_profile::profile_url(
+            &app_state.public_url,
+            &path.email_hash,
+        ),
+        avatar_url,
+        avatar_alt_text: "".to_string(),
+        location: visible.location.unwrap_or_default(),
+        description: visible.about_me.unwrap_or_default(),
+        job_title: visible.job_title.unwrap_or_default(),
+        company: visible.company.unwrap_or_default(),
+        verified_accounts: Vec::new(),
+        pronunciation: "".to_string(),
+        pronouns: visible.pronouns.unwrap_or_default(),
+        links: visible
+            .links
+            .into_iter()
+            .map(|link| Link {
+                label: link.label,
+                url: link.url,
+            })
+            .collect(),
+    })
+}
@@ -0,0 +1,58 @@
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, Responder};
+
+use crate::{
+    helpers::api_scopes,
+    middlewares::auth_middleware::UserData,
+    models::user_model::UserFromDB,
+    responses::{api_error::ApiError, gravatar_v3::Avatar},
+    AppState,
+};
+
+pub async fn list_avatars(req: HttpRequest, app_state: web::Data<AppState>) -> impl Responder {
+    if req.extensions().get::<UserData>().is_none() {
+        return HttpResponse::InternalServerError().json(ApiError {
+            error: "Issue talking to the database".to_string(),
+            code: "internal_error".to_string(),
+        });
+    }
+    let user_data = req.extensions().get::<UserData>().unwrap().clone();
+    if !user_data.has_scope(api_scopes::AVATARS_READ) {
+        return crate::middlewares::auth_middleware::insufficient_scope(api_scopes::AVATARS_READ);
+    }
+
+    let user_from_db_res = sqlx::query_as::<_, UserFromDB>("select * from users where id = $1")
+        .bind(user_data.user_id)
+        .fetch_one(&app_state.database_connection_pool)
+        .await;
+    if user_from_db_res.is_err() {
+        return HttpResponse::InternalServerError().json(ApiError {
+            error: "Issue talking to the database".to_string(),
+            code: "internal_error".to_string(),
+        });
+    }
+    let user = user_from_db_res.unwrap();
+
+    match crate::dbcalls::fetch_profile_images::fetch_profile_images(user.id, &app_state).await {
+        Err(err_string) => HttpResponse::InternalServerError().json(ApiError {
+            error: err_string,
+            code: "internal_error".to_string(),
+        }),
+        Ok(profile_pics) => HttpResponse::Ok().json(
+            profile_pics
+                .iter()
+                .map(|profile| {
+                    Avatar::new(
+                        profile.id,
+                        crate::helpers::image_url::image_url(
+                            &app_state.cloudinary_cloud_name,
+                            user.id,
+                            profile.id,
+                            None,
+                        ),
+                        profile.id == user.active_photo_id,
+                    )
+                })
+                .collect::<Vec<Avatar>>(),
+        ),
+    }
+}
@@ -0,0 +1,4 @@
+pub mod get_profile;
+pub mod list_avatars;
+pub mod select_avatar;
+pub mod upload_avatar;
@@ -0,0 +1,75 @@
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, Responder};
+use validator::Validate;
+
+use crate::{
+    helpers::api_scopes, middlewares::auth_middleware::UserData, models::user_model::UserFromDB,
+    responses::api_error::ApiError, AppState,
+};
+
+#[derive(serde::Deserialize)]
+pub struct PathParams {
+    pub image_id: i64,
+}
+
+pub async fn select_avatar(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    path: web::Path<PathParams>,
+    select_data: web::Json<crate::validation_types::v3::select_avatar::SelectAvatarData>,
+) -> impl Responder {
+    if select_data.validate().is_err() {
+        return HttpResponse::BadRequest().json(ApiError {
+            error: "Email hash is required".to_string(),
+            code: "bad_request".to_string(),
+        });
+    }
+
+    if req.extensions().get::<UserData>().is_none() {
+        return HttpResponse::InternalServerError().json(ApiError {
+            error: "Issue talking to the database".to_string(),
+            code: "internal_error".to_string(),
+        });
+    }
+    let user_data = req.extensions().get::<UserData>().unwrap().clone();
+    if !user_data.has_scope(api_scopes::AVATARS_WRITE) {
+        return crate::middlewares::auth_middleware::insufficient_scope(api_scopes::AVATARS_WRITE);
+    }
+
+    // the only email we know about for a user is the one they signed up with
+    let user_from_db_res =
+        sqlx::query_as::<_, UserFromDB>("select * from users where id = $1 and email_hash = $2")
+            .bind(user_data.user_id)
+            .bind(&select_data.email_hash)
+            .fetch_optional(&app_state.database_connection_pool)
+            .await;
+    if user_from_db_res.is_err() {
+        return HttpResponse::InternalServerError().json(ApiError {
+            error: "Issue talking to the database".to_string(),
+            code: "internal_error".to_string(),
+        });
+    }
+    if user_from_db_res.unwrap().is_none() {
+        return HttpResponse::NotFound().json(ApiError {
+            error: "Email not found for this account".to_string(),
+            code: "not_found".to_string(),
+        });
+    }
+
+    match crate::dbcalls::select_profile_image::select_profile_image(
+        user_data.user_id,
+        path.image_id,
+        &app_state,
+    )
+    .await
+    {
+        Err(err_string) => HttpResponse::InternalServerError().json(ApiError {
+            error: err_string,
+            code: "internal_error".to_string(),
+        }),
+        Ok(false) => HttpResponse::NotFound().json(ApiError {
+            error: "Avatar not found".to_string(),
+            code: "not_found".to_string(),
+        }),
+        Ok(true) => HttpResponse::NoContent().finish(),
+    }
+}
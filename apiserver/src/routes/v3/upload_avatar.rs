@@ -0,0 +1,62 @@
+use actix_multipart::form::MultipartForm;
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, Responder};
+
+use crate::{
+    helpers::api_scopes,
+    middlewares::auth_middleware::UserData,
+    responses::{api_error::ApiError, gravatar_v3::Avatar},
+    validation_types::v3::upload_avatar::UploadAvatarForm,
+    AppState,
+};
+
+pub async fn upload_avatar(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    MultipartForm(form): MultipartForm<UploadAvatarForm>,
+) -> impl Responder {
+    if req.extensions().get::<UserData>().is_none() {
+        return HttpResponse::InternalServerError().json(ApiError {
+            error: "Issue talking to the database".to_string(),
+            code: "internal_error".to_string(),
+        });
+    }
+    let user_data = req.extensions().get::<UserData>().unwrap().clone();
+    if !user_data.has_scope(api_scopes::AVATARS_WRITE) {
+        return crate::middlewares::auth_middleware::insufficient_scope(api_scopes::AVATARS_WRITE);
+    }
+
+    let is_image = form
+        .image
+        .content_type
+        .as_ref()
+        .is_some_and(|content_type| content_type.type_() == mime::IMAGE);
+    if !is_image {
+        return HttpResponse::BadRequest().json(ApiError {
+            error: "Not an image".to_string(),
+            code: "invalid_image".to_string(),
+        });
+    }
+
+    match crate::dbcalls::add_profile_image::add_profile_image(
+        user_data.user_id,
+        form.image.file.path(),
+        &app_state,
+    )
+    .await
+    {
+        Err(err_string) => HttpResponse::InternalServerError().json(ApiError {
+            error: err_string,
+            code: "internal_error".to_string(),
+        }),
+        Ok(added_image) => HttpResponse::Ok().json(Avatar::new(
+            added_image.profile_id,
+            crate::helpers::image_url::image_url(
+                &app_state.cloudinary_cloud_name,
+                user_data.user_id,
+                added_image.profile_id,
+                None,
+            ),
+            false,
+        )),
+    }
+}
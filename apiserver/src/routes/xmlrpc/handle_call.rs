@@ -0,0 +1,422 @@
+use std::io::Write;
+
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+
+use crate::{
+    dbcalls::login_protection::{
+        check_login_gate, clear_failed_logins, record_failed_login, LoginGate, LoginSubject,
+    },
+    helpers::{
+        api_scopes::{API_TOKEN_PREFIX, AVATARS_READ, AVATARS_WRITE},
+        xmlrpc::{fault_response, method_response, parse_method_call, XmlRpcValue},
+    },
+    models::user_model::UserFromDBWithPassword,
+    AppState,
+};
+
+// fault codes used by the original gravatar xml-rpc service
+const FAULT_INTERNAL: i64 = -8;
+const FAULT_AUTHENTICATION: i64 = -9;
+const FAULT_METHOD_NOT_FOUND: i64 = -10;
+const FAULT_MISSING_PARAMETER: i64 = -11;
+
+#[derive(serde::Deserialize)]
+pub struct QueryParams {
+    pub user: String,
+}
+
+struct Fault {
+    code: i64,
+    message: String,
+}
+
+impl Fault {
+    fn new(code: i64, message: &str) -> Self {
+        Fault {
+            code,
+            message: message.to_string(),
+        }
+    }
+}
+
+pub async fn handle_xmlrpc_call(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    query: web::Query<QueryParams>,
+    body: String,
+) -> impl Responder {
+    let ip = app_state
+        .trusted_proxies
+        .client_ip(req.peer_addr(), req.headers());
+    let response_body = match dispatch(&app_state, &query.user, &ip, &body).await {
+        Ok(value) => method_response(&value),
+        Err(fault) => fault_response(fault.code, &fault.message),
+    };
+
+    // xml-rpc reports faults inside a successful http response
+    HttpResponse::Ok()
+        .content_type("text/xml; charset=utf-8")
+        .body(response_body)
+}
+
+async fn dispatch(
+    app_state: &AppState,
+    email_hash: &str,
+    ip: &str,
+    body: &str,
+) -> Result<XmlRpcValue, Fault> {
+    let call = match parse_method_call(body) {
+        Ok(call) => call,
+        Err(err_string) => return Err(Fault::new(FAULT_MISSING_PARAMETER, &err_string)),
+    };
+    let args = call
+        .params
+        .into_iter()
+        .next()
+        .unwrap_or(XmlRpcValue::Struct(Vec::new()));
+
+    let (user, scopes) = authenticate(app_state, email_hash, ip, &args).await?;
+
+    // personal access tokens are held to their scopes like on the rest api
+    let required_scope = match call.method_name.as_str() {
+        "grav.saveData" | "grav.useUserimage" | "grav.removeImage" => Some(AVATARS_WRITE),
+        "grav.addresses" | "grav.userimages" => Some(AVATARS_READ),
+        _ => None,
+    };
+    if let (Some(required_scope), Some(scopes)) = (required_scope, &scopes) {
+        if !scopes.iter().any(|scope| scope == required_scope) {
+            return Err(Fault::new(
+                FAULT_AUTHENTICATION,
+                &format!("Token is missing the {} scope", required_scope),
+            ));
+        }
+    }
+
+    match call.method_name.as_str() {
+        "grav.exists" => exists(app_state, &args).await,
+        "grav.addresses" => addresses(app_state, &user),
+        "grav.userimages" => user_images(app_state, &user).await,
+        "grav.saveData" => save_data(app_state, &user, &args).await,
+        "grav.useUserimage" => use_user_image(app_state, &user, &args).await,
+        "grav.removeImage" => remove_image(app_state, &user, &args).await,
+        _ => Err(Fault::new(FAULT_METHOD_NOT_FOUND, "Method not found")),
+    }
+}
+
+// legacy clients only know a password field, accounts with two factor put a
+// personal access token there instead
+async fn authenticate(
+    app_state: &AppState,
+    email_hash: &str,
+    ip: &str,
+    args: &XmlRpcValue,
+) -> Result<(UserFromDBWithPassword, Option<Vec<String>>), Fault> {
+    let password = match args.member("password").and_then(|value| value.as_str()) {
+        Some(password) => password,
+        None => {
+            return Err(Fault::new(
+                FAULT_MISSING_PARAMETER,
+                "Missing password parameter",
+            ))
+        }
+    };
+
+    let user_from_db_res =
+        sqlx::query_as::<_, UserFromDBWithPassword>("select * from users where email_hash = $1")
+            .bind(email_hash)
+            .fetch_optional(&app_state.database_connection_pool)
+            .await;
+
+    if user_from_db_res.is_err() {
+        return Err(Fault::new(FAULT_INTERNAL, "Issue talking to the database"));
+    }
+    let user_from_db = user_from_db_res.unwrap();
+
+    // the same backoff and lockout as the sign in route, unknown hashes included
+    let subject = match &user_from_db {
+        Some(user) => LoginSubject::account(user),
+        None => LoginSubject::Unknown(email_hash),
+    };
+    let gate_res = check_login_gate(&subject, ip, app_state).await;
+    match gate_res {
+        Err(err_string) => return Err(Fault::new(FAULT_INTERNAL, &err_string)),
+        Ok(LoginGate::Locked) => {
+            return Err(Fault::new(
+                FAULT_AUTHENTICATION,
+                "Account temporarily locked",
+            ))
+        }
+        Ok(LoginGate::RetryAfter(_)) => {
+            return Err(Fault::new(
+                FAULT_AUTHENTICATION,
+                "Too many failed attempts, try again later",
+            ))
+        }
+        Ok(LoginGate::Allowed) => {}
+    }
+
+    let user = match user_from_db {
+        Some(user) => user,
+        None => {
+            crate::helpers::password::verify_password_or_dummy(
+                password,
+                None,
+                &app_state.dummy_password_hash,
+            );
+            let unknown = LoginSubject::Unknown(email_hash);
+            if let Err(err_string) = record_failed_login(&unknown, ip, app_state).await {
+                return Err(Fault::new(FAULT_INTERNAL, &err_string));
+            }
+            return Err(Fault::new(
+                FAULT_AUTHENTICATION,
+                "Error validating password",
+            ));
+        }
+    };
+
+    // scopes stay none for a password, which is not limited by scope
+    let (valid, scopes) = if password.starts_with(API_TOKEN_PREFIX) {
+        match crate::dbcalls::find_api_token::find_api_token(password, app_state).await {
+            Err(err_string) => return Err(Fault::new(FAULT_INTERNAL, &err_string)),
+            Ok(Some(owner)) if owner.user_id == user.id => (true, Some(owner.scopes)),
+            Ok(_) => (false, None),
+        }
+    } else {
+        (
+            crate::helpers::password::verify_password_or_dummy(
+                password,
+                user.password.as_deref(),
+                &app_state.dummy_password_hash,
+            ),
+            None,
+        )
+    };
+
+    if !valid {
+        let account = LoginSubject::account(&user);
+        if let Err(err_string) = record_failed_login(&account, ip, app_state).await {
+            return Err(Fault::new(FAULT_INTERNAL, &err_string));
+        }
+        return Err(Fault::new(
+            FAULT_AUTHENTICATION,
+            "Error validating password",
+        ));
+    }
+    // a password alone is one factor, which is not enough once two factor is on,
+    // and it must not wipe the second factor failures counted against the account
+    if scopes.is_none() {
+        match crate::dbcalls::two_factor::is_two_factor_enabled(user.id, app_state).await {
+            Err(_) => return Err(Fault::new(FAULT_INTERNAL, "Issue talking to the database")),
+            Ok(true) => return Err(Fault::new(
+                FAULT_AUTHENTICATION,
+                "Two factor authentication is enabled, use a personal access token as the password",
+            )),
+            Ok(false) => {}
+        }
+    }
+    if let Err(err_string) = clear_failed_logins(user.id, app_state).await {
+        return Err(Fault::new(FAULT_INTERNAL, &err_string));
+    }
+
+    Ok((user, scopes))
+}
+
+fn string_list(args: &XmlRpcValue, name: &str) -> Result<Vec<String>, Fault> {
+    match args.member(name).and_then(|value| value.as_array()) {
+        Some(values) => Ok(values
+            .iter()
+            .filter_map(|value| value.as_str().map(|value| value.to_string()))
+            .collect()),
+        None => Err(Fault::new(
+            FAULT_MISSING_PARAMETER,
+            &format!("Missing {} parameter", name),
+        )),
+    }
+}
+
+fn image_url(app_state: &AppState, user_id: i64, profile_id: i64) -> String {
+    crate::helpers::image_url::image_url(
+        &app_state.cloudinary_cloud_name,
+        user_id,
+        profile_id,
+        None,
+    )
+}
+
+#[derive(sqlx::FromRow)]
+struct ExistingHash {
+    email_hash: String,
+}
+
+async fn exists(app_state: &AppState, args: &XmlRpcValue) -> Result<XmlRpcValue, Fault> {
+    let hashes = string_list(args, "hashes")?;
+
+    let existing_res = sqlx::query_as::<_, ExistingHash>(
+        "select email_hash from users where email_hash = any($1)",
+    )
+    .bind(&hashes)
+    .fetch_all(&app_state.database_connection_pool)
+    .await;
+
+    if existing_res.is_err() {
+        return Err(Fault::new(FAULT_INTERNAL, "Issue talking to the database"));
+    }
+    let existing = existing_res.unwrap();
+
+    Ok(XmlRpcValue::Struct(
+        hashes
+            .into_iter()
+            .map(|hash| {
+                let found = existing.iter().any(|row| row.email_hash == hash);
+                (hash, XmlRpcValue::Int(if found { 1 } else { 0 }))
+            })
+            .collect(),
+    ))
+}
+
+fn addresses(app_state: &AppState, user: &UserFromDBWithPassword) -> Result<XmlRpcValue, Fault> {
+    let (user_image, user_image_url) = if user.active_photo_id == -1 {
+        ("".to_string(), "".to_string())
+    } else {
+        (
+            user.active_photo_id.to_string(),
+            image_url(app_state, user.id, user.active_photo_id),
+        )
+    };
+
+    Ok(XmlRpcValue::Struct(vec![(
+        user.email.clone(),
+        XmlRpcValue::Struct(vec![
+            ("rating".to_string(), XmlRpcValue::Int(0)),
+            ("userimage".to_string(), XmlRpcValue::String(user_image)),
+            (
+                "userimage_url".to_string(),
+                XmlRpcValue::String(user_image_url),
+            ),
+        ]),
+    )]))
+}
+
+async fn user_images(
+    app_state: &AppState,
+    user: &UserFromDBWithPassword,
+) -> Result<XmlRpcValue, Fault> {
+    match crate::dbcalls::fetch_profile_images::fetch_profile_images(user.id, app_state).await {
+        Err(err_string) => Err(Fault::new(FAULT_INTERNAL, &err_string)),
+        Ok(profile_pics) => Ok(XmlRpcValue::Struct(
+            profile_pics
+                .iter()
+                .map(|profile| {
+                    (
+                        profile.id.to_string(),
+                        XmlRpcValue::Array(vec![
+                            XmlRpcValue::Int(0),
+                            XmlRpcValue::String(image_url(app_state, user.id, profile.id)),
+                        ]),
+                    )
+                })
+                .collect(),
+        )),
+    }
+}
+
+async fn save_data(
+    app_state: &AppState,
+    user: &UserFromDBWithPassword,
+    args: &XmlRpcValue,
+) -> Result<XmlRpcValue, Fault> {
+    let data = match args.member("data").and_then(|value| value.as_bytes()) {
+        Some(data) => data,
+        None => {
+            return Err(Fault::new(
+                FAULT_MISSING_PARAMETER,
+                "Missing data parameter",
+            ))
+        }
+    };
+
+    if data.len() > app_state.max_upload_bytes || image::guess_format(data).is_err() {
+        return Ok(XmlRpcValue::Boolean(false));
+    }
+
+    let temp_file_res = tempfile::NamedTempFile::new();
+    if temp_file_res.is_err() {
+        return Err(Fault::new(FAULT_INTERNAL, "Issue storing the image"));
+    }
+    let mut temp_file = temp_file_res.unwrap();
+    if temp_file.write_all(data).is_err() {
+        return Err(Fault::new(FAULT_INTERNAL, "Issue storing the image"));
+    }
+
+    match crate::dbcalls::add_profile_image::add_profile_image(user.id, temp_file.path(), app_state)
+        .await
+    {
+        Err(err_string) => Err(Fault::new(FAULT_INTERNAL, &err_string)),
+        Ok(added_image) => Ok(XmlRpcValue::String(added_image.profile_id.to_string())),
+    }
+}
+
+async fn use_user_image(
+    app_state: &AppState,
+    user: &UserFromDBWithPassword,
+    args: &XmlRpcValue,
+) -> Result<XmlRpcValue, Fault> {
+    let profile_id = match args
+        .member("userimage")
+        .and_then(|value| value.as_str())
+        .and_then(|value| value.parse::<i64>().ok())
+    {
+        Some(profile_id) => profile_id,
+        None => {
+            return Err(Fault::new(
+                FAULT_MISSING_PARAMETER,
+                "Missing userimage parameter",
+            ))
+        }
+    };
+    let addresses = string_list(args, "addresses")?;
+
+    let mut results = Vec::new();
+    for address in addresses {
+        let mut selected = false;
+        if address.eq_ignore_ascii_case(&user.email) {
+            match crate::dbcalls::select_profile_image::select_profile_image(
+                user.id, profile_id, app_state,
+            )
+            .await
+            {
+                Err(err_string) => return Err(Fault::new(FAULT_INTERNAL, &err_string)),
+                Ok(found) => selected = found,
+            }
+        }
+        results.push((address, XmlRpcValue::Boolean(selected)));
+    }
+
+    Ok(XmlRpcValue::Struct(results))
+}
+
+async fn remove_image(
+    app_state: &AppState,
+    user: &UserFromDBWithPassword,
+    args: &XmlRpcValue,
+) -> Result<XmlRpcValue, Fault> {
+    let addresses = string_list(args, "addresses")?;
+
+    let mut results = Vec::new();
+    for address in addresses {
+        let mut removed = false;
+        if address.eq_ignore_ascii_case(&user.email) {
+            let update_res = sqlx::query("update users set active_photo_id=-1 where id=$1")
+                .bind(user.id)
+                .execute(&app_state.database_connection_pool)
+                .await;
+            if update_res.is_err() {
+                return Err(Fault::new(FAULT_INTERNAL, "Issue updating the database"));
+            }
+            removed = true;
+        }
+        results.push((address, XmlRpcValue::Boolean(removed)));
+    }
+
+    Ok(XmlRpcValue::Struct(results))
+}
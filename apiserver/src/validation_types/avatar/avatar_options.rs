@@ -0,0 +1,59 @@
+// query parameters shared by the libravatar routes, both short and long names are accepted
+#[derive(serde::Deserialize, Clone, Default)]
+pub struct AvatarOptions {
+    #[serde(alias = "size")]
+    pub s: Option<u32>,
+    #[serde(alias = "default")]
+    pub d: Option<String>,
+    #[serde(alias = "forcedefault")]
+    pub f: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct FederatedAvatarQuery {
+    pub email: String,
+    #[serde(alias = "size")]
+    pub s: Option<u32>,
+    #[serde(alias = "default")]
+    pub d: Option<String>,
+    #[serde(alias = "forcedefault")]
+    pub f: Option<String>,
+}
+
+impl AvatarOptions {
+    pub fn size(&self) -> u32 {
+        self.s.unwrap_or(80).clamp(1, 512)
+    }
+
+    pub fn force_default(&self) -> bool {
+        matches!(
+            self.f.as_deref(),
+            Some("y") | Some("yes") | Some("true") | Some("1")
+        )
+    }
+
+    // the options as query pairs so redirects keep them
+    pub fn query_pairs(&self) -> Vec<(&'static str, String)> {
+        let mut pairs = Vec::new();
+        if let Some(size) = self.s {
+            pairs.push(("s", size.to_string()));
+        }
+        if let Some(default) = &self.d {
+            pairs.push(("d", default.clone()));
+        }
+        if let Some(force_default) = &self.f {
+            pairs.push(("f", force_default.clone()));
+        }
+        pairs
+    }
+}
+
+impl FederatedAvatarQuery {
+    pub fn options(&self) -> AvatarOptions {
+        AvatarOptions {
+            s: self.s,
+            d: self.d.clone(),
+            f: self.f.clone(),
+        }
+    }
+}
@@ -1,2 +1,5 @@
+pub mod avatar;
+pub mod oauth;
 pub mod profile;
 pub mod user;
+pub mod v3;
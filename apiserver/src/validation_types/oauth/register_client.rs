@@ -0,0 +1,41 @@
+use validator::{Validate, ValidationError};
+
+// redirect uris are matched exactly, so they must be absolute and carry no fragment
+fn validate_redirect_uris(redirect_uris: &[String]) -> Result<(), ValidationError> {
+    let valid = redirect_uris
+        .iter()
+        .all(|redirect_uri| match reqwest::Url::parse(redirect_uri) {
+            Ok(url) => url.fragment().is_none() && redirect_uri.len() <= 500,
+            Err(_) => false,
+        });
+    if valid {
+        return Ok(());
+    }
+    Err(ValidationError::new("redirect_uris")
+        .with_message("Redirect uris should be absolute urls without a fragment".into()))
+}
+
+#[derive(Validate, serde::Deserialize)]
+pub struct RegisterClientData {
+    #[validate(length(
+        min = 1,
+        max = 100,
+        message = "Client name should be between 1 and 100 length"
+    ))]
+    pub name: String,
+    #[serde(rename = "redirectUris")]
+    #[validate(length(
+        min = 1,
+        max = 10,
+        message = "Between 1 and 10 redirect uris are allowed"
+    ))]
+    #[validate(custom(function = "validate_redirect_uris"))]
+    pub redirect_uris: Vec<String>,
+    // public clients such as mobile and single page apps get no secret and rely on pkce
+    #[serde(default = "default_confidential")]
+    pub confidential: bool,
+}
+
+fn default_confidential() -> bool {
+    true
+}
@@ -1,7 +1,7 @@
 use actix_multipart::form::{tempfile::TempFile, MultipartForm};
 
+// the size limit comes from the MultipartFormConfig registered in main
 #[derive(Debug, MultipartForm)]
 pub struct UploadForm {
-    #[multipart(limit = "100KB")]
     pub file: TempFile,
 }
@@ -0,0 +1,8 @@
+use validator::Validate;
+
+#[derive(Validate, serde::Deserialize)]
+pub struct AddOpenidUrlData {
+    #[validate(custom(function = "super::update_details::validate_web_url"))]
+    #[validate(length(max = 500, message = "Openid url should be at most 500 length"))]
+    pub url: String,
+}
@@ -1,2 +1,5 @@
 pub mod add_image;
+pub mod add_openid_url;
+pub mod update_details;
 pub mod update_profile;
+pub mod update_visibility;
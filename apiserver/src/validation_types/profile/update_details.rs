@@ -0,0 +1,121 @@
+use validator::{Validate, ValidationError, ValidationErrors, ValidationErrorsKind};
+
+// links are served on the public profile, so anything a browser would run is refused
+pub fn validate_web_url(url: &str) -> Result<(), ValidationError> {
+    match reqwest::Url::parse(url) {
+        Ok(parsed) if matches!(parsed.scheme(), "http" | "https") && parsed.has_host() => Ok(()),
+        _ => Err(ValidationError::new("url")
+            .with_message("Link url should be an http or https url".into())),
+    }
+}
+
+#[derive(Validate, serde::Deserialize, serde::Serialize)]
+pub struct ProfileLinkData {
+    #[validate(length(
+        min = 1,
+        max = 50,
+        message = "Link label should be between 1 and 50 length"
+    ))]
+    pub label: String,
+    #[validate(custom(function = "validate_web_url"))]
+    #[validate(length(max = 500, message = "Link url should be at most 500 length"))]
+    pub url: String,
+}
+
+#[derive(Validate, serde::Deserialize)]
+pub struct UpdateDetailsData {
+    #[validate(length(max = 100, message = "Display name should be at most 100 length"))]
+    pub display_name: Option<String>,
+    #[validate(length(max = 1000, message = "About me should be at most 1000 length"))]
+    pub about_me: Option<String>,
+    #[validate(length(max = 100, message = "Location should be at most 100 length"))]
+    pub location: Option<String>,
+    #[validate(length(max = 50, message = "Pronouns should be at most 50 length"))]
+    pub pronouns: Option<String>,
+    #[validate(length(max = 100, message = "Job title should be at most 100 length"))]
+    pub job_title: Option<String>,
+    #[validate(length(max = 100, message = "Company should be at most 100 length"))]
+    pub company: Option<String>,
+    #[validate(length(max = 20, message = "At most 20 links are allowed"))]
+    #[validate(nested)]
+    #[serde(default)]
+    pub links: Vec<ProfileLinkData>,
+}
+
+// field_errors only sees the top level, link failures sit a level down under their position
+pub fn error_messages(errors: &ValidationErrors) -> Vec<String> {
+    let mut messages: Vec<String> = Vec::new();
+    for kind in errors.errors().values() {
+        match kind {
+            ValidationErrorsKind::Field(field_errors) => messages.extend(
+                field_errors
+                    .iter()
+                    .filter_map(|err| err.message.as_ref().map(|message| message.to_string())),
+            ),
+            ValidationErrorsKind::Struct(nested) => messages.extend(error_messages(nested)),
+            ValidationErrorsKind::List(entries) => {
+                for (position, nested) in entries {
+                    messages.extend(
+                        error_messages(nested)
+                            .into_iter()
+                            .map(|message| format!("Link {}: {}", position + 1, message)),
+                    );
+                }
+            }
+        }
+    }
+    messages
+}
+
+#[cfg(test)]
+mod tests {
+    use validator::Validate;
+
+    use super::{error_messages, validate_web_url, ProfileLinkData, UpdateDetailsData};
+
+    #[test]
+    fn accepts_http_and_https() {
+        assert!(validate_web_url("https://example.com/me").is_ok());
+        assert!(validate_web_url("http://example.com").is_ok());
+    }
+
+    #[test]
+    fn refuses_script_and_data_urls() {
+        assert!(validate_web_url("javascript:alert(1)").is_err());
+        assert!(validate_web_url("JavaScript:alert(1)").is_err());
+        assert!(validate_web_url("data:text/html,<script>alert(1)</script>").is_err());
+        assert!(validate_web_url("vbscript:msgbox").is_err());
+        assert!(validate_web_url("not a url").is_err());
+    }
+
+    #[test]
+    fn reports_each_failing_link() {
+        let details = UpdateDetailsData {
+            display_name: None,
+            about_me: None,
+            location: None,
+            pronouns: None,
+            job_title: None,
+            company: None,
+            links: vec![
+                ProfileLinkData {
+                    label: "Blog".to_string(),
+                    url: "https://example.com".to_string(),
+                },
+                ProfileLinkData {
+                    label: "".to_string(),
+                    url: "javascript:alert(1)".to_string(),
+                },
+            ],
+        };
+        let mut messages = error_messages(&details.validate().unwrap_err());
+        messages.sort();
+        assert_eq!(
+            messages,
+            vec![
+                "Link 2: Link label should be between 1 and 50 length",
+                "Link 2: Link url should be an http or https url",
+            ]
+        );
+    }
+}
@@ -0,0 +1,12 @@
+use validator::Validate;
+
+#[derive(Validate, serde::Deserialize)]
+pub struct UpdateVisibilityData {
+    pub display_name: Option<bool>,
+    pub about_me: Option<bool>,
+    pub location: Option<bool>,
+    pub pronouns: Option<bool>,
+    pub job_title: Option<bool>,
+    pub company: Option<bool>,
+    pub links: Option<bool>,
+}
@@ -0,0 +1,17 @@
+use validator::Validate;
+
+#[derive(Validate, serde::Deserialize)]
+pub struct ChangePasswordData {
+    // accounts created through single sign on set their first password without one,
+    // after a recent sign in or re-authentication
+    #[serde(rename = "currentPassword")]
+    #[validate(length(max = 128, message = "Current password should be at most 128 length"))]
+    pub current_password: Option<String>,
+    #[serde(rename = "newPassword")]
+    #[validate(length(
+        min = 6,
+        max = 128,
+        message = "New password should be between 6 and 128 length"
+    ))]
+    pub new_password: String,
+}
@@ -0,0 +1,37 @@
+use validator::{Validate, ValidationError};
+
+fn validate_scopes(scopes: &[String]) -> Result<(), ValidationError> {
+    if scopes
+        .iter()
+        .all(|scope| crate::helpers::api_scopes::ALL_SCOPES.contains(&scope.as_str()))
+    {
+        return Ok(());
+    }
+    Err(ValidationError::new("scopes").with_message(
+        format!(
+            "Scopes should be any of {}",
+            crate::helpers::api_scopes::ALL_SCOPES.join(", ")
+        )
+        .into(),
+    ))
+}
+
+#[derive(Validate, serde::Deserialize)]
+pub struct CreateApiTokenData {
+    #[validate(length(
+        min = 1,
+        max = 100,
+        message = "Token name should be between 1 and 100 length"
+    ))]
+    pub name: String,
+    #[validate(length(min = 1, message = "At least one scope is required"))]
+    #[validate(custom(function = "validate_scopes"))]
+    pub scopes: Vec<String>,
+    #[serde(rename = "expiresInDays")]
+    #[validate(range(
+        min = 1,
+        max = 365,
+        message = "Expiry should be between 1 and 365 days"
+    ))]
+    pub expires_in_days: Option<i64>,
+}
@@ -0,0 +1,7 @@
+use validator::Validate;
+
+#[derive(Validate, serde::Deserialize)]
+pub struct MagicLinkData {
+    #[validate(email)]
+    pub email: String,
+}
@@ -1,2 +1,9 @@
+pub mod change_password;
+pub mod create_api_token;
+pub mod magic_link;
+pub mod passkey;
+pub mod reauthenticate;
+pub mod refresh;
 pub mod signin;
 pub mod signup;
+pub mod two_factor;
@@ -0,0 +1,27 @@
+use validator::Validate;
+use webauthn_rs::prelude::{PublicKeyCredential, RegisterPublicKeyCredential};
+
+#[derive(Validate, serde::Deserialize)]
+pub struct RegisterPasskeyData {
+    #[validate(length(
+        min = 1,
+        max = 100,
+        message = "Name should be between 1 and 100 length"
+    ))]
+    pub name: String,
+    pub credential: RegisterPublicKeyCredential,
+}
+
+#[derive(Validate, serde::Deserialize)]
+pub struct PasskeySigninStartData {
+    #[validate(email(message = "Invalid email"))]
+    pub email: String,
+}
+
+#[derive(Validate, serde::Deserialize)]
+pub struct PasskeySigninFinishData {
+    #[serde(rename = "challengeId")]
+    #[validate(length(min = 1, message = "Challenge id is required"))]
+    pub challenge_id: String,
+    pub credential: PublicKeyCredential,
+}
@@ -0,0 +1,13 @@
+use validator::Validate;
+
+#[derive(Validate, serde::Deserialize)]
+pub struct ReauthenticateData {
+    // accounts created through single sign on have no password to confirm
+    #[validate(length(max = 128, message = "Password should be at most 128 length"))]
+    pub password: Option<String>,
+    #[validate(length(equal = 6, message = "Code should be 6 digits"))]
+    pub code: Option<String>,
+    #[serde(rename = "recoveryCode")]
+    #[validate(length(max = 20, message = "Recovery code should be at most 20 length"))]
+    pub recovery_code: Option<String>,
+}
@@ -0,0 +1,7 @@
+use validator::Validate;
+
+#[derive(Validate, serde::Deserialize)]
+pub struct RefreshData {
+    #[serde(rename = "refreshToken")]
+    pub refresh_token: Option<String>,
+}
@@ -6,8 +6,8 @@ pub struct SigninData {
     pub email: String,
     #[validate(length(
         min = 6,
-        max = 20,
-        message = "Password should be between 6 and 20 length"
+        max = 128,
+        message = "Password should be between 6 and 128 length"
     ))]
     pub password: String,
 }
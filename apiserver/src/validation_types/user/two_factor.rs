@@ -0,0 +1,32 @@
+use validator::Validate;
+
+#[derive(Validate, serde::Deserialize)]
+pub struct ConfirmTotpData {
+    #[validate(length(equal = 6, message = "Code should be 6 digits"))]
+    pub code: String,
+}
+
+// a second factor is either an authenticator code or a recovery code
+#[derive(Validate, serde::Deserialize)]
+pub struct SecondFactorData {
+    #[serde(rename = "challengeToken")]
+    #[validate(length(min = 1, message = "Challenge token is required"))]
+    pub challenge_token: String,
+    #[validate(length(equal = 6, message = "Code should be 6 digits"))]
+    pub code: Option<String>,
+    #[serde(rename = "recoveryCode")]
+    #[validate(length(max = 20, message = "Recovery code should be at most 20 length"))]
+    pub recovery_code: Option<String>,
+}
+
+#[derive(Validate, serde::Deserialize)]
+pub struct DisableTwoFactorData {
+    // accounts created through single sign on have no password to confirm
+    #[validate(length(max = 128, message = "Password should be at most 128 length"))]
+    pub password: Option<String>,
+    #[validate(length(equal = 6, message = "Code should be 6 digits"))]
+    pub code: Option<String>,
+    #[serde(rename = "recoveryCode")]
+    #[validate(length(max = 20, message = "Recovery code should be at most 20 length"))]
+    pub recovery_code: Option<String>,
+}
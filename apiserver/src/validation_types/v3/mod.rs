@@ -0,0 +1,2 @@
+pub mod select_avatar;
+pub mod upload_avatar;
@@ -0,0 +1,24 @@
+use validator::Validate;
+
+#[derive(Validate, serde::Deserialize)]
+pub struct SelectAvatarData {
+    // the v3 sdks send email_md5
+    #[validate(length(min = 1, message = "Email hash is required"))]
+    #[serde(alias = "email_md5")]
+    pub email_hash: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SelectAvatarData;
+
+    #[test]
+    fn accepts_the_v3_field_name() {
+        let select_data: SelectAvatarData =
+            serde_json::from_str(r#"{"email_md5": "abc"}"#).unwrap();
+        assert_eq!(select_data.email_hash, "abc");
+        let select_data: SelectAvatarData =
+            serde_json::from_str(r#"{"email_hash": "abc"}"#).unwrap();
+        assert_eq!(select_data.email_hash, "abc");
+    }
+}
@@ -0,0 +1,7 @@
+use actix_multipart::form::{tempfile::TempFile, MultipartForm};
+
+// the size limit comes from the MultipartFormConfig registered in main
+#[derive(Debug, MultipartForm)]
+pub struct UploadAvatarForm {
+    pub image: TempFile,
+}